@@ -0,0 +1,31 @@
+CREATE TABLE IF NOT EXISTS markets (
+    id INTEGER PRIMARY KEY,
+    base TEXT NOT NULL,
+    quote TEXT NOT NULL,
+    UNIQUE (base, quote)
+);
+
+CREATE TABLE IF NOT EXISTS intervals (
+    name TEXT PRIMARY KEY,
+    -- Duration in milliseconds.
+    duration INTEGER NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS candlesticks (
+    market_id INTEGER NOT NULL REFERENCES markets (id),
+    interval TEXT NOT NULL REFERENCES intervals (name),
+    -- Times in milliseconds since the unix epoch.
+    open_time INTEGER NOT NULL,
+    close_time INTEGER NOT NULL,
+    high REAL NOT NULL,
+    low REAL NOT NULL,
+    open REAL NOT NULL,
+    close REAL NOT NULL,
+    volume REAL NOT NULL,
+    quote_volume REAL NOT NULL,
+    taker_buy_base_volume REAL NOT NULL,
+    taker_buy_quote_volume REAL NOT NULL,
+    trades INTEGER NOT NULL,
+    closed INTEGER NOT NULL,
+    PRIMARY KEY (market_id, interval, open_time)
+);
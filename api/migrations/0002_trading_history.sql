@@ -0,0 +1,55 @@
+CREATE TABLE IF NOT EXISTS orders (
+    id INTEGER PRIMARY KEY,
+    -- Id that is assigned before the order is sent to the exchange.
+    client_id TEXT NOT NULL UNIQUE,
+    -- Id that is assigned by the exchange, once it accepted the order.
+    exchange_id TEXT,
+    market_id INTEGER NOT NULL REFERENCES markets (id),
+    side TEXT NOT NULL,
+    quantity REAL NOT NULL,
+    quantity_asset TEXT NOT NULL,
+    price REAL NOT NULL,
+    -- Only set for OCO orders.
+    stop_price REAL,
+    executed_quantity REAL NOT NULL,
+    state TEXT NOT NULL,
+    time INTEGER NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS order_transitions (
+    order_id INTEGER NOT NULL REFERENCES orders (id),
+    state TEXT NOT NULL,
+    time INTEGER NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS order_transitions_order_id ON order_transitions (order_id);
+
+CREATE TABLE IF NOT EXISTS fills (
+    id INTEGER PRIMARY KEY,
+    order_id INTEGER NOT NULL REFERENCES orders (id),
+    price REAL NOT NULL,
+    quantity REAL NOT NULL,
+    commission REAL NOT NULL,
+    commission_asset TEXT NOT NULL,
+    time INTEGER NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS fills_order_id ON fills (order_id);
+
+CREATE TABLE IF NOT EXISTS positions (
+    id INTEGER PRIMARY KEY,
+    entry_order_id INTEGER NOT NULL REFERENCES orders (id),
+    exit_order_id INTEGER REFERENCES orders (id),
+    take_profit REAL NOT NULL,
+    stop_loss REAL NOT NULL,
+    opened_time INTEGER NOT NULL,
+    closed_time INTEGER
+);
+
+CREATE TABLE IF NOT EXISTS balances (
+    time INTEGER NOT NULL,
+    asset TEXT NOT NULL,
+    free REAL NOT NULL,
+    locked REAL NOT NULL,
+    PRIMARY KEY (time, asset)
+);
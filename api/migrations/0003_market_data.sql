@@ -0,0 +1,25 @@
+CREATE TABLE IF NOT EXISTS trades (
+    market_id INTEGER NOT NULL REFERENCES markets (id),
+    -- Id that is assigned by the exchange.
+    id INTEGER NOT NULL,
+    -- Time in milliseconds since the unix epoch.
+    time INTEGER NOT NULL,
+    price REAL NOT NULL,
+    quantity REAL NOT NULL,
+    buyer_maker INTEGER NOT NULL,
+    PRIMARY KEY (market_id, id)
+);
+
+CREATE INDEX IF NOT EXISTS trades_time ON trades (market_id, time);
+
+CREATE TABLE IF NOT EXISTS depth (
+    market_id INTEGER NOT NULL REFERENCES markets (id),
+    -- Time of the snapshot in milliseconds since the unix epoch.
+    time INTEGER NOT NULL,
+    side TEXT NOT NULL,
+    -- Position of the level in the order book, starting with 0 at the best price.
+    level INTEGER NOT NULL,
+    price REAL NOT NULL,
+    quantity REAL NOT NULL,
+    PRIMARY KEY (market_id, time, side, level)
+);
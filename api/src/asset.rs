@@ -1,8 +1,32 @@
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use std::collections::HashSet;
 use std::fmt;
 use std::hash::Hash;
+use std::sync::Mutex;
+
+lazy_static::lazy_static! {
+    /// All assets that were interned, so every asset exists only once.
+    static ref ASSETS: Mutex<HashSet<&'static Asset>> = Mutex::new(HashSet::new());
+}
+
 #[derive(Clone, Debug, Eq, PartialEq, Hash)]
 pub struct Asset(String);
 
+impl Asset {
+    /// Returns the registered asset with the given name, which is registered first if it is new.
+    pub fn intern(name: &str) -> &'static Asset {
+        let mut assets = ASSETS.lock().unwrap();
+        let asset = Asset(String::from(name));
+        if let Some(asset) = assets.get(&asset) {
+            return asset;
+        }
+
+        let asset: &'static Asset = Box::leak(Box::new(asset));
+        assets.insert(asset);
+        asset
+    }
+}
+
 impl fmt::Display for Asset {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "{}", self.0)
@@ -13,4 +37,25 @@ impl From<String> for Asset {
     fn from(string: String) -> Asset {
         Asset(string)
     }
-}
\ No newline at end of file
+}
+
+/// Assets are represented by their name.
+impl Serialize for Asset {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.0)
+    }
+}
+
+impl<'de> Deserialize<'de> for &'static Asset {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        if name.is_empty() {
+            return Err(de::Error::invalid_value(
+                de::Unexpected::Str(&name),
+                &"an asset name",
+            ));
+        }
+
+        Ok(Asset::intern(&name))
+    }
+}
@@ -0,0 +1,276 @@
+use crate::{Candlestick, Error, Interval, Market, Timestamp};
+use futures_core::{
+    stream::Stream,
+    task::{Context, Poll},
+};
+use std::collections::VecDeque;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+
+pub type HistoryFuture = Pin<Box<dyn Future<Output = Result<Vec<Candlestick>, Error>> + Send>>;
+
+/// Fetches the candlesticks with open times between the given start and end, both inclusive.
+pub type History = Arc<dyn Fn(Timestamp, Timestamp) -> HistoryFuture + Send + Sync>;
+
+/// Range of candlesticks that are missing in a subscription and could not be backfilled.
+#[derive(Debug, Copy, Clone)]
+pub struct Gap {
+    pub market: &'static Market,
+    pub interval: Interval,
+    /// Open time of the first missing candlestick.
+    pub start: Timestamp,
+    /// Open time of the last missing candlestick.
+    pub end: Timestamp,
+}
+
+/// Gap that a backfilled stream passes on in place of the candlesticks it misses.
+#[derive(Debug)]
+pub enum BackfillError {
+    /// The history does not contain the candlesticks of the gap.
+    Missing(Gap),
+    /// The history of the gap could not be fetched.
+    History(Gap, Error),
+}
+
+impl BackfillError {
+    pub fn gap(&self) -> Gap {
+        match self {
+            Self::Missing(gap) | Self::History(gap, _) => *gap,
+        }
+    }
+}
+
+/// Stream adapter that checks the open times of candlesticks for continuity
+/// and fetches missing candlesticks before passing on the candlestick that follows them.
+/// Candlesticks that cannot be backfilled are passed on as a gap in their place.
+pub struct Backfill<S>
+where
+    S: Stream<Item = Candlestick> + Unpin,
+{
+    stream: S,
+    market: &'static Market,
+    interval: Interval,
+    history: History,
+    /// Open time of the candlestick that is expected next.
+    expected: Option<Timestamp>,
+    /// Candlestick that follows the gap that is currently backfilled.
+    pending: Option<Candlestick>,
+    fetching: Option<HistoryFuture>,
+    buffer: VecDeque<Result<Candlestick, BackfillError>>,
+    done: bool,
+}
+
+impl<S> Backfill<S>
+where
+    S: Stream<Item = Candlestick> + Unpin,
+{
+    pub fn new(stream: S, market: &'static Market, interval: Interval, history: History) -> Self {
+        Self {
+            stream,
+            market,
+            interval,
+            history,
+            expected: None,
+            pending: None,
+            fetching: None,
+            buffer: VecDeque::new(),
+            done: false,
+        }
+    }
+
+    /// Expects the first candlestick to open at the given time, so candlesticks
+    /// that are missing before it are backfilled as well, for example when resuming a recording.
+    pub fn expecting(mut self, open_time: Timestamp) -> Self {
+        self.expected = Some(open_time);
+        self
+    }
+
+    fn check(&mut self, candlestick: Candlestick) {
+        if let Some(expected) = self.expected {
+            if candlestick.open_time > expected {
+                let end = candlestick.open_time - self.interval.duration();
+                self.fetching = Some((self.history)(expected, end));
+                self.pending = Some(candlestick);
+                return;
+            }
+        }
+
+        self.forward(candlestick);
+    }
+
+    fn forward(&mut self, candlestick: Candlestick) {
+        let next = candlestick.open_time + self.interval.duration();
+        if self.expected.is_none_or(|expected| next > expected) {
+            self.expected = Some(next);
+        }
+        self.buffer.push_back(Ok(candlestick));
+    }
+
+    fn backfill(&mut self, result: Result<Vec<Candlestick>, Error>) {
+        let pending = match self.pending.take() {
+            Some(pending) => pending,
+            None => return,
+        };
+        let mut expected = self.expected.unwrap_or(pending.open_time);
+        let mut candlesticks = match result {
+            Ok(candlesticks) => candlesticks,
+            Err(error) => {
+                let gap = self.gap(expected, pending.open_time - self.interval.duration());
+                self.buffer
+                    .push_back(Err(BackfillError::History(gap, error)));
+                self.forward(pending);
+                return;
+            }
+        };
+        candlesticks.sort_by_key(|candlestick| candlestick.open_time);
+
+        for candlestick in candlesticks {
+            if candlestick.open_time < expected || candlestick.open_time >= pending.open_time {
+                continue;
+            }
+            if candlestick.open_time > expected {
+                self.missing(expected, candlestick.open_time - self.interval.duration());
+            }
+            expected = candlestick.open_time + self.interval.duration();
+            self.forward(candlestick);
+        }
+
+        if pending.open_time > expected {
+            self.missing(expected, pending.open_time - self.interval.duration());
+        }
+        self.forward(pending);
+    }
+
+    fn gap(&self, start: Timestamp, end: Timestamp) -> Gap {
+        Gap {
+            market: self.market,
+            interval: self.interval,
+            start,
+            end,
+        }
+    }
+
+    fn missing(&mut self, start: Timestamp, end: Timestamp) {
+        let gap = self.gap(start, end);
+        self.buffer.push_back(Err(BackfillError::Missing(gap)));
+    }
+}
+
+impl<S> Stream for Backfill<S>
+where
+    S: Stream<Item = Candlestick> + Unpin,
+{
+    type Item = Result<Candlestick, BackfillError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.as_mut().get_mut();
+
+        loop {
+            if let Some(item) = this.buffer.pop_front() {
+                return Poll::Ready(Some(item));
+            }
+
+            if let Some(fetching) = this.fetching.as_mut() {
+                match fetching.as_mut().poll(cx) {
+                    Poll::Ready(result) => {
+                        this.fetching = None;
+                        this.backfill(result);
+                        continue;
+                    }
+                    Poll::Pending => return Poll::Pending,
+                }
+            }
+
+            if this.done {
+                return Poll::Ready(None);
+            }
+
+            match Pin::new(&mut this.stream).poll_next(cx) {
+                Poll::Ready(Some(candlestick)) => this.check(candlestick),
+                Poll::Ready(None) => this.done = true,
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{candlestick, ethbtc};
+    use futures::{executor, stream, StreamExt};
+
+    /// Minute of a candlestick, or the first and last minute of a gap.
+    fn minutes(item: &Result<Candlestick, BackfillError>) -> (u64, u64) {
+        match item {
+            Ok(candlestick) => {
+                let minute = candlestick.open_time.millis() / 60_000;
+                (minute, minute)
+            }
+            Err(error) => (
+                error.gap().start.millis() / 60_000,
+                error.gap().end.millis() / 60_000,
+            ),
+        }
+    }
+
+    #[test]
+    fn test_backfill() {
+        let market = ethbtc();
+
+        // Minutes 2 to 6 are missing in the stream, but only 3 and 5 are available as history.
+        let live = stream::iter(vec![
+            candlestick(market, 0),
+            candlestick(market, 1),
+            candlestick(market, 7),
+        ]);
+        let history: History = Arc::new(move |start, end| {
+            Box::pin(async move {
+                Ok([3, 5]
+                    .iter()
+                    .map(|minute| candlestick(market, *minute))
+                    .filter(|candlestick| {
+                        candlestick.open_time >= start && candlestick.open_time <= end
+                    })
+                    .collect())
+            })
+        });
+
+        let items: Vec<_> =
+            executor::block_on(Backfill::new(live, market, Interval::I1m, history).collect());
+        let minutes: Vec<(u64, u64)> = items.iter().map(minutes).collect();
+        assert_eq!(
+            minutes,
+            vec![
+                (0, 0),
+                (1, 1),
+                (2, 2),
+                (3, 3),
+                (4, 4),
+                (5, 5),
+                (6, 6),
+                (7, 7)
+            ]
+        );
+        assert!(matches!(items[2], Err(BackfillError::Missing(_))));
+        assert!(matches!(items[6], Err(BackfillError::Missing(_))));
+    }
+
+    #[test]
+    fn test_backfill_history_error() {
+        let market = ethbtc();
+
+        let live = stream::iter(vec![candlestick(market, 0), candlestick(market, 4)]);
+        let history: History = Arc::new(|_, _| Box::pin(async { Err(Error::ConnectionError) }));
+
+        let items: Vec<_> =
+            executor::block_on(Backfill::new(live, market, Interval::I1m, history).collect());
+        let minutes: Vec<(u64, u64)> = items.iter().map(minutes).collect();
+        assert_eq!(minutes, vec![(0, 0), (1, 3), (4, 4)]);
+        assert!(matches!(
+            items[1],
+            Err(BackfillError::History(_, Error::ConnectionError))
+        ));
+    }
+}
@@ -0,0 +1,295 @@
+use crate::{Candlestick, Interval, Monetary, Quantity, Trade};
+use chrono::Duration;
+use futures_core::{
+    stream::Stream,
+    task::{Context, Poll},
+};
+use std::pin::Pin;
+
+/// Decides when a bar built from trades is complete.
+#[derive(Debug, Copy, Clone)]
+pub enum BarType {
+    /// A bar per interval.
+    Time(Interval),
+    /// A bar per number of trades.
+    Tick(u64),
+    /// A bar per traded quantity of the base asset.
+    Volume(Monetary),
+    /// A bar per traded quantity of the quote asset.
+    Dollar(Monetary),
+}
+
+/// Builds candlesticks from trades, which have to be pushed in order of their time.
+/// The trade that reaches the threshold of a tick, volume or dollar bar is part of that bar.
+/// Those bars close one millisecond after their last trade, so that even a bar of a single trade
+/// closes after it opens.
+#[derive(Debug, Clone)]
+pub struct BarBuilder {
+    bar_type: BarType,
+    current: Option<Candlestick>,
+}
+
+impl BarBuilder {
+    pub fn new(bar_type: BarType) -> Self {
+        Self {
+            bar_type,
+            current: None,
+        }
+    }
+
+    /// Pushes a trade and returns the bar that was completed by it.
+    pub fn push(&mut self, trade: Trade) -> Option<Candlestick> {
+        let mut completed = None;
+
+        if let (BarType::Time(interval), Some(current)) = (self.bar_type, self.current) {
+            if interval.open_time(trade.time) > current.open_time {
+                completed = self.complete();
+            } else if trade.time < current.open_time {
+                return None;
+            }
+        }
+
+        let bar_type = self.bar_type;
+        let current = self
+            .current
+            .get_or_insert_with(|| BarBuilder::open(bar_type, trade));
+        if trade.price > current.high {
+            current.high = trade.price;
+        }
+        if trade.price < current.low {
+            current.low = trade.price;
+        }
+        current.close = trade.price;
+        let quote_quantity = Quantity {
+            quantity: trade.quantity.quantity * trade.price.price,
+            asset: trade.market.quote,
+        };
+        current.volume = current.volume + trade.quantity;
+        current.quote_volume = current.quote_volume + quote_quantity;
+        if !trade.buyer_maker {
+            current.taker_buy_base_volume = current.taker_buy_base_volume + trade.quantity;
+            current.taker_buy_quote_volume = current.taker_buy_quote_volume + quote_quantity;
+        }
+        current.trades += 1;
+        if !matches!(bar_type, BarType::Time(_)) {
+            current.close_time = trade.time + Duration::milliseconds(1);
+        }
+
+        let full = match self.bar_type {
+            BarType::Time(_) => false,
+            BarType::Tick(trades) => current.trades >= trades,
+            BarType::Volume(volume) => current.volume.quantity >= volume,
+            BarType::Dollar(dollars) => current.quote_volume.quantity >= dollars,
+        };
+
+        if full {
+            self.complete()
+        } else {
+            completed
+        }
+    }
+
+    /// Returns the current, possibly partial, bar and starts a new one.
+    pub fn flush(&mut self) -> Option<Candlestick> {
+        self.current.take()
+    }
+
+    fn complete(&mut self) -> Option<Candlestick> {
+        self.flush().map(|bar| Candlestick {
+            closed: true,
+            ..bar
+        })
+    }
+
+    /// Opens an empty bar with the time and price of its first trade.
+    fn open(bar_type: BarType, trade: Trade) -> Candlestick {
+        let market = trade.market;
+        let (open_time, close_time) = match bar_type {
+            BarType::Time(interval) => (
+                interval.open_time(trade.time),
+                interval.close_time(trade.time),
+            ),
+            _ => (trade.time, trade.time + Duration::milliseconds(1)),
+        };
+
+        Candlestick {
+            market,
+            open_time,
+            close_time,
+            high: trade.price,
+            low: trade.price,
+            open: trade.price,
+            close: trade.price,
+            volume: Quantity::zero(market.base),
+            quote_volume: Quantity::zero(market.quote),
+            taker_buy_base_volume: Quantity::zero(market.base),
+            taker_buy_quote_volume: Quantity::zero(market.quote),
+            trades: 0,
+            closed: false,
+        }
+    }
+}
+
+/// Builds bars from a series of trades, ordered by time.
+/// The partial last bar is included.
+pub fn bars<I>(trades: I, bar_type: BarType) -> Vec<Candlestick>
+where
+    I: IntoIterator<Item = Trade>,
+{
+    let mut builder = BarBuilder::new(bar_type);
+    let mut bars: Vec<Candlestick> = trades
+        .into_iter()
+        .filter_map(|trade| builder.push(trade))
+        .collect();
+    bars.extend(builder.flush());
+
+    bars
+}
+
+/// Stream adapter that builds bars from a stream of trades, such as `Api::subscribe_trades`.
+/// The partial last bar is emitted when the underlying stream ends.
+pub struct Bars<S>
+where
+    S: Stream<Item = Trade> + Unpin,
+{
+    stream: S,
+    builder: BarBuilder,
+    done: bool,
+}
+
+impl<S> Bars<S>
+where
+    S: Stream<Item = Trade> + Unpin,
+{
+    pub fn new(stream: S, bar_type: BarType) -> Self {
+        Self {
+            stream,
+            builder: BarBuilder::new(bar_type),
+            done: false,
+        }
+    }
+}
+
+impl<S> Stream for Bars<S>
+where
+    S: Stream<Item = Trade> + Unpin,
+{
+    type Item = Candlestick;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.as_mut().get_mut();
+
+        loop {
+            if this.done {
+                return Poll::Ready(None);
+            }
+
+            match Pin::new(&mut this.stream).poll_next(cx) {
+                Poll::Ready(Some(trade)) => {
+                    if let Some(bar) = this.builder.push(trade) {
+                        return Poll::Ready(Some(bar));
+                    }
+                }
+                Poll::Ready(None) => {
+                    this.done = true;
+                    return Poll::Ready(this.builder.flush());
+                }
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{ethbtc, trade};
+
+    /// Trades every 30 seconds whose buyers alternately took and provided liquidity.
+    fn trades() -> Vec<Trade> {
+        [(2.0, 1.0), (4.0, 2.0), (3.0, 1.0), (1.0, 3.0), (5.0, 1.0)]
+            .iter()
+            .enumerate()
+            .map(|(i, &(price, quantity))| Trade {
+                buyer_maker: i % 2 == 0,
+                ..trade(ethbtc(), i as u64, i as u64 * 30_000, price, quantity)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_volume_bars() {
+        let volume = bars(trades(), BarType::Volume(3.0));
+        assert_eq!(volume.len(), 3);
+        assert_eq!(volume[0].trades, 2);
+        assert_eq!(volume[0].high.price, 4.0);
+        assert_eq!(volume[0].close_time.millis(), 30_001);
+        assert_eq!(volume[1].volume.quantity, 4.0);
+        assert_eq!(volume[1].low.price, 1.0);
+        assert!(volume[1].closed);
+    }
+
+    #[test]
+    fn test_partial_bar() {
+        let volume = bars(trades(), BarType::Volume(3.0));
+        assert_eq!(volume[2].trades, 1);
+        assert!(!volume[2].closed);
+    }
+
+    #[test]
+    fn test_dollar_bars() {
+        let dollar = bars(trades(), BarType::Dollar(10.0));
+        assert_eq!(dollar.len(), 2);
+        assert_eq!(dollar[0].trades, 2);
+        assert_eq!(dollar[0].quote_volume.quantity, 10.0);
+        assert_eq!(dollar[0].taker_buy_base_volume.quantity, 2.0);
+    }
+
+    #[test]
+    fn test_tick_bars() {
+        let tick = bars(trades(), BarType::Tick(2));
+        assert_eq!(tick.len(), 3);
+        assert_eq!(tick[0].trades, 2);
+        assert_eq!(tick[2].trades, 1);
+    }
+
+    #[test]
+    fn test_single_trade_tick_bars() {
+        let tick = bars(trades(), BarType::Tick(1));
+        assert_eq!(tick.len(), 5);
+        assert_eq!(tick[1].open_time.millis(), 30_000);
+        assert_eq!(tick[1].close_time.millis(), 30_001);
+        assert!(tick.iter().all(|bar| bar.validate().is_ok()));
+    }
+
+    #[test]
+    fn test_single_trade_volume_bar() {
+        // Every trade reaches the volume on its own.
+        let volume = bars(trades(), BarType::Volume(1.0));
+        assert_eq!(volume.len(), 5);
+        assert_eq!(volume[3].trades, 1);
+        assert_eq!(volume[3].volume.quantity, 3.0);
+        assert!(volume.iter().all(|bar| bar.validate().is_ok()));
+    }
+
+    #[test]
+    fn test_single_trade_dollar_bar() {
+        // The last trade alone exceeds the value.
+        let dollar = bars(trades(), BarType::Dollar(4.0));
+        let last = dollar.last().unwrap();
+        assert_eq!(last.trades, 1);
+        assert_eq!(last.quote_volume.quantity, 5.0);
+        assert!(last.closed);
+        assert!(last.validate().is_ok());
+    }
+
+    #[test]
+    fn test_time_bars() {
+        let time = bars(trades(), BarType::Time(Interval::I1m));
+        assert_eq!(time.len(), 3);
+        assert_eq!(time[1].open_time.millis(), 60_000);
+        assert_eq!(time[1].close_time.millis(), 120_000 - 1);
+        assert_eq!(time[1].open.price, 3.0);
+        assert_eq!(time[1].close.price, 1.0);
+    }
+}
@@ -0,0 +1,462 @@
+use crate::{
+    Api, Asset, Candlestick, DepthStream, Error, Filters, Interval, Market, Order, OrderError,
+    OrderResponse, Subscription, Timestamp, TradeStream,
+};
+use futures_core::{
+    future::Future,
+    stream::Stream,
+    task::{Context, Poll},
+};
+use std::any::Any;
+use std::collections::{HashMap, HashSet};
+use std::convert::Infallible;
+use std::pin::Pin;
+use std::sync::{Arc, Weak};
+use tokio::stream::StreamExt;
+use tokio::sync::{broadcast, oneshot, Mutex};
+
+/// Kind of the data of a shared subscription.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+enum Kind {
+    Candlesticks(Interval),
+    Trades,
+    Depth,
+}
+
+type Key = (&'static Market, Kind);
+
+/// Stops the upstream subscription when the last consumer is dropped.
+struct Guard(Option<oneshot::Sender<()>>);
+
+impl Drop for Guard {
+    fn drop(&mut self) {
+        if let Some(stop) = self.0.take() {
+            stop.send(()).ok();
+        }
+    }
+}
+
+struct Channel {
+    /// Sender of the items of the kind of the key.
+    sender: Box<dyn Any + Send>,
+    guard: Weak<Guard>,
+}
+
+type Channels = Arc<Mutex<HashMap<Key, Channel>>>;
+
+/// Removes a pending channel unless it is disarmed once its upstream subscription is connected,
+/// so that consumers which joined it end when the connecting subscriber fails or is dropped.
+struct Pending {
+    channels: Channels,
+    key: Key,
+    guard: Option<Weak<Guard>>,
+}
+
+impl Drop for Pending {
+    fn drop(&mut self) {
+        if let Some(guard) = self.guard.take() {
+            match self.channels.try_lock() {
+                Ok(mut channels) => remove_pending(&mut channels, self.key, &guard),
+                Err(_) => {
+                    let (channels, key) = (Arc::clone(&self.channels), self.key);
+                    tokio::spawn(async move { remove(&channels, key, &guard).await });
+                }
+            }
+        }
+    }
+}
+
+/// Shares subscriptions of the wrapped API between all consumers that subscribe
+/// to the same market and interval, or to the trades of the same market,
+/// so only a single upstream subscription is kept open.
+pub struct Broadcast<API, S>
+where
+    API: Api<S> + Send + Sync + 'static,
+    S: Stream<Item = Candlestick> + Unpin + Send + 'static,
+{
+    api: API,
+    /// Number of items that are buffered for each consumer.
+    capacity: usize,
+    channels: Channels,
+    _phantom: std::marker::PhantomData<fn() -> S>,
+}
+
+impl<API, S> Broadcast<API, S>
+where
+    API: Api<S> + Send + Sync + 'static,
+    S: Stream<Item = Candlestick> + Unpin + Send + 'static,
+{
+    pub fn new(api: API, capacity: usize) -> Self {
+        Self {
+            api,
+            capacity,
+            channels: Arc::new(Mutex::new(HashMap::new())),
+            _phantom: std::marker::PhantomData,
+        }
+    }
+
+    /// Returns a consumer of the channel of the key, which is opened with the upstream
+    /// subscription that `connect` resolves to if it is not open yet.
+    ///
+    /// The channel is registered before connecting and the lock is released while connecting,
+    /// so other subscriptions do not wait for the connection
+    /// and consumers of the same key share the pending channel.
+    /// If the connection fails or the connecting subscriber is dropped, the pending channel
+    /// is closed.
+    async fn share<T, F, U, E>(&self, key: Key, connect: F) -> Result<BroadcastStream<T>, E>
+    where
+        T: Clone + Send + 'static,
+        F: Future<Output = Result<U, E>> + Send,
+        U: Stream<Item = T> + Unpin + Send + 'static,
+    {
+        let mut channels = self.channels.lock().await;
+        if let Some(channel) = channels.get(&key) {
+            let sender = channel.sender.downcast_ref::<broadcast::Sender<T>>();
+            if let (Some(guard), Some(sender)) = (channel.guard.upgrade(), sender) {
+                return Ok(BroadcastStream::new(sender.subscribe(), guard));
+            }
+        }
+
+        let (sender, receiver) = broadcast::channel(self.capacity);
+        let (stop, mut stopped) = oneshot::channel();
+        let guard = Arc::new(Guard(Some(stop)));
+        let weak = Arc::downgrade(&guard);
+        channels.insert(
+            key,
+            Channel {
+                sender: Box::new(sender.clone()),
+                guard: Weak::clone(&weak),
+            },
+        );
+        drop(channels);
+
+        let mut pending = Pending {
+            channels: Arc::clone(&self.channels),
+            key,
+            guard: Some(Weak::clone(&weak)),
+        };
+        let mut upstream = connect.await?;
+        pending.guard = None;
+
+        let channels = Arc::clone(&self.channels);
+        tokio::spawn(async move {
+            loop {
+                tokio::select! {
+                    item = upstream.next() => match item {
+                        Some(item) => {
+                            if sender.send(item).is_err() {
+                                break;
+                            }
+                        }
+                        None => break,
+                    },
+                    _ = &mut stopped => break,
+                }
+            }
+
+            remove(&channels, key, &weak).await;
+        });
+
+        Ok(BroadcastStream::new(receiver, guard))
+    }
+}
+
+/// Removes the channel of the key, unless it was already replaced by a new one.
+async fn remove(channels: &Channels, key: Key, guard: &Weak<Guard>) {
+    remove_pending(&mut *channels.lock().await, key, guard);
+}
+
+fn remove_pending(channels: &mut HashMap<Key, Channel>, key: Key, guard: &Weak<Guard>) {
+    if let Some(channel) = channels.get(&key) {
+        if Weak::ptr_eq(&channel.guard, guard) {
+            channels.remove(&key);
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl<API, S> Api<BroadcastStream> for Broadcast<API, S>
+where
+    API: Api<S> + Send + Sync + 'static,
+    S: Stream<Item = Candlestick> + Unpin + Send + 'static,
+{
+    async fn update(&mut self) -> Result<(), Error> {
+        self.api.update().await
+    }
+
+    fn get_markets(&self) -> &HashSet<&'static Market> {
+        self.api.get_markets()
+    }
+
+    fn get_assets(&self) -> &HashSet<&'static Asset> {
+        self.api.get_assets()
+    }
+
+    fn filters(&self) -> Filters {
+        self.api.filters()
+    }
+
+    async fn subscribe(
+        &self,
+        market: &'static Market,
+        interval: Interval,
+    ) -> Subscription<BroadcastStream> {
+        let connect = async { Ok::<_, Infallible>(self.api.subscribe(market, interval).await) };
+        let stream = match self
+            .share((market, Kind::Candlesticks(interval)), connect)
+            .await
+        {
+            Ok(stream) => stream,
+            Err(never) => match never {},
+        };
+
+        Subscription::new(market, interval, stream)
+    }
+
+    async fn history(
+        &self,
+        market: &'static Market,
+        interval: Interval,
+        start: Timestamp,
+        end: Timestamp,
+    ) -> Result<Vec<Candlestick>, Error> {
+        self.api.history(market, interval, start, end).await
+    }
+
+    async fn subscribe_trades(&self, market: &'static Market) -> Result<TradeStream, Error> {
+        let connect = self.api.subscribe_trades(market);
+        let stream = self.share((market, Kind::Trades), connect).await?;
+
+        Ok(Box::pin(stream))
+    }
+
+    async fn subscribe_depth(&self, market: &'static Market) -> Result<DepthStream, Error> {
+        let connect = self.api.subscribe_depth(market);
+        let stream = self.share((market, Kind::Depth), connect).await?;
+
+        Ok(Box::pin(stream))
+    }
+
+    async fn order(&mut self, order: Order) -> Result<OrderResponse, OrderError> {
+        self.api.order(order).await
+    }
+}
+
+/// Stream of a single consumer of a broadcasted subscription.
+/// If the consumer falls behind by more than the capacity of the broadcast,
+/// the oldest items are skipped and counted as lagged.
+pub struct BroadcastStream<T = Candlestick> {
+    receiver: broadcast::Receiver<T>,
+    lagged: u64,
+    _guard: Arc<Guard>,
+}
+
+impl<T> BroadcastStream<T> {
+    fn new(receiver: broadcast::Receiver<T>, guard: Arc<Guard>) -> Self {
+        Self {
+            receiver,
+            lagged: 0,
+            _guard: guard,
+        }
+    }
+
+    /// Returns the number of items that were skipped because this consumer fell behind.
+    pub fn lagged(&self) -> u64 {
+        self.lagged
+    }
+}
+
+impl<T> Stream for BroadcastStream<T>
+where
+    T: Clone,
+{
+    type Item = T;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.as_mut().get_mut();
+
+        loop {
+            match Pin::new(&mut this.receiver).poll_next(cx) {
+                Poll::Ready(Some(Ok(item))) => return Poll::Ready(Some(item)),
+                Poll::Ready(Some(Err(broadcast::RecvError::Lagged(lagged)))) => {
+                    this.lagged += lagged;
+                }
+                Poll::Ready(Some(Err(broadcast::RecvError::Closed))) | Poll::Ready(None) => {
+                    return Poll::Ready(None)
+                }
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{candlestick, ethbtc, trade};
+    use futures::stream::{self, BoxStream, StreamExt};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    /// Counts the upstream subscriptions and never connects to hourly candlesticks.
+    struct Mock {
+        markets: HashSet<&'static Market>,
+        assets: HashSet<&'static Asset>,
+        subscriptions: Arc<AtomicUsize>,
+    }
+
+    #[async_trait::async_trait]
+    impl Api<BoxStream<'static, Candlestick>> for Mock {
+        async fn update(&mut self) -> Result<(), Error> {
+            Ok(())
+        }
+
+        fn get_markets(&self) -> &HashSet<&'static Market> {
+            &self.markets
+        }
+
+        fn get_assets(&self) -> &HashSet<&'static Asset> {
+            &self.assets
+        }
+
+        async fn subscribe(
+            &self,
+            market: &'static Market,
+            interval: Interval,
+        ) -> Subscription<BoxStream<'static, Candlestick>> {
+            self.subscriptions.fetch_add(1, Ordering::SeqCst);
+            if interval == Interval::I1h {
+                futures::future::pending::<()>().await;
+            }
+            let candlestick = Candlestick {
+                close_time: interval.close_time(Timestamp::from_millis(0)),
+                ..candlestick(market, 0)
+            };
+            let stream = stream::iter(vec![candlestick])
+                .chain(stream::pending())
+                .boxed();
+
+            Subscription::new(market, interval, stream)
+        }
+
+        async fn history(
+            &self,
+            _market: &'static Market,
+            _interval: Interval,
+            _start: Timestamp,
+            _end: Timestamp,
+        ) -> Result<Vec<Candlestick>, Error> {
+            Ok(Vec::new())
+        }
+
+        async fn subscribe_trades(&self, market: &'static Market) -> Result<TradeStream, Error> {
+            self.subscriptions.fetch_add(1, Ordering::SeqCst);
+            let trade = trade(market, 1, 0, 1.0, 1.0);
+
+            Ok(stream::iter(vec![trade]).chain(stream::pending()).boxed())
+        }
+
+        async fn order(&mut self, _order: Order) -> Result<OrderResponse, OrderError> {
+            Err(OrderError::Other(Error::ConnectionError))
+        }
+    }
+
+    fn broadcast() -> (
+        Broadcast<Mock, BoxStream<'static, Candlestick>>,
+        Arc<AtomicUsize>,
+    ) {
+        let subscriptions = Arc::new(AtomicUsize::new(0));
+        let broadcast = Broadcast::new(
+            Mock {
+                markets: HashSet::new(),
+                assets: HashSet::new(),
+                subscriptions: Arc::clone(&subscriptions),
+            },
+            16,
+        );
+
+        (broadcast, subscriptions)
+    }
+
+    #[tokio::test]
+    async fn test_broadcast_shared() {
+        let (broadcast, subscriptions) = broadcast();
+
+        let mut first = broadcast.subscribe(ethbtc(), Interval::I5m).await;
+        let mut second = broadcast.subscribe(ethbtc(), Interval::I5m).await;
+        assert_eq!(subscriptions.load(Ordering::SeqCst), 1);
+        assert!(first.next().await.is_some());
+        assert!(second.next().await.is_some());
+        assert_eq!(first.get_ref().lagged(), 0);
+
+        broadcast.subscribe(ethbtc(), Interval::I1m).await;
+        assert_eq!(subscriptions.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_broadcast_teardown() {
+        let (broadcast, subscriptions) = broadcast();
+
+        let first = broadcast.subscribe(ethbtc(), Interval::I5m).await;
+        let second = broadcast.subscribe(ethbtc(), Interval::I5m).await;
+        drop(first);
+        drop(second);
+        broadcast.subscribe(ethbtc(), Interval::I5m).await;
+        assert_eq!(subscriptions.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_broadcast_kinds() {
+        let (broadcast, subscriptions) = broadcast();
+
+        let _candlesticks = broadcast.subscribe(ethbtc(), Interval::I5m).await;
+        let mut first = broadcast.subscribe_trades(ethbtc()).await.unwrap();
+        let mut second = broadcast.subscribe_trades(ethbtc()).await.unwrap();
+        assert_eq!(subscriptions.load(Ordering::SeqCst), 2);
+        assert_eq!(first.next().await.unwrap().id, 1);
+        assert_eq!(second.next().await.unwrap().id, 1);
+    }
+
+    #[tokio::test]
+    async fn test_broadcast_pending() {
+        let (broadcast, subscriptions) = broadcast();
+
+        // The first subscription registers its channel and waits for the connection.
+        let slow = broadcast.subscribe(ethbtc(), Interval::I1h);
+        futures::pin_mut!(slow);
+        assert!(futures::poll!(slow.as_mut()).is_pending());
+
+        // Neither other keys nor further consumers of the same key wait for it.
+        let timeout = Duration::from_secs(5);
+        tokio::time::timeout(timeout, broadcast.subscribe(ethbtc(), Interval::I5m))
+            .await
+            .unwrap();
+        tokio::time::timeout(timeout, broadcast.subscribe(ethbtc(), Interval::I1h))
+            .await
+            .unwrap();
+        assert_eq!(subscriptions.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_broadcast_cancelled() {
+        let (broadcast, subscriptions) = broadcast();
+
+        let mut slow = Box::pin(broadcast.subscribe(ethbtc(), Interval::I1h));
+        assert!(futures::poll!(slow.as_mut()).is_pending());
+        let mut joined = broadcast.subscribe(ethbtc(), Interval::I1h).await;
+
+        // Dropping the connecting subscriber closes the channel that the other one joined.
+        drop(slow);
+        let timeout = Duration::from_secs(5);
+        assert!(tokio::time::timeout(timeout, joined.next())
+            .await
+            .unwrap()
+            .is_none());
+
+        // Later subscribers connect again instead of joining the dead channel.
+        let again = broadcast.subscribe(ethbtc(), Interval::I1h);
+        futures::pin_mut!(again);
+        assert!(futures::poll!(again.as_mut()).is_pending());
+        assert_eq!(subscriptions.load(Ordering::SeqCst), 2);
+    }
+}
@@ -1,20 +1,292 @@
-use crate::{Error, Market, Price, Quantity};
+use crate::{Error, Interval, Market, Price, Quantity, Storage, Timestamp};
+use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug)]
+pub enum CandlestickError {
+    /// The open time is not before the close time.
+    InvalidTimes,
+    /// The open or close price lies outside of the low and high prices.
+    InvalidPrices,
+    /// A volume is negative or the taker volumes exceed the total volumes.
+    InvalidVolumes,
+    /// A price belongs to a different market.
+    WrongMarket,
+    /// A volume is not measured in the asset of the market it belongs to.
+    WrongAsset,
+}
+
+/// Prices and volumes of a market over an interval.
+///
+/// Candlesticks from external sources are created with `Candlestick::new`,
+/// which rejects them if they violate the invariants checked by `validate`.
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
 pub struct Candlestick {
     pub market: &'static Market,
-    pub open_time: u64,
-    pub close_time: u64,
+    pub open_time: Timestamp,
+    pub close_time: Timestamp,
     pub high: Price,
     pub low: Price,
     pub open: Price,
     pub close: Price,
+    /// Traded quantity of the base asset.
     pub volume: Quantity,
+    /// Traded quantity of the quote asset.
+    pub quote_volume: Quantity,
+    /// Quantity of the base asset bought by takers.
+    pub taker_buy_base_volume: Quantity,
+    /// Quantity of the quote asset spent by takers on buying.
+    pub taker_buy_quote_volume: Quantity,
     pub trades: u64,
+    /// False while the candlestick is still forming and may receive further updates.
+    pub closed: bool,
 }
 
 impl Candlestick {
-    pub fn insert(&self) -> Result<(), Error> {
+    /// Creates a candlestick and checks that it is consistent.
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        market: &'static Market,
+        open_time: Timestamp,
+        close_time: Timestamp,
+        open: Price,
+        high: Price,
+        low: Price,
+        close: Price,
+        volume: Quantity,
+        quote_volume: Quantity,
+        taker_buy_base_volume: Quantity,
+        taker_buy_quote_volume: Quantity,
+        trades: u64,
+        closed: bool,
+    ) -> Result<Self, CandlestickError> {
+        let candlestick = Self {
+            market,
+            open_time,
+            close_time,
+            high,
+            low,
+            open,
+            close,
+            volume,
+            quote_volume,
+            taker_buy_base_volume,
+            taker_buy_quote_volume,
+            trades,
+            closed,
+        };
+        candlestick.validate()?;
+
+        Ok(candlestick)
+    }
+
+    /// Checks that the candlestick is consistent.
+    pub fn validate(&self) -> Result<(), CandlestickError> {
+        if self.open_time >= self.close_time {
+            return Err(CandlestickError::InvalidTimes);
+        }
+
+        if [self.high, self.low, self.open, self.close]
+            .iter()
+            .any(|price| price.market != self.market)
+        {
+            return Err(CandlestickError::WrongMarket);
+        }
+
+        if !(self.low <= self.open
+            && self.low <= self.close
+            && self.open <= self.high
+            && self.close <= self.high)
+        {
+            return Err(CandlestickError::InvalidPrices);
+        }
+
+        if self.volume.asset != self.market.base
+            || self.taker_buy_base_volume.asset != self.market.base
+            || self.quote_volume.asset != self.market.quote
+            || self.taker_buy_quote_volume.asset != self.market.quote
+        {
+            return Err(CandlestickError::WrongAsset);
+        }
+
+        if !(0.0 <= self.taker_buy_base_volume.quantity
+            && self.taker_buy_base_volume.quantity <= self.volume.quantity
+            && 0.0 <= self.taker_buy_quote_volume.quantity
+            && self.taker_buy_quote_volume.quantity <= self.quote_volume.quantity)
+        {
+            return Err(CandlestickError::InvalidVolumes);
+        }
+
         Ok(())
     }
+
+    /// Inserts the candlestick into the storage, or updates it if it is already stored.
+    pub fn insert(&self, storage: &Storage, interval: Interval) -> Result<(), Error> {
+        storage.insert(interval, self)
+    }
+
+    /// Merges this candlestick with the candlestick that directly follows it.
+    pub fn merge(self, next: Candlestick) -> Candlestick {
+        Candlestick {
+            market: self.market,
+            open_time: self.open_time,
+            close_time: next.close_time,
+            high: if self.high > next.high {
+                self.high
+            } else {
+                next.high
+            },
+            low: if self.low < next.low {
+                self.low
+            } else {
+                next.low
+            },
+            open: self.open,
+            close: next.close,
+            volume: self.volume + next.volume,
+            quote_volume: self.quote_volume + next.quote_volume,
+            taker_buy_base_volume: self.taker_buy_base_volume + next.taker_buy_base_volume,
+            taker_buy_quote_volume: self.taker_buy_quote_volume + next.taker_buy_quote_volume,
+            trades: self.trades + next.trades,
+            closed: next.closed,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Asset;
+
+    /// Creates a candlestick of ETHBTC with the given prices and base volumes,
+    /// whose quote volumes are half of them.
+    fn new(
+        open_time: u64,
+        close_time: u64,
+        [open, high, low, close]: [f64; 4],
+        volume: f64,
+        taker_buy_volume: f64,
+    ) -> Result<Candlestick, CandlestickError> {
+        let market = Market::intern(Asset::intern("ETH"), Asset::intern("BTC"));
+        let price = |price| Price { price, market };
+        let quantity = |quantity, asset| Quantity { quantity, asset };
+
+        Candlestick::new(
+            market,
+            Timestamp::from_millis(open_time),
+            Timestamp::from_millis(close_time),
+            price(open),
+            price(high),
+            price(low),
+            price(close),
+            quantity(volume, market.base),
+            quantity(volume * 0.5, market.quote),
+            quantity(taker_buy_volume, market.base),
+            quantity(taker_buy_volume * 0.5, market.quote),
+            3,
+            true,
+        )
+    }
+
+    #[test]
+    fn test_new() {
+        let candlestick = new(0, 59_999, [0.5, 0.6, 0.4, 0.55], 2.0, 1.0).unwrap();
+        assert_eq!(candlestick.high.price, 0.6);
+        assert_eq!(candlestick.low.price, 0.4);
+        assert_eq!(candlestick.quote_volume.quantity, 1.0);
+    }
+
+    #[test]
+    fn test_new_invalid_times() {
+        assert!(matches!(
+            new(60_000, 59_999, [0.5, 0.6, 0.4, 0.55], 2.0, 1.0),
+            Err(CandlestickError::InvalidTimes)
+        ));
+        assert!(matches!(
+            new(60_000, 60_000, [0.5, 0.6, 0.4, 0.55], 2.0, 1.0),
+            Err(CandlestickError::InvalidTimes)
+        ));
+    }
+
+    #[test]
+    fn test_new_invalid_prices() {
+        for &prices in &[
+            [0.7, 0.6, 0.4, 0.55],
+            [0.5, 0.6, 0.4, 0.3],
+            [0.5, 0.4, 0.6, 0.55],
+        ] {
+            assert!(matches!(
+                new(0, 59_999, prices, 2.0, 1.0),
+                Err(CandlestickError::InvalidPrices)
+            ));
+        }
+    }
+
+    #[test]
+    fn test_new_invalid_volumes() {
+        assert!(matches!(
+            new(0, 59_999, [0.5, 0.6, 0.4, 0.55], 2.0, -1.0),
+            Err(CandlestickError::InvalidVolumes)
+        ));
+        assert!(matches!(
+            new(0, 59_999, [0.5, 0.6, 0.4, 0.55], 2.0, 3.0),
+            Err(CandlestickError::InvalidVolumes)
+        ));
+    }
+
+    #[test]
+    fn test_new_wrong_market() {
+        let mut candlestick = new(0, 59_999, [0.5, 0.6, 0.4, 0.55], 2.0, 1.0).unwrap();
+        candlestick.close.market = Market::intern(Asset::intern("ETH"), Asset::intern("USDT"));
+        assert!(matches!(
+            candlestick.validate(),
+            Err(CandlestickError::WrongMarket)
+        ));
+    }
+
+    #[test]
+    fn test_new_wrong_asset() {
+        let mut candlestick = new(0, 59_999, [0.5, 0.6, 0.4, 0.55], 2.0, 1.0).unwrap();
+        candlestick.quote_volume.asset = candlestick.market.base;
+        assert!(matches!(
+            candlestick.validate(),
+            Err(CandlestickError::WrongAsset)
+        ));
+    }
+
+    #[test]
+    fn test_serde() {
+        let market = Market::intern(Asset::intern("ETH"), Asset::intern("BTC"));
+        let price = Price { price: 0.5, market };
+        let quantity = |quantity, asset| Quantity { quantity, asset };
+        let candlestick = Candlestick {
+            market,
+            open_time: Timestamp::from_millis(0),
+            close_time: Timestamp::from_millis(59_999),
+            high: price,
+            low: price,
+            open: price,
+            close: price,
+            volume: quantity(2.0, market.base),
+            quote_volume: quantity(1.0, market.quote),
+            taker_buy_base_volume: quantity(1.0, market.base),
+            taker_buy_quote_volume: quantity(0.5, market.quote),
+            trades: 3,
+            closed: true,
+        };
+
+        let json = serde_json::to_string(&candlestick).unwrap();
+        assert!(json.starts_with(r#"{"market":"ETHBTC","open_time":0,"close_time":59999,"high":{"price":0.5,"market":"ETHBTC"}"#));
+        assert!(json.contains(r#""volume":{"quantity":2.0,"asset":"ETH"}"#));
+
+        let deserialized: Candlestick = serde_json::from_str(&json).unwrap();
+        assert!(std::ptr::eq(deserialized.market, market));
+        assert!(std::ptr::eq(deserialized.volume.asset, market.base));
+        assert_eq!(deserialized.close, price);
+        assert_eq!(
+            deserialized.taker_buy_quote_volume,
+            quantity(0.5, market.quote)
+        );
+
+        assert!(serde_json::from_str::<Price>(r#"{"price":1.0,"market":"XMRBTC"}"#).is_err());
+    }
 }
@@ -0,0 +1,88 @@
+use crate::{Candlestick, Timestamp, Transform};
+
+/// Passes on every closed candlestick exactly once.
+/// Updates of forming candlesticks are dropped, as are closed candlesticks
+/// that were already passed on, for example when they are replayed after a reconnect.
+#[derive(Debug, Clone, Default)]
+pub struct Closed {
+    /// Open time of the latest closed candlestick.
+    latest: Option<Timestamp>,
+}
+
+impl Closed {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only passes on closed candlesticks that open after the given time.
+    pub fn after(open_time: Timestamp) -> Self {
+        Self {
+            latest: Some(open_time),
+        }
+    }
+}
+
+impl Transform for Closed {
+    fn push(&mut self, candlestick: Candlestick) -> Vec<Candlestick> {
+        if !candlestick.closed {
+            return Vec::new();
+        }
+
+        if let Some(latest) = self.latest {
+            if candlestick.open_time <= latest {
+                return Vec::new();
+            }
+        }
+        self.latest = Some(candlestick.open_time);
+
+        vec![candlestick]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{candlestick, ethbtc, minute};
+    use crate::transform;
+
+    fn forming(minute: u64) -> Candlestick {
+        Candlestick {
+            closed: false,
+            ..candlestick(ethbtc(), minute)
+        }
+    }
+
+    fn open_times(candlesticks: Vec<Candlestick>) -> Vec<Timestamp> {
+        candlesticks
+            .iter()
+            .map(|candlestick| candlestick.open_time)
+            .collect()
+    }
+
+    #[test]
+    fn test_closed_reconnect() {
+        // After the reconnect the stream replays the latest closed candlestick
+        // before it continues with the forming one.
+        let candlesticks = vec![
+            forming(0),
+            candlestick(ethbtc(), 0),
+            forming(1),
+            candlestick(ethbtc(), 0),
+            forming(1),
+            candlestick(ethbtc(), 1),
+        ];
+        assert_eq!(
+            open_times(transform(candlesticks, Closed::new())),
+            vec![minute(0), minute(1)]
+        );
+    }
+
+    #[test]
+    fn test_closed_after() {
+        let candlesticks = vec![candlestick(ethbtc(), 1), candlestick(ethbtc(), 2)];
+        assert_eq!(
+            open_times(transform(candlesticks, Closed::after(minute(1)))),
+            vec![minute(2)]
+        );
+    }
+}
@@ -0,0 +1,736 @@
+use crate::{
+    Asset, Candlestick, CsvError, CsvLayout, Error, Interval, Market, Monetary, Price, Quantity,
+    Storage, Timestamp,
+};
+use memmap2::Mmap;
+use std::convert::{TryFrom, TryInto};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+const MAGIC: &[u8; 4] = b"CNDL";
+const VERSION: u16 = 1;
+const HEADER: usize = 64;
+/// Number of rows after which the open time is stored absolutely instead of as a delta.
+const BLOCK: usize = 1024;
+/// Maximal length of the name of an asset in the header.
+const NAME: usize = 16;
+
+// Indices of the fixed-point columns.
+const OPEN: usize = 0;
+const HIGH: usize = 1;
+const LOW: usize = 2;
+const CLOSE: usize = 3;
+const VOLUME: usize = 4;
+const QUOTE_VOLUME: usize = 5;
+const TAKER_BUY_BASE_VOLUME: usize = 6;
+const TAKER_BUY_QUOTE_VOLUME: usize = 7;
+const FIXED_COLUMNS: usize = 8;
+
+#[derive(Debug)]
+pub enum ColumnarError {
+    Io(std::io::Error),
+    Storage(Error),
+    Csv(CsvError),
+    /// The file is not in the columnar format, its size does not match its header
+    /// or its open times overflow.
+    Corrupted,
+    UnsupportedVersion(u16),
+    /// The name of an asset does not fit into the header.
+    NameTooLong,
+    /// The candlesticks are not of the given market, not strictly ordered by open time
+    /// or their open times are not apart by multiples of the interval.
+    InvalidSeries,
+    /// A price or volume cannot be represented with the configured number of decimals,
+    /// a number of trades does not fit into 32 bits or the series is too long.
+    OutOfRange,
+}
+
+impl From<std::io::Error> for ColumnarError {
+    fn from(error: std::io::Error) -> Self {
+        Self::Io(error)
+    }
+}
+
+impl From<Error> for ColumnarError {
+    fn from(error: Error) -> Self {
+        Self::Storage(error)
+    }
+}
+
+impl From<CsvError> for ColumnarError {
+    fn from(error: CsvError) -> Self {
+        Self::Csv(error)
+    }
+}
+
+/// Offsets of the sections of a file with the given number of rows.
+///
+/// A file starts with a header of 64 bytes, which holds the magic bytes, the version,
+/// the numbers of decimals of prices and volumes, the number of rows, the interval and the market.
+/// It is followed by the absolute open time of every block of rows, the open time deltas
+/// in intervals, the fixed-point prices and volumes, the numbers of trades
+/// and the closed flags, each as a column of little-endian values that starts 8-byte aligned.
+#[derive(Debug, Copy, Clone)]
+struct Layout {
+    checkpoints: usize,
+    deltas: usize,
+    fixed: usize,
+    trades: usize,
+    closed: usize,
+    len: usize,
+}
+
+impl Layout {
+    /// Returns `None` if the offsets do not fit into `usize`,
+    /// for example when the number of rows is read from a corrupted header.
+    fn new(rows: usize) -> Option<Self> {
+        let padded = |len: usize| len.checked_add(7).map(|len| len / 8 * 8);
+        let blocks = rows.div_ceil(BLOCK);
+
+        let checkpoints = HEADER;
+        let deltas = checkpoints.checked_add(blocks.checked_mul(8)?)?;
+        let fixed = deltas.checked_add(padded(rows.checked_mul(4)?)?)?;
+        let trades = fixed.checked_add(rows.checked_mul(FIXED_COLUMNS * 8)?)?;
+        let closed = trades.checked_add(padded(rows.checked_mul(4)?)?)?;
+        let len = closed.checked_add(padded(rows)?)?;
+
+        Some(Self {
+            checkpoints,
+            deltas,
+            fixed,
+            trades,
+            closed,
+            len,
+        })
+    }
+}
+
+/// Writes series of candlesticks in a compact, columnar format that is read by `ColumnarFile`.
+///
+/// Open times are stored as deltas in intervals, prices and volumes as fixed-point numbers
+/// with a configurable number of decimals.
+#[derive(Debug, Copy, Clone)]
+pub struct ColumnarWriter {
+    price_decimals: u8,
+    volume_decimals: u8,
+}
+
+impl ColumnarWriter {
+    /// Creates a writer that stores prices and volumes with 8 decimals.
+    pub fn new() -> Self {
+        Self {
+            price_decimals: 8,
+            volume_decimals: 8,
+        }
+    }
+
+    pub fn price_decimals(mut self, decimals: u8) -> Self {
+        self.price_decimals = decimals;
+        self
+    }
+
+    pub fn volume_decimals(mut self, decimals: u8) -> Self {
+        self.volume_decimals = decimals;
+        self
+    }
+
+    /// Writes the candlesticks of the market, which have to be ordered by their open times.
+    /// Nothing is written if the candlesticks cannot be encoded.
+    pub fn write<W: Write>(
+        &self,
+        writer: W,
+        market: &'static Market,
+        interval: Interval,
+        candlesticks: &[Candlestick],
+    ) -> Result<(), ColumnarError> {
+        let mut header = [0u8; HEADER];
+        header[0..4].copy_from_slice(MAGIC);
+        header[4..6].copy_from_slice(&VERSION.to_le_bytes());
+        header[6] = self.price_decimals;
+        header[7] = self.volume_decimals;
+        header[8..16].copy_from_slice(&(candlesticks.len() as u64).to_le_bytes());
+        name(&mut header[16..20], &interval.to_string())?;
+        name(&mut header[24..24 + NAME], &market.base.to_string())?;
+        name(&mut header[40..40 + NAME], &market.quote.to_string())?;
+
+        let duration = interval.duration().num_milliseconds() as u64;
+        let mut checkpoints = Vec::new();
+        let mut deltas = Vec::with_capacity(candlesticks.len());
+        let mut trades = Vec::with_capacity(candlesticks.len());
+        let mut fixed = (0..FIXED_COLUMNS)
+            .map(|_| Vec::with_capacity(candlesticks.len()))
+            .collect::<Vec<_>>();
+        let price = |price: Price| fixed_point(price.price, self.price_decimals);
+        let volume = |volume: Quantity| fixed_point(volume.quantity, self.volume_decimals);
+
+        for (index, candlestick) in candlesticks.iter().enumerate() {
+            if candlestick.market != market {
+                return Err(ColumnarError::InvalidSeries);
+            }
+
+            let delta = match index {
+                0 => 0,
+                _ => {
+                    let previous = candlesticks[index - 1].open_time.millis();
+                    let open_time = candlestick.open_time.millis();
+                    if open_time <= previous || !(open_time - previous).is_multiple_of(duration) {
+                        return Err(ColumnarError::InvalidSeries);
+                    }
+                    (open_time - previous) / duration
+                }
+            };
+            if index.is_multiple_of(BLOCK) {
+                checkpoints.push(candlestick.open_time.millis());
+            }
+            deltas.push(delta.try_into().map_err(|_| ColumnarError::InvalidSeries)?);
+            trades.push(u32::try_from(candlestick.trades).map_err(|_| ColumnarError::OutOfRange)?);
+
+            fixed[OPEN].push(price(candlestick.open)?);
+            fixed[HIGH].push(price(candlestick.high)?);
+            fixed[LOW].push(price(candlestick.low)?);
+            fixed[CLOSE].push(price(candlestick.close)?);
+            fixed[VOLUME].push(volume(candlestick.volume)?);
+            fixed[QUOTE_VOLUME].push(volume(candlestick.quote_volume)?);
+            fixed[TAKER_BUY_BASE_VOLUME].push(volume(candlestick.taker_buy_base_volume)?);
+            fixed[TAKER_BUY_QUOTE_VOLUME].push(volume(candlestick.taker_buy_quote_volume)?);
+        }
+
+        let layout = Layout::new(candlesticks.len()).ok_or(ColumnarError::OutOfRange)?;
+        let mut writer = Counting {
+            writer: BufWriter::new(writer),
+            written: 0,
+        };
+        writer.write_all(&header)?;
+        for checkpoint in checkpoints {
+            writer.write_all(&checkpoint.to_le_bytes())?;
+        }
+        for delta in deltas {
+            writer.write_all(&u32::to_le_bytes(delta))?;
+        }
+        writer.pad(layout.fixed)?;
+        for column in fixed {
+            for value in column {
+                writer.write_all(&value.to_le_bytes())?;
+            }
+        }
+        for trades in trades {
+            writer.write_all(&trades.to_le_bytes())?;
+        }
+        writer.pad(layout.closed)?;
+        for candlestick in candlesticks {
+            writer.write_all(&[candlestick.closed as u8])?;
+        }
+        writer.pad(layout.len)?;
+        writer.writer.flush()?;
+
+        Ok(())
+    }
+
+    pub fn save<P: AsRef<Path>>(
+        &self,
+        path: P,
+        market: &'static Market,
+        interval: Interval,
+        candlesticks: &[Candlestick],
+    ) -> Result<(), ColumnarError> {
+        self.write(File::create(path)?, market, interval, candlesticks)
+    }
+
+    /// Writes the stored candlesticks with open times between `start` and `end`, both inclusive,
+    /// to the file and returns their number.
+    pub fn export<P: AsRef<Path>>(
+        &self,
+        storage: &Storage,
+        market: &'static Market,
+        interval: Interval,
+        start: Timestamp,
+        end: Timestamp,
+        path: P,
+    ) -> Result<usize, ColumnarError> {
+        let candlesticks = storage.candlesticks(market, interval, start, end)?;
+        self.save(path, market, interval, &candlesticks)?;
+
+        Ok(candlesticks.len())
+    }
+
+    /// Converts a CSV file of candlesticks into a columnar file and returns their number.
+    pub fn convert_csv<P: AsRef<Path>, Q: AsRef<Path>>(
+        &self,
+        layout: &CsvLayout,
+        csv: P,
+        market: &'static Market,
+        interval: Interval,
+        path: Q,
+    ) -> Result<usize, ColumnarError> {
+        let mut candlesticks = layout.load(csv, market, interval)?;
+        candlesticks.sort_by_key(|candlestick| candlestick.open_time);
+        self.save(path, market, interval, &candlesticks)?;
+
+        Ok(candlesticks.len())
+    }
+}
+
+impl Default for ColumnarWriter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Writer that keeps track of the number of written bytes to align the columns.
+struct Counting<W: Write> {
+    writer: W,
+    written: usize,
+}
+
+impl<W: Write> Counting<W> {
+    fn write_all(&mut self, bytes: &[u8]) -> std::io::Result<()> {
+        self.written += bytes.len();
+        self.writer.write_all(bytes)
+    }
+
+    /// Fills the output with zeros up to the given offset.
+    fn pad(&mut self, offset: usize) -> std::io::Result<()> {
+        let padding = vec![0u8; offset - self.written];
+        self.write_all(&padding)
+    }
+}
+
+fn name(field: &mut [u8], name: &str) -> Result<(), ColumnarError> {
+    if name.len() > field.len() {
+        return Err(ColumnarError::NameTooLong);
+    }
+    field[..name.len()].copy_from_slice(name.as_bytes());
+
+    Ok(())
+}
+
+fn fixed_point(value: Monetary, decimals: u8) -> Result<i64, ColumnarError> {
+    let scaled = (value * 10f64.powi(decimals as i32)).round();
+    if !scaled.is_finite() || scaled.abs() >= i64::MAX as f64 {
+        return Err(ColumnarError::OutOfRange);
+    }
+
+    Ok(scaled as i64)
+}
+
+/// Memory mapped file of candlesticks in the columnar format written by `ColumnarWriter`.
+///
+/// Candlesticks are decoded directly from the mapped file while iterating,
+/// so only the pages of the iterated range are read from disk.
+pub struct ColumnarFile {
+    map: Mmap,
+    market: &'static Market,
+    interval: Interval,
+    rows: usize,
+    price_scale: Monetary,
+    volume_scale: Monetary,
+    layout: Layout,
+}
+
+impl ColumnarFile {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, ColumnarError> {
+        let file = File::open(path)?;
+        // The file must not be modified while it is mapped, which holds for files
+        // that are only written once by `ColumnarWriter`.
+        let map = unsafe { Mmap::map(&file)? };
+
+        if map.len() < HEADER || &map[0..4] != MAGIC {
+            return Err(ColumnarError::Corrupted);
+        }
+        let version = u16::from_le_bytes(map[4..6].try_into().unwrap());
+        if version != VERSION {
+            return Err(ColumnarError::UnsupportedVersion(version));
+        }
+
+        let text = |field: &[u8]| -> Result<String, ColumnarError> {
+            let end = field
+                .iter()
+                .position(|&byte| byte == 0)
+                .unwrap_or(field.len());
+            String::from_utf8(field[..end].to_vec()).map_err(|_| ColumnarError::Corrupted)
+        };
+        let rows = usize::try_from(u64::from_le_bytes(map[8..16].try_into().unwrap()))
+            .map_err(|_| ColumnarError::Corrupted)?;
+        let layout = Layout::new(rows)
+            .filter(|layout| layout.len == map.len())
+            .ok_or(ColumnarError::Corrupted)?;
+
+        let interval = text(&map[16..20])?
+            .parse()
+            .map_err(|_| ColumnarError::Corrupted)?;
+        let base = Asset::intern(&text(&map[24..24 + NAME])?);
+        let quote = Asset::intern(&text(&map[40..40 + NAME])?);
+
+        let file = Self {
+            market: Market::intern(base, quote),
+            interval,
+            rows,
+            price_scale: 10f64.powi(map[6] as i32),
+            volume_scale: 10f64.powi(map[7] as i32),
+            layout,
+            map,
+        };
+        file.validate()?;
+
+        Ok(file)
+    }
+
+    pub fn market(&self) -> &'static Market {
+        self.market
+    }
+
+    pub fn interval(&self) -> Interval {
+        self.interval
+    }
+
+    pub fn len(&self) -> usize {
+        self.rows
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rows == 0
+    }
+
+    /// Returns the candlestick at the given index.
+    pub fn get(&self, index: usize) -> Option<Candlestick> {
+        if index >= self.rows {
+            return None;
+        }
+
+        let start = index / BLOCK * BLOCK;
+        let open_time = (start + 1..=index)
+            .fold(self.checkpoint(index / BLOCK), |open_time, row| {
+                self.next_open_time(open_time, row)
+            });
+
+        Some(self.candlestick(index, open_time))
+    }
+
+    /// Iterates over all candlesticks.
+    pub fn iter(&self) -> ColumnarIter<'_> {
+        ColumnarIter {
+            file: self,
+            index: 0,
+            open_time: Timestamp::default(),
+            end: None,
+        }
+    }
+
+    /// Iterates over the candlesticks with open times between `start` and `end`, both inclusive.
+    pub fn range(&self, start: Timestamp, end: Timestamp) -> ColumnarIter<'_> {
+        // Find the last block that starts at or before the start.
+        let (mut low, mut high) = (0, self.rows.div_ceil(BLOCK));
+        while low < high {
+            let middle = (low + high) / 2;
+            if self.checkpoint(middle) <= start {
+                low = middle + 1;
+            } else {
+                high = middle;
+            }
+        }
+        let block = low.saturating_sub(1);
+
+        let mut iter = ColumnarIter {
+            file: self,
+            index: block * BLOCK,
+            open_time: Timestamp::default(),
+            end: Some(end),
+        };
+        while iter.index < self.rows {
+            let open_time = iter.open_time(iter.index);
+            if open_time >= start {
+                break;
+            }
+            iter.open_time = open_time;
+            iter.index += 1;
+        }
+
+        iter
+    }
+
+    /// Inserts all candlesticks into the storage and returns their number.
+    pub fn import(&self, storage: &Storage) -> Result<usize, ColumnarError> {
+        let candlesticks: Vec<Candlestick> = self.iter().collect();
+        storage.insert_all(self.interval, &candlesticks)?;
+
+        Ok(candlesticks.len())
+    }
+
+    /// Writes all candlesticks as CSV.
+    pub fn write_csv<W: Write>(&self, layout: &CsvLayout, writer: W) -> Result<(), ColumnarError> {
+        let candlesticks: Vec<Candlestick> = self.iter().collect();
+        layout.write(writer, &candlesticks)?;
+
+        Ok(())
+    }
+
+    /// Checks that the open and close times of all rows can be computed without overflow,
+    /// so that they are decoded without checks while iterating.
+    fn validate(&self) -> Result<(), ColumnarError> {
+        let duration = self.interval.duration().num_milliseconds() as u64;
+        let max = i64::MAX as u64 - duration;
+        let mut open_time = 0u64;
+        for row in 0..self.rows {
+            open_time = if row.is_multiple_of(BLOCK) {
+                self.checkpoint(row / BLOCK).millis()
+            } else {
+                self.delta(row)
+                    .checked_mul(duration)
+                    .and_then(|delta| open_time.checked_add(delta))
+                    .ok_or(ColumnarError::Corrupted)?
+            };
+            if open_time > max {
+                return Err(ColumnarError::Corrupted);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn bytes<const N: usize>(&self, offset: usize) -> [u8; N] {
+        self.map[offset..offset + N].try_into().unwrap()
+    }
+
+    fn checkpoint(&self, block: usize) -> Timestamp {
+        Timestamp::from_millis(u64::from_le_bytes(
+            self.bytes(self.layout.checkpoints + block * 8),
+        ))
+    }
+
+    /// Returns the open time of the row, given the open time of the row before it.
+    fn next_open_time(&self, previous: Timestamp, row: usize) -> Timestamp {
+        if row.is_multiple_of(BLOCK) {
+            return self.checkpoint(row / BLOCK);
+        }
+
+        let duration = self.interval.duration().num_milliseconds() as u64;
+        Timestamp::from_millis(previous.millis() + self.delta(row) * duration)
+    }
+
+    /// Returns the number of intervals between the open time of the row and the one before it.
+    fn delta(&self, row: usize) -> u64 {
+        u32::from_le_bytes(self.bytes(self.layout.deltas + row * 4)) as u64
+    }
+
+    fn fixed(&self, column: usize, row: usize) -> i64 {
+        i64::from_le_bytes(self.bytes(self.layout.fixed + (column * self.rows + row) * 8))
+    }
+
+    fn candlestick(&self, row: usize, open_time: Timestamp) -> Candlestick {
+        let market = self.market;
+        let price = |column| Price {
+            price: self.fixed(column, row) as Monetary / self.price_scale,
+            market,
+        };
+        let quantity = |column, asset| Quantity {
+            quantity: self.fixed(column, row) as Monetary / self.volume_scale,
+            asset,
+        };
+
+        Candlestick {
+            market,
+            open_time,
+            close_time: self.interval.close_time(open_time),
+            high: price(HIGH),
+            low: price(LOW),
+            open: price(OPEN),
+            close: price(CLOSE),
+            volume: quantity(VOLUME, market.base),
+            quote_volume: quantity(QUOTE_VOLUME, market.quote),
+            taker_buy_base_volume: quantity(TAKER_BUY_BASE_VOLUME, market.base),
+            taker_buy_quote_volume: quantity(TAKER_BUY_QUOTE_VOLUME, market.quote),
+            trades: u32::from_le_bytes(self.bytes(self.layout.trades + row * 4)) as u64,
+            closed: self.map[self.layout.closed + row] != 0,
+        }
+    }
+}
+
+/// Iterator over the candlesticks of a `ColumnarFile`.
+pub struct ColumnarIter<'a> {
+    file: &'a ColumnarFile,
+    index: usize,
+    /// Open time of the row before the current index.
+    open_time: Timestamp,
+    end: Option<Timestamp>,
+}
+
+impl<'a> ColumnarIter<'a> {
+    fn open_time(&self, index: usize) -> Timestamp {
+        self.file.next_open_time(self.open_time, index)
+    }
+}
+
+impl<'a> Iterator for ColumnarIter<'a> {
+    type Item = Candlestick;
+
+    fn next(&mut self) -> Option<Candlestick> {
+        if self.index >= self.file.rows {
+            return None;
+        }
+
+        let open_time = self.open_time(self.index);
+        if let Some(end) = self.end {
+            if open_time > end {
+                return None;
+            }
+        }
+        let candlestick = self.file.candlestick(self.index, open_time);
+        self.open_time = open_time;
+        self.index += 1;
+
+        Some(candlestick)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{candlestick, eth, ethbtc, minute, price};
+
+    #[test]
+    fn test_columnar() {
+        let market = ethbtc();
+        let candlestick = |minute: u64| Candlestick {
+            close: price(0.025 + minute as f64 * 1e-8),
+            volume: eth(12.5),
+            trades: minute,
+            closed: minute != 3000,
+            ..candlestick(market, minute)
+        };
+        // Minutes 1000 to 1099 are missing.
+        let candlesticks: Vec<Candlestick> =
+            (0..1000).chain(1100..=3000).map(candlestick).collect();
+
+        let path = std::env::temp_dir().join(format!("test_columnar_{}.cndl", std::process::id()));
+        ColumnarWriter::new()
+            .save(&path, market, Interval::I1m, &candlesticks)
+            .unwrap();
+        let file = ColumnarFile::open(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(file.market(), market);
+        assert_eq!(file.interval(), Interval::I1m);
+        assert_eq!(file.len(), candlesticks.len());
+        let read: Vec<Candlestick> = file.iter().collect();
+        assert_eq!(read.len(), candlesticks.len());
+        for (read, written) in read.iter().zip(&candlesticks) {
+            assert_eq!(read.open_time, written.open_time);
+            assert_eq!(read.close_time, written.close_time);
+            assert!((read.close.price - written.close.price).abs() < 1e-12);
+            assert_eq!(read.volume, written.volume);
+            assert_eq!(read.trades, written.trades);
+            assert_eq!(read.closed, written.closed);
+        }
+        assert_eq!(
+            file.get(1500).unwrap().open_time,
+            candlesticks[1500].open_time
+        );
+
+        let minutes = |start: u64, end: u64| {
+            file.range(minute(start), minute(end))
+                .map(|candlestick| candlestick.open_time.millis() / 60_000)
+                .collect::<Vec<u64>>()
+        };
+        assert_eq!(minutes(998, 1101), vec![998, 999, 1100, 1101]);
+        assert_eq!(minutes(2047, 2049), vec![2047, 2048, 2049]);
+        assert_eq!(minutes(3000, 4000), vec![3000]);
+        assert!(minutes(4000, 5000).is_empty());
+
+        let storage = Storage::in_memory().unwrap();
+        assert_eq!(file.import(&storage).unwrap(), candlesticks.len());
+        assert_eq!(
+            storage.latest(market, Interval::I1m).unwrap(),
+            Some(minute(2999))
+        );
+
+        assert!(matches!(
+            ColumnarWriter::new().write(Vec::new(), market, Interval::I5m, &candlesticks[..2]),
+            Err(ColumnarError::InvalidSeries)
+        ));
+        assert!(matches!(
+            ColumnarWriter::new().volume_decimals(18).write(
+                Vec::new(),
+                market,
+                Interval::I1m,
+                &candlesticks[..2]
+            ),
+            Err(ColumnarError::OutOfRange)
+        ));
+    }
+
+    #[test]
+    fn test_columnar_trades_out_of_range() {
+        let candlestick = Candlestick {
+            trades: u32::MAX as u64 + 1,
+            ..candlestick(ethbtc(), 0)
+        };
+        assert!(matches!(
+            ColumnarWriter::new().write(Vec::new(), ethbtc(), Interval::I1m, &[candlestick]),
+            Err(ColumnarError::OutOfRange)
+        ));
+    }
+
+    #[test]
+    fn test_columnar_corrupted_rows() {
+        let mut bytes = Vec::new();
+        ColumnarWriter::new()
+            .write(
+                &mut bytes,
+                ethbtc(),
+                Interval::I1m,
+                &[candlestick(ethbtc(), 0)],
+            )
+            .unwrap();
+        let path = std::env::temp_dir().join(format!(
+            "test_columnar_corrupted_{}.cndl",
+            std::process::id()
+        ));
+
+        // Row counts that overflow the layout or do not match the file size are rejected.
+        let mut opened = Vec::new();
+        for &rows in &[u64::MAX, u64::MAX / 8, 2] {
+            bytes[8..16].copy_from_slice(&rows.to_le_bytes());
+            std::fs::write(&path, &bytes).unwrap();
+            opened.push(ColumnarFile::open(&path));
+        }
+        std::fs::remove_file(&path).unwrap();
+        assert!(opened
+            .iter()
+            .all(|opened| matches!(opened, Err(ColumnarError::Corrupted))));
+    }
+
+    #[test]
+    fn test_columnar_corrupted_open_times() {
+        let mut bytes = Vec::new();
+        ColumnarWriter::new()
+            .write(
+                &mut bytes,
+                ethbtc(),
+                Interval::I1m,
+                &[candlestick(ethbtc(), 0), candlestick(ethbtc(), 1)],
+            )
+            .unwrap();
+        let layout = Layout::new(2).unwrap();
+        let path = std::env::temp_dir().join(format!(
+            "test_columnar_open_times_{}.cndl",
+            std::process::id()
+        ));
+
+        // Open times that leave no room for the close time are rejected.
+        let mut opened = Vec::new();
+        let max = i64::MAX as u64 - 60_000;
+        for &(checkpoint, delta) in &[(max + 1, 1), (max - 60_000, 2), (max, u32::MAX)] {
+            let mut bytes = bytes.clone();
+            bytes[layout.checkpoints..layout.checkpoints + 8]
+                .copy_from_slice(&checkpoint.to_le_bytes());
+            bytes[layout.deltas + 4..layout.deltas + 8].copy_from_slice(&delta.to_le_bytes());
+            std::fs::write(&path, &bytes).unwrap();
+            opened.push(ColumnarFile::open(&path));
+        }
+        std::fs::remove_file(&path).unwrap();
+        assert!(opened
+            .iter()
+            .all(|opened| matches!(opened, Err(ColumnarError::Corrupted))));
+    }
+}
@@ -0,0 +1,241 @@
+use crate::{Asset, Candlestick, Market, Monetary, Price, Quantity, Wallet};
+use std::collections::HashMap;
+
+#[derive(Debug)]
+pub enum ConversionError {
+    /// There is no path of markets with known prices from the asset to the target asset.
+    NoPath(&'static Asset),
+}
+
+/// Converts quantities between assets using the latest prices of all markets,
+/// also if there is no direct market between two assets.
+///
+/// Assets and markets form a graph, which is searched for the path with the best rate
+/// among all paths of up to `max_hops` markets.
+#[derive(Debug, Clone)]
+pub struct Converter {
+    prices: HashMap<&'static Market, Price>,
+    /// Markets with a known price, grouped by their assets.
+    markets: HashMap<&'static Asset, Vec<&'static Market>>,
+    max_hops: usize,
+}
+
+impl Converter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the maximum number of markets on a path, which is 3 by default.
+    pub fn max_hops(mut self, max_hops: usize) -> Self {
+        self.max_hops = max_hops;
+        self
+    }
+
+    /// Updates the latest price of the market of the price.
+    pub fn update(&mut self, price: Price) {
+        if self.prices.insert(price.market, price).is_none() {
+            for asset in &[price.market.base, price.market.quote] {
+                self.markets.entry(asset).or_default().push(price.market);
+            }
+        }
+    }
+
+    /// Updates the latest price with the close price of the candlestick.
+    pub fn update_candlestick(&mut self, candlestick: &Candlestick) {
+        self.update(candlestick.close);
+    }
+
+    pub fn price(&self, market: &'static Market) -> Option<Price> {
+        self.prices.get(market).copied()
+    }
+
+    /// Returns the quantity of `to` that is worth one unit of `from`,
+    /// using the best path of up to `max_hops` markets.
+    pub fn rate(&self, from: &'static Asset, to: &'static Asset) -> Option<Monetary> {
+        if from == to {
+            return Some(1.0);
+        }
+
+        let mut path = vec![from];
+        self.best_rate(&mut path, 1.0, to)
+    }
+
+    /// Depth first search over all paths that extend the given one without visiting an asset twice.
+    fn best_rate(
+        &self,
+        path: &mut Vec<&'static Asset>,
+        rate: Monetary,
+        to: &'static Asset,
+    ) -> Option<Monetary> {
+        if path.len() > self.max_hops {
+            return None;
+        }
+
+        let asset = *path.last().unwrap();
+        let mut best: Option<Monetary> = None;
+        for market in self.markets.get(asset).into_iter().flatten() {
+            let price = self.prices[market].price;
+            if price <= 0.0 {
+                continue;
+            }
+            let (neighbour, neighbour_rate) = if market.base == asset {
+                (market.quote, rate * price)
+            } else {
+                (market.base, rate / price)
+            };
+            if path.contains(&neighbour) {
+                continue;
+            }
+
+            let found = if neighbour == to {
+                Some(neighbour_rate)
+            } else {
+                path.push(neighbour);
+                let found = self.best_rate(path, neighbour_rate, to);
+                path.pop();
+                found
+            };
+            best = match (best, found) {
+                (Some(best), Some(found)) => Some(best.max(found)),
+                (best, found) => best.or(found),
+            };
+        }
+
+        best
+    }
+
+    /// Converts the quantity into the target asset.
+    pub fn convert(
+        &self,
+        quantity: Quantity,
+        to: &'static Asset,
+    ) -> Result<Quantity, ConversionError> {
+        let rate = self
+            .rate(quantity.asset, to)
+            .ok_or(ConversionError::NoPath(quantity.asset))?;
+
+        Ok(Quantity {
+            quantity: quantity.quantity * rate,
+            asset: to,
+        })
+    }
+
+    /// Returns the total value of all balances in the wallet in the target asset.
+    pub fn value(&self, wallet: &Wallet, to: &'static Asset) -> Result<Quantity, ConversionError> {
+        wallet
+            .balances()
+            .try_fold(Quantity::zero(to), |value, (_, balance)| {
+                Ok(value + self.convert(balance.total(), to)?)
+            })
+    }
+}
+
+impl Default for Converter {
+    fn default() -> Self {
+        Self {
+            prices: HashMap::new(),
+            markets: HashMap::new(),
+            max_hops: 3,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::market;
+
+    fn asset(name: &str) -> &'static Asset {
+        Asset::intern(name)
+    }
+
+    fn quantity(quantity: Monetary, asset: &str) -> Quantity {
+        Quantity {
+            quantity,
+            asset: self::asset(asset),
+        }
+    }
+
+    fn converter(prices: &[(&'static Market, Monetary)]) -> Converter {
+        let mut converter = Converter::new();
+        for &(market, price) in prices {
+            converter.update(Price { price, market });
+        }
+
+        converter
+    }
+
+    /// Returns a converter with the prices of ADA, BTC, ETH and USDT.
+    fn prices() -> Converter {
+        converter(&[
+            (market("ADA", "BTC"), 0.00001),
+            (market("BTC", "USDT"), 50_000.0),
+            (market("ETH", "BTC"), 0.05),
+            (market("ETH", "USDT"), 2_000.0),
+            // Selling ADA through ETH yields less than through BTC.
+            (market("ADA", "ETH"), 0.0002),
+        ])
+    }
+
+    #[test]
+    fn test_converter() {
+        let converter = prices();
+        let converted = converter
+            .convert(quantity(1000.0, "ADA"), asset("USDT"))
+            .unwrap();
+        assert!((converted.quantity - 500.0).abs() < 1e-9);
+        assert_eq!(
+            converter.rate(asset("USDT"), asset("ETH")),
+            Some(1.0 / 2_000.0)
+        );
+        assert_eq!(converter.rate(asset("USDT"), asset("USDT")), Some(1.0));
+        assert!(matches!(
+            converter.convert(quantity(1.0, "XMR"), asset("USDT")),
+            Err(ConversionError::NoPath(_))
+        ));
+    }
+
+    #[test]
+    fn test_converter_longer_path() {
+        // The direct market is worse than the path through BTC.
+        let converter = converter(&[
+            (market("ADA", "USDT"), 0.4),
+            (market("ADA", "BTC"), 0.00001),
+            (market("BTC", "USDT"), 50_000.0),
+        ]);
+        let rate = converter.rate(asset("ADA"), asset("USDT")).unwrap();
+        assert!((rate - 0.5).abs() < 1e-9);
+
+        let rate = converter
+            .clone()
+            .max_hops(1)
+            .rate(asset("ADA"), asset("USDT"))
+            .unwrap();
+        assert!((rate - 0.4).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_converter_max_hops() {
+        let converter = prices();
+        assert!(converter
+            .clone()
+            .max_hops(2)
+            .rate(asset("ADA"), asset("USDT"))
+            .is_some());
+        assert_eq!(
+            converter.max_hops(1).rate(asset("ADA"), asset("USDT")),
+            None
+        );
+    }
+
+    #[test]
+    fn test_converter_value() {
+        let mut wallet = Wallet::new();
+        wallet.deposit(quantity(1000.0, "ADA")).unwrap();
+        wallet.deposit(quantity(1.0, "ETH")).unwrap();
+        wallet.deposit(quantity(100.0, "USDT")).unwrap();
+        // ETH is worth more when sold through BTC than directly.
+        let value = prices().value(&wallet, asset("USDT")).unwrap();
+        assert!((value.quantity - 3_100.0).abs() < 1e-9);
+    }
+}
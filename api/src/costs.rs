@@ -0,0 +1,145 @@
+use crate::{Asset, Monetary, Price, Quantity};
+
+/// Whether a fill added liquidity to the order book or took it.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Liquidity {
+    Maker,
+    Taker,
+}
+
+/// Fees of an exchange as fractions of the value of a fill.
+///
+/// Fees are paid in the received asset, unless a discount asset is set
+/// and enough of it is held, which is then used at the discounted rate.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct FeeSchedule {
+    pub maker: Monetary,
+    pub taker: Monetary,
+    /// Asset that pays fees at a discount, with the discount as a fraction of the fee.
+    pub discount: Option<(&'static Asset, Monetary)>,
+}
+
+impl FeeSchedule {
+    pub fn new(maker: Monetary, taker: Monetary) -> Self {
+        Self {
+            maker,
+            taker,
+            discount: None,
+        }
+    }
+
+    /// Regular fees of Binance, which are reduced by a quarter when paid in BNB.
+    pub fn binance() -> Self {
+        Self::new(0.001, 0.001).discount(Asset::intern("BNB"), 0.25)
+    }
+
+    pub fn discount(mut self, asset: &'static Asset, discount: Monetary) -> Self {
+        self.discount = Some((asset, discount));
+        self
+    }
+
+    pub fn rate(&self, liquidity: Liquidity) -> Monetary {
+        match liquidity {
+            Liquidity::Maker => self.maker,
+            Liquidity::Taker => self.taker,
+        }
+    }
+}
+
+impl Default for FeeSchedule {
+    fn default() -> Self {
+        Self::new(0.0, 0.0)
+    }
+}
+
+/// Model of the price impact of orders that take liquidity.
+pub trait Slippage: Send + Sync {
+    /// Returns the basis points by which the price moves against a taker fill of the base quantity,
+    /// given the volume of the latest candlestick of the market if it is known.
+    fn bps(&self, quantity: Quantity, price: Price, volume: Option<Quantity>) -> Monetary;
+}
+
+/// Moves the price by the same basis points for every fill.
+#[derive(Debug, Copy, Clone)]
+pub struct FixedSlippage(pub Monetary);
+
+impl Slippage for FixedSlippage {
+    fn bps(&self, _quantity: Quantity, _price: Price, _volume: Option<Quantity>) -> Monetary {
+        self.0
+    }
+}
+
+/// Moves the price by the given basis points for fills as large as the volume of the candlestick
+/// and by the square root of their share of the volume for smaller ones.
+/// Without a known volume, every fill moves the price by the given basis points.
+#[derive(Debug, Copy, Clone)]
+pub struct VolumeSlippage(pub Monetary);
+
+impl Slippage for VolumeSlippage {
+    fn bps(&self, quantity: Quantity, _price: Price, volume: Option<Quantity>) -> Monetary {
+        match volume {
+            Some(volume) if volume.quantity > 0.0 => {
+                self.0 * (quantity.quantity / volume.quantity).sqrt()
+            }
+            _ => self.0,
+        }
+    }
+}
+
+/// Walks an order book that holds the same base quantity within every basis point from the price.
+///
+/// This approximates the order book by a constant depth, which does not follow the actual
+/// order book of the market.
+#[derive(Debug, Copy, Clone)]
+pub struct ConstantDepthSlippage(Quantity);
+
+impl ConstantDepthSlippage {
+    /// Returns `None` unless the depth per basis point is positive.
+    pub fn new(depth: Quantity) -> Option<Self> {
+        Some(Self(depth)).filter(|_| depth.quantity > 0.0)
+    }
+}
+
+impl Slippage for ConstantDepthSlippage {
+    fn bps(&self, quantity: Quantity, _price: Price, _volume: Option<Quantity>) -> Monetary {
+        quantity.quantity / self.0.quantity
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Market;
+
+    #[test]
+    fn test_costs() {
+        let market = Market::intern(Asset::intern("ETH"), Asset::intern("BTC"));
+        let eth = |quantity| Quantity {
+            quantity,
+            asset: market.base,
+        };
+        let price = Price {
+            price: 0.05,
+            market,
+        };
+
+        let fees = FeeSchedule::new(0.001, 0.002);
+        assert_eq!(fees.rate(Liquidity::Maker), 0.001);
+        assert_eq!(fees.rate(Liquidity::Taker), 0.002);
+        assert_eq!(
+            FeeSchedule::binance().discount,
+            Some((Asset::intern("BNB"), 0.25))
+        );
+
+        assert_eq!(FixedSlippage(5.0).bps(eth(1.0), price, None), 5.0);
+        assert_eq!(
+            VolumeSlippage(10.0).bps(eth(1.0), price, Some(eth(4.0))),
+            5.0
+        );
+        assert_eq!(VolumeSlippage(10.0).bps(eth(1.0), price, None), 10.0);
+        let depth = ConstantDepthSlippage::new(eth(2.0)).unwrap();
+        assert_eq!(depth.bps(eth(3.0), price, None), 1.5);
+        assert!(ConstantDepthSlippage::new(eth(0.0)).is_none());
+        assert!(ConstantDepthSlippage::new(eth(-1.0)).is_none());
+    }
+}
@@ -0,0 +1,433 @@
+use crate::{Candlestick, CandlestickError, Interval, Market, Price, Quantity, Timestamp};
+use chrono::{NaiveDateTime, TimeZone, Utc};
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::Path;
+
+#[derive(Debug)]
+pub enum CsvError {
+    Io(std::io::Error),
+    Csv(csv::Error),
+    /// A required column is not part of the layout.
+    MissingColumn(Column),
+    /// The value of a column could not be parsed, with the line it occurred on.
+    InvalidValue(u64, Column),
+    /// A parsed candlestick is not consistent, with the line it occurred on.
+    InvalidCandlestick(u64, CandlestickError),
+}
+
+impl From<std::io::Error> for CsvError {
+    fn from(error: std::io::Error) -> Self {
+        Self::Io(error)
+    }
+}
+
+impl From<csv::Error> for CsvError {
+    fn from(error: csv::Error) -> Self {
+        Self::Csv(error)
+    }
+}
+
+/// Field of a candlestick that is stored in a column of a CSV file.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Column {
+    OpenTime,
+    CloseTime,
+    Open,
+    High,
+    Low,
+    Close,
+    Volume,
+    QuoteVolume,
+    TakerBuyBaseVolume,
+    TakerBuyQuoteVolume,
+    Trades,
+    Closed,
+    /// Column that is skipped when reading and left empty when writing.
+    Ignore,
+}
+
+impl Column {
+    /// Returns the name of the column in the header.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Column::OpenTime => "open_time",
+            Column::CloseTime => "close_time",
+            Column::Open => "open",
+            Column::High => "high",
+            Column::Low => "low",
+            Column::Close => "close",
+            Column::Volume => "volume",
+            Column::QuoteVolume => "quote_volume",
+            Column::TakerBuyBaseVolume => "taker_buy_base_volume",
+            Column::TakerBuyQuoteVolume => "taker_buy_quote_volume",
+            Column::Trades => "trades",
+            Column::Closed => "closed",
+            Column::Ignore => "ignore",
+        }
+    }
+}
+
+/// Format of the open and close times when writing.
+/// Both formats are accepted when reading.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum TimeFormat {
+    /// Milliseconds since the unix epoch.
+    Millis,
+    /// Date and time in UTC, for example `2020-01-01 00:00:00.000`.
+    DateTime,
+}
+
+/// Column layout of CSV files of candlesticks.
+///
+/// When reading, the open time and the prices are required. The close time is derived
+/// from the interval if it is missing, missing volumes and trades are zero
+/// and candlesticks without a closed column are closed.
+#[derive(Clone, Debug)]
+pub struct CsvLayout {
+    columns: Vec<Column>,
+    header: bool,
+    delimiter: u8,
+    time_format: TimeFormat,
+}
+
+impl CsvLayout {
+    /// Creates a layout with the given columns, a header and comma separated values.
+    pub fn new(columns: Vec<Column>) -> Self {
+        Self {
+            columns,
+            header: true,
+            delimiter: b',',
+            time_format: TimeFormat::Millis,
+        }
+    }
+
+    /// Layout of the klines of Binance, which have no header.
+    pub fn binance() -> Self {
+        Self::new(vec![
+            Column::OpenTime,
+            Column::Open,
+            Column::High,
+            Column::Low,
+            Column::Close,
+            Column::Volume,
+            Column::CloseTime,
+            Column::QuoteVolume,
+            Column::Trades,
+            Column::TakerBuyBaseVolume,
+            Column::TakerBuyQuoteVolume,
+            Column::Ignore,
+        ])
+        .header(false)
+    }
+
+    /// Sets whether the first line holds the names of the columns.
+    pub fn header(mut self, header: bool) -> Self {
+        self.header = header;
+        self
+    }
+
+    pub fn delimiter(mut self, delimiter: u8) -> Self {
+        self.delimiter = delimiter;
+        self
+    }
+
+    pub fn time_format(mut self, time_format: TimeFormat) -> Self {
+        self.time_format = time_format;
+        self
+    }
+
+    pub fn columns(&self) -> &[Column] {
+        &self.columns
+    }
+
+    /// Reads the candlesticks of the market in the given interval.
+    pub fn read<R: Read>(
+        &self,
+        reader: R,
+        market: &'static Market,
+        interval: Interval,
+    ) -> Result<Vec<Candlestick>, CsvError> {
+        for &required in &[
+            Column::OpenTime,
+            Column::Open,
+            Column::High,
+            Column::Low,
+            Column::Close,
+        ] {
+            if !self.columns.contains(&required) {
+                return Err(CsvError::MissingColumn(required));
+            }
+        }
+
+        let mut reader = csv::ReaderBuilder::new()
+            .has_headers(self.header)
+            .delimiter(self.delimiter)
+            .flexible(true)
+            .from_reader(reader);
+
+        let mut candlesticks = Vec::new();
+        for record in reader.records() {
+            let record = record?;
+            let line = record.position().map_or(0, |position| position.line());
+            let candlestick = self.candlestick(&record, line, market, interval)?;
+            candlestick
+                .validate()
+                .map_err(|error| CsvError::InvalidCandlestick(line, error))?;
+            candlesticks.push(candlestick);
+        }
+
+        Ok(candlesticks)
+    }
+
+    /// Writes the candlesticks, preceded by the header if the layout has one.
+    pub fn write<W: Write>(&self, writer: W, candlesticks: &[Candlestick]) -> Result<(), CsvError> {
+        let mut writer = csv::WriterBuilder::new()
+            .delimiter(self.delimiter)
+            .from_writer(writer);
+
+        if self.header {
+            writer.write_record(self.columns.iter().map(Column::name))?;
+        }
+        for candlestick in candlesticks {
+            writer.write_record(
+                self.columns
+                    .iter()
+                    .map(|column| self.value(candlestick, *column)),
+            )?;
+        }
+        writer.flush()?;
+
+        Ok(())
+    }
+
+    pub fn load<P: AsRef<Path>>(
+        &self,
+        path: P,
+        market: &'static Market,
+        interval: Interval,
+    ) -> Result<Vec<Candlestick>, CsvError> {
+        self.read(File::open(path)?, market, interval)
+    }
+
+    pub fn save<P: AsRef<Path>>(
+        &self,
+        path: P,
+        candlesticks: &[Candlestick],
+    ) -> Result<(), CsvError> {
+        self.write(File::create(path)?, candlesticks)
+    }
+
+    fn candlestick(
+        &self,
+        record: &csv::StringRecord,
+        line: u64,
+        market: &'static Market,
+        interval: Interval,
+    ) -> Result<Candlestick, CsvError> {
+        let field = |column| {
+            self.columns
+                .iter()
+                .position(|&other| other == column)
+                .map(|index| record.get(index).unwrap_or("").trim())
+        };
+        let invalid = |column| CsvError::InvalidValue(line, column);
+        let time = |column| match field(column) {
+            Some(value) => parse_time(value).map(Some).ok_or_else(|| invalid(column)),
+            None => Ok(None),
+        };
+        let number = |column| match field(column) {
+            Some(value) => value.parse::<f64>().map(Some).map_err(|_| invalid(column)),
+            None => Ok(None),
+        };
+        let price = |column| -> Result<Price, CsvError> {
+            Ok(Price {
+                price: number(column)?.ok_or_else(|| invalid(column))?,
+                market,
+            })
+        };
+        let quantity = |column, asset| -> Result<Quantity, CsvError> {
+            Ok(Quantity {
+                quantity: number(column)?.unwrap_or(0.0),
+                asset,
+            })
+        };
+
+        let open_time = time(Column::OpenTime)?.ok_or_else(|| invalid(Column::OpenTime))?;
+
+        Ok(Candlestick {
+            market,
+            open_time,
+            close_time: time(Column::CloseTime)?.unwrap_or_else(|| interval.close_time(open_time)),
+            high: price(Column::High)?,
+            low: price(Column::Low)?,
+            open: price(Column::Open)?,
+            close: price(Column::Close)?,
+            volume: quantity(Column::Volume, market.base)?,
+            quote_volume: quantity(Column::QuoteVolume, market.quote)?,
+            taker_buy_base_volume: quantity(Column::TakerBuyBaseVolume, market.base)?,
+            taker_buy_quote_volume: quantity(Column::TakerBuyQuoteVolume, market.quote)?,
+            trades: match field(Column::Trades) {
+                Some(value) => value.parse().map_err(|_| invalid(Column::Trades))?,
+                None => 0,
+            },
+            closed: match field(Column::Closed) {
+                Some(value) => value.parse().map_err(|_| invalid(Column::Closed))?,
+                None => true,
+            },
+        })
+    }
+
+    fn value(&self, candlestick: &Candlestick, column: Column) -> String {
+        let time = |time: Timestamp| match self.time_format {
+            TimeFormat::Millis => time.millis().to_string(),
+            TimeFormat::DateTime => time.to_string(),
+        };
+
+        match column {
+            Column::OpenTime => time(candlestick.open_time),
+            Column::CloseTime => time(candlestick.close_time),
+            Column::Open => candlestick.open.price.to_string(),
+            Column::High => candlestick.high.price.to_string(),
+            Column::Low => candlestick.low.price.to_string(),
+            Column::Close => candlestick.close.price.to_string(),
+            Column::Volume => candlestick.volume.quantity.to_string(),
+            Column::QuoteVolume => candlestick.quote_volume.quantity.to_string(),
+            Column::TakerBuyBaseVolume => candlestick.taker_buy_base_volume.quantity.to_string(),
+            Column::TakerBuyQuoteVolume => candlestick.taker_buy_quote_volume.quantity.to_string(),
+            Column::Trades => candlestick.trades.to_string(),
+            Column::Closed => candlestick.closed.to_string(),
+            Column::Ignore => String::new(),
+        }
+    }
+}
+
+/// Contains all fields of a candlestick in the order of its struct, with a header.
+impl Default for CsvLayout {
+    fn default() -> Self {
+        Self::new(vec![
+            Column::OpenTime,
+            Column::CloseTime,
+            Column::Open,
+            Column::High,
+            Column::Low,
+            Column::Close,
+            Column::Volume,
+            Column::QuoteVolume,
+            Column::TakerBuyBaseVolume,
+            Column::TakerBuyQuoteVolume,
+            Column::Trades,
+            Column::Closed,
+        ])
+    }
+}
+
+/// Parses milliseconds since the unix epoch or a date and time in UTC.
+fn parse_time(value: &str) -> Option<Timestamp> {
+    if let Ok(millis) = value.parse() {
+        return Some(Timestamp::from_millis(millis));
+    }
+
+    ["%Y-%m-%d %H:%M:%S%.f", "%Y-%m-%dT%H:%M:%S%.f"]
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(value, format).ok())
+        .map(|datetime| Timestamp::from(Utc.from_utc_datetime(&datetime)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::ethbtc;
+
+    const BINANCE: &str =
+        "1577836800000,0.0181,0.0183,0.0180,0.0182,10.5,1577836859999,0.19,42,4.0,0.07,0\n\
+         1577836860000,0.0182,0.0184,0.0181,0.0183,2.0,1577836919999,0.04,7,1.0,0.02,0\n";
+
+    fn layout() -> CsvLayout {
+        CsvLayout::new(vec![
+            Column::OpenTime,
+            Column::Open,
+            Column::High,
+            Column::Low,
+            Column::Close,
+        ])
+        .delimiter(b';')
+    }
+
+    #[test]
+    fn test_csv_binance() {
+        let candlesticks = CsvLayout::binance()
+            .read(BINANCE.as_bytes(), ethbtc(), Interval::I1m)
+            .unwrap();
+        assert_eq!(candlesticks.len(), 2);
+        assert_eq!(
+            candlesticks[0].open_time,
+            Timestamp::from_millis(1577836800000)
+        );
+        assert_eq!(candlesticks[0].close.price, 0.0182);
+        assert_eq!(candlesticks[0].volume.quantity, 10.5);
+        assert_eq!(candlesticks[1].trades, 7);
+        assert!(candlesticks[1].closed);
+    }
+
+    #[test]
+    fn test_csv_round_trip() {
+        let candlesticks = CsvLayout::binance()
+            .read(BINANCE.as_bytes(), ethbtc(), Interval::I1m)
+            .unwrap();
+        let layout = CsvLayout::default().time_format(TimeFormat::DateTime);
+        let mut written = Vec::new();
+        layout.write(&mut written, &candlesticks).unwrap();
+        let written = String::from_utf8(written).unwrap();
+        assert!(written.starts_with("open_time,close_time,open,high,low,close,volume,"));
+        assert!(written.contains("2020-01-01 00:01:00.000,2020-01-01 00:01:59.999,0.0182"));
+
+        let read = layout
+            .read(written.as_bytes(), ethbtc(), Interval::I1m)
+            .unwrap();
+        assert_eq!(read.len(), 2);
+        assert_eq!(read[1].close_time, candlesticks[1].close_time);
+        assert_eq!(read[1].quote_volume, candlesticks[1].quote_volume);
+    }
+
+    #[test]
+    fn test_csv_derived_columns() {
+        // Missing close times and volumes are derived from the interval or zero.
+        let read = layout()
+            .read(
+                "open_time;open;high;low;close\n0;1;2;0.5;1.5\n".as_bytes(),
+                ethbtc(),
+                Interval::I1h,
+            )
+            .unwrap();
+        assert_eq!(read[0].close_time, Timestamp::from_millis(3_600_000 - 1));
+        assert_eq!(read[0].volume, Quantity::zero(ethbtc().base));
+    }
+
+    #[test]
+    fn test_csv_errors() {
+        assert!(matches!(
+            layout().read(
+                "open_time;open;high;low;close\n0;1;x;0.5;1.5\n".as_bytes(),
+                ethbtc(),
+                Interval::I1h
+            ),
+            Err(CsvError::InvalidValue(2, Column::High))
+        ));
+        assert!(matches!(
+            layout().read(
+                "open_time;open;high;low;close\n0;1;0.5;2;1.5\n".as_bytes(),
+                ethbtc(),
+                Interval::I1h
+            ),
+            Err(CsvError::InvalidCandlestick(
+                2,
+                CandlestickError::InvalidPrices
+            ))
+        ));
+        assert!(matches!(
+            CsvLayout::new(vec![Column::OpenTime]).read("".as_bytes(), ethbtc(), Interval::I1h),
+            Err(CsvError::MissingColumn(Column::Open))
+        ));
+    }
+}
@@ -0,0 +1,24 @@
+use crate::{Market, Price, Quantity, Timestamp};
+use futures_core::stream::BoxStream;
+use serde::{Deserialize, Serialize};
+
+/// Stream of snapshots of the order book of a market.
+pub type DepthStream = BoxStream<'static, Depth>;
+
+/// Quantity that is offered at a price of the order book.
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Level {
+    pub price: Price,
+    pub quantity: Quantity,
+}
+
+/// Snapshot of the best levels of the order book of a market.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Depth {
+    pub market: &'static Market,
+    pub time: Timestamp,
+    /// Buy orders, from the highest price down.
+    pub bids: Vec<Level>,
+    /// Sell orders, from the lowest price up.
+    pub asks: Vec<Level>,
+}
@@ -2,4 +2,15 @@
 pub enum Error {
     ConnectionError,
     DatabaseError,
+    /// The API does not provide the requested data.
+    Unsupported,
+}
+
+/// Error of arithmetic between quantities or prices that do not fit together.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum ArithmeticError {
+    /// The quantities are measured in different assets.
+    WrongAsset,
+    /// The prices belong to different markets.
+    WrongMarket,
 }
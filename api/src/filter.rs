@@ -0,0 +1,238 @@
+use crate::{Filter, Market, Monetary, Order, Price, Quantity, Side};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// Rule of a market that an order violates.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum FilterError {
+    /// The base quantity lies outside of the allowed range.
+    Quantity,
+    /// A price lies outside of the allowed range.
+    Price,
+    /// The value of the order in the quote asset is below the minimum.
+    Notional,
+}
+
+/// Trading rules of the markets of an exchange, which orders have to satisfy.
+#[derive(Debug, Clone, Default)]
+pub struct Filters {
+    filters: HashMap<&'static Market, Vec<Arc<dyn Filter>>>,
+}
+
+impl Filters {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Replaces the rules of the market, for example with the rules reported by the exchange.
+    pub fn set(&mut self, market: &'static Market, filters: Vec<Arc<dyn Filter>>) {
+        self.filters.insert(market, filters);
+    }
+
+    /// Applies all rules of the market to the order,
+    /// which may adjust the order or reject it if it cannot be placed.
+    pub fn apply(&self, market: &'static Market, order: Order) -> Result<Order, FilterError> {
+        self.filters
+            .get(market)
+            .into_iter()
+            .flatten()
+            .try_fold(order, |order, filter| filter.apply(order))
+    }
+}
+
+/// Limits the base quantity of orders to a range and rounds it down to a multiple of the step.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct LotSize {
+    pub min: Monetary,
+    pub max: Monetary,
+    pub step: Monetary,
+}
+
+impl Filter for LotSize {
+    fn apply(&self, order: Order) -> Result<Order, FilterError> {
+        let quantity = |quantity: Quantity| {
+            let rounded = round_down(quantity.quantity, self.step);
+            if rounded < self.min || rounded > self.max {
+                return Err(FilterError::Quantity);
+            }
+
+            Ok(Quantity {
+                quantity: rounded,
+                asset: quantity.asset,
+            })
+        };
+
+        Ok(match order {
+            Order::Limit(side, base, price) => Order::Limit(side, quantity(base)?, price),
+            Order::Stop(side, base, price) => Order::Stop(side, quantity(base)?, price),
+            Order::Oco(side, base, stop, limit) => Order::Oco(side, quantity(base)?, stop, limit),
+        })
+    }
+}
+
+/// Limits the prices of orders to a range and rounds them to a multiple of the tick size,
+/// down for buy and up for sell orders so that the order never gets a worse price.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct PriceFilter {
+    pub min: Monetary,
+    pub max: Monetary,
+    pub tick: Monetary,
+}
+
+impl Filter for PriceFilter {
+    fn apply(&self, order: Order) -> Result<Order, FilterError> {
+        let price = |side: Side, price: Price| {
+            let rounded = match side {
+                Side::Buy => round_down(price.price, self.tick),
+                Side::Sell => round_up(price.price, self.tick),
+            };
+            if rounded < self.min || rounded > self.max {
+                return Err(FilterError::Price);
+            }
+
+            Ok(Price {
+                price: rounded,
+                market: price.market,
+            })
+        };
+
+        Ok(match order {
+            Order::Limit(side, base, limit) => Order::Limit(side, base, price(side, limit)?),
+            Order::Stop(side, base, stop) => Order::Stop(side, base, price(side, stop)?),
+            Order::Oco(side, base, stop, limit) => {
+                Order::Oco(side, base, price(side, stop)?, price(side, limit)?)
+            }
+        })
+    }
+}
+
+/// Rejects orders whose value in the quote asset is below the minimum.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct MinNotional {
+    pub min: Monetary,
+}
+
+impl Filter for MinNotional {
+    fn apply(&self, order: Order) -> Result<Order, FilterError> {
+        let notional = match &order {
+            Order::Limit(_, base, price) | Order::Stop(_, base, price) => {
+                base.quantity * price.price
+            }
+            Order::Oco(_, base, stop, limit) => base.quantity * stop.price.min(limit.price),
+        };
+
+        if notional < self.min {
+            Err(FilterError::Notional)
+        } else {
+            Ok(order)
+        }
+    }
+}
+
+/// Rounds down to a multiple of the step, where a step of zero disables rounding.
+/// Values that are a multiple of the step up to floating point errors are kept.
+fn round_down(value: Monetary, step: Monetary) -> Monetary {
+    if step <= 0.0 {
+        return value;
+    }
+
+    (value / step + 1e-9).floor() * step
+}
+
+fn round_up(value: Monetary, step: Monetary) -> Monetary {
+    if step <= 0.0 {
+        return value;
+    }
+
+    (value / step - 1e-9).ceil() * step
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{eth, ethbtc, market, price};
+
+    fn quantity(order: Order) -> Monetary {
+        match order {
+            Order::Limit(_, base, _) | Order::Stop(_, base, _) | Order::Oco(_, base, _, _) => {
+                base.quantity
+            }
+        }
+    }
+
+    #[test]
+    fn test_lot_size() {
+        let filter = LotSize {
+            min: 0.1,
+            max: 10.0,
+            step: 0.01,
+        };
+        let order = |quantity| Order::Limit(Side::Buy, eth(quantity), price(1.0));
+        assert!((quantity(filter.apply(order(1.239)).unwrap()) - 1.23).abs() < 1e-12);
+        assert!((quantity(filter.apply(order(0.3)).unwrap()) - 0.3).abs() < 1e-12);
+        assert_eq!(filter.apply(order(0.05)).err(), Some(FilterError::Quantity));
+        assert_eq!(filter.apply(order(11.0)).err(), Some(FilterError::Quantity));
+    }
+
+    #[test]
+    fn test_price_filter() {
+        let filter = PriceFilter {
+            min: 0.01,
+            max: 100.0,
+            tick: 0.01,
+        };
+        match filter.apply(Order::Limit(Side::Buy, eth(1.0), price(1.237))) {
+            Ok(Order::Limit(_, _, limit)) => assert!((limit.price - 1.23).abs() < 1e-12),
+            order => panic!("unexpected {:?}", order),
+        }
+        match filter.apply(Order::Limit(Side::Sell, eth(1.0), price(1.231))) {
+            Ok(Order::Limit(_, _, limit)) => assert!((limit.price - 1.24).abs() < 1e-12),
+            order => panic!("unexpected {:?}", order),
+        }
+        assert_eq!(
+            filter
+                .apply(Order::Limit(Side::Buy, eth(1.0), price(200.0)))
+                .err(),
+            Some(FilterError::Price)
+        );
+    }
+
+    #[test]
+    fn test_min_notional() {
+        let filter = MinNotional { min: 0.001 };
+        assert!(filter
+            .apply(Order::Limit(Side::Buy, eth(0.1), price(0.05)))
+            .is_ok());
+        assert_eq!(
+            filter
+                .apply(Order::Oco(Side::Sell, eth(0.1), price(0.005), price(0.05)))
+                .err(),
+            Some(FilterError::Notional)
+        );
+    }
+
+    #[test]
+    fn test_filters() {
+        let mut filters = Filters::new();
+        let order = || Order::Limit(Side::Buy, eth(0.05), price(1.0));
+        assert!(filters.apply(ethbtc(), order()).is_ok());
+
+        filters.set(
+            ethbtc(),
+            vec![
+                Arc::new(LotSize {
+                    min: 0.1,
+                    max: 10.0,
+                    step: 0.01,
+                }),
+                Arc::new(MinNotional { min: 0.001 }),
+            ],
+        );
+        assert_eq!(
+            filters.apply(ethbtc(), order()).err(),
+            Some(FilterError::Quantity)
+        );
+        // Rules only apply to their own market.
+        assert!(filters.apply(market("ETH", "USDT"), order()).is_ok());
+    }
+}
@@ -0,0 +1,91 @@
+//! Fixtures shared by the tests of all modules.
+//!
+//! Candlesticks that differ from the default one are built with the struct update syntax,
+//! for example `Candlestick { closed: false, ..candlestick(ethbtc(), 0) }`.
+
+use crate::{Asset, Candlestick, Market, Price, Quantity, Timestamp, Trade};
+
+pub fn market(base: &str, quote: &str) -> &'static Market {
+    Market::intern(Asset::intern(base), Asset::intern(quote))
+}
+
+/// Market of the tests that need only one.
+pub fn ethbtc() -> &'static Market {
+    market("ETH", "BTC")
+}
+
+/// Returns a price of ETHBTC.
+pub fn price(price: f64) -> Price {
+    Price {
+        price,
+        market: ethbtc(),
+    }
+}
+
+pub fn eth(quantity: f64) -> Quantity {
+    Quantity {
+        quantity,
+        asset: Asset::intern("ETH"),
+    }
+}
+
+pub fn btc(quantity: f64) -> Quantity {
+    Quantity {
+        quantity,
+        asset: Asset::intern("BTC"),
+    }
+}
+
+/// Returns the time at the start of the minute.
+pub fn minute(minute: u64) -> Timestamp {
+    Timestamp::from_millis(minute * 60_000)
+}
+
+/// Returns a closed one minute candlestick that opens at the minute,
+/// with all prices at one and one unit of both assets traded by a single seller.
+pub fn candlestick(market: &'static Market, minute: u64) -> Candlestick {
+    ohlc(market, minute, [1.0, 1.0, 1.0, 1.0])
+}
+
+/// Returns a closed one minute candlestick that opens at the minute with the given
+/// open, high, low and close prices and one unit of both assets traded by a single seller.
+pub fn ohlc(
+    market: &'static Market,
+    minute: u64,
+    [open, high, low, close]: [f64; 4],
+) -> Candlestick {
+    let price = |price| Price { price, market };
+    let quantity = |quantity, asset| Quantity { quantity, asset };
+
+    Candlestick {
+        market,
+        open_time: self::minute(minute),
+        close_time: self::minute(minute + 1) - chrono::Duration::milliseconds(1),
+        high: price(high),
+        low: price(low),
+        open: price(open),
+        close: price(close),
+        volume: quantity(1.0, market.base),
+        quote_volume: quantity(1.0, market.quote),
+        taker_buy_base_volume: quantity(0.0, market.base),
+        taker_buy_quote_volume: quantity(0.0, market.quote),
+        trades: 1,
+        closed: true,
+    }
+}
+
+/// Returns a trade of the base quantity at the price and time in milliseconds,
+/// in which the buyer took liquidity.
+pub fn trade(market: &'static Market, id: u64, time: u64, price: f64, quantity: f64) -> Trade {
+    Trade {
+        market,
+        id,
+        time: Timestamp::from_millis(time),
+        price: Price { price, market },
+        quantity: Quantity {
+            quantity,
+            asset: market.base,
+        },
+        buyer_maker: false,
+    }
+}
@@ -0,0 +1,96 @@
+use crate::{Candlestick, Price, Transform};
+
+/// Transforms candlesticks into Heikin-Ashi candlesticks.
+/// A candlestick with the same open time as the previous one is treated as an update and replaces it.
+#[derive(Debug, Clone, Default)]
+pub struct HeikinAshi {
+    /// Heikin-Ashi candlestick preceding the latest one.
+    previous: Option<Candlestick>,
+    /// Latest Heikin-Ashi candlestick.
+    latest: Option<Candlestick>,
+}
+
+impl HeikinAshi {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl Transform for HeikinAshi {
+    fn push(&mut self, candlestick: Candlestick) -> Vec<Candlestick> {
+        if let Some(latest) = self.latest {
+            if candlestick.open_time < latest.open_time {
+                return Vec::new();
+            }
+            if candlestick.open_time > latest.open_time {
+                self.previous = Some(latest);
+            }
+        }
+
+        let market = candlestick.market;
+        let close = (candlestick.open.price
+            + candlestick.high.price
+            + candlestick.low.price
+            + candlestick.close.price)
+            / 4.0;
+        let open = match self.previous {
+            Some(previous) => (previous.open.price + previous.close.price) / 2.0,
+            None => (candlestick.open.price + candlestick.close.price) / 2.0,
+        };
+
+        let heikin_ashi = Candlestick {
+            high: Price {
+                price: candlestick.high.price.max(open).max(close),
+                market,
+            },
+            low: Price {
+                price: candlestick.low.price.min(open).min(close),
+                market,
+            },
+            open: Price {
+                price: open,
+                market,
+            },
+            close: Price {
+                price: close,
+                market,
+            },
+            ..candlestick
+        };
+        self.latest = Some(heikin_ashi);
+
+        vec![heikin_ashi]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{ethbtc, ohlc};
+
+    fn heikin_ashi() -> Vec<Candlestick> {
+        let candlesticks = vec![
+            ohlc(ethbtc(), 0, [10.0, 14.0, 8.0, 12.0]),
+            ohlc(ethbtc(), 1, [12.0, 16.0, 12.0, 16.0]),
+        ];
+
+        crate::transform(candlesticks, HeikinAshi::new())
+    }
+
+    #[test]
+    fn test_heikin_ashi_first() {
+        // The first candlestick opens at the midpoint of its own open and close.
+        let heikin_ashi = heikin_ashi();
+        assert_eq!(heikin_ashi[0].open.price, 11.0);
+        assert_eq!(heikin_ashi[0].close.price, 11.0);
+    }
+
+    #[test]
+    fn test_heikin_ashi() {
+        let heikin_ashi = heikin_ashi();
+        assert_eq!(heikin_ashi[1].open.price, 11.0);
+        assert_eq!(heikin_ashi[1].close.price, 14.0);
+        assert_eq!(heikin_ashi[1].high.price, 16.0);
+        assert_eq!(heikin_ashi[1].low.price, 11.0);
+    }
+}
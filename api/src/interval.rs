@@ -1,9 +1,89 @@
+use crate::Timestamp;
+use chrono::Duration;
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
 use std::fmt;
+use std::str::FromStr;
 
-#[derive(Copy, Clone, Debug)]
+/// Offset of the weekly interval, since Binance aligns weeks on Mondays
+/// but the unix epoch started on a Thursday.
+const WEEK_OFFSET: u64 = 4 * 24 * 60 * 60 * 1000;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
 pub enum Interval {
     I1m,
+    I3m,
     I5m,
+    I15m,
+    I30m,
+    I1h,
+    I2h,
+    I4h,
+    I6h,
+    I8h,
+    I12h,
+    I1d,
+    I3d,
+    I1w,
+}
+
+impl Interval {
+    /// Returns the duration of the interval.
+    pub fn duration(&self) -> Duration {
+        Duration::milliseconds(self.millis() as i64)
+    }
+
+    /// Returns the duration of the interval in milliseconds.
+    fn millis(&self) -> u64 {
+        const MINUTE: u64 = 60 * 1000;
+        const HOUR: u64 = 60 * MINUTE;
+        const DAY: u64 = 24 * HOUR;
+
+        match self {
+            Interval::I1m => MINUTE,
+            Interval::I3m => 3 * MINUTE,
+            Interval::I5m => 5 * MINUTE,
+            Interval::I15m => 15 * MINUTE,
+            Interval::I30m => 30 * MINUTE,
+            Interval::I1h => HOUR,
+            Interval::I2h => 2 * HOUR,
+            Interval::I4h => 4 * HOUR,
+            Interval::I6h => 6 * HOUR,
+            Interval::I8h => 8 * HOUR,
+            Interval::I12h => 12 * HOUR,
+            Interval::I1d => DAY,
+            Interval::I3d => 3 * DAY,
+            Interval::I1w => 7 * DAY,
+        }
+    }
+
+    /// Returns the open time of the interval that contains `time`.
+    /// Intervals that open before the unix epoch, like the first week, open at the epoch instead.
+    pub fn open_time(&self, time: Timestamp) -> Timestamp {
+        Timestamp::from_millis(self.open_millis(time).max(0) as u64)
+    }
+
+    /// Returns the close time of the interval that contains `time`.
+    pub fn close_time(&self, time: Timestamp) -> Timestamp {
+        Timestamp::from_millis((self.open_millis(time) + self.millis() as i64 - 1) as u64)
+    }
+
+    /// Returns the open time of the interval that contains `time` in milliseconds,
+    /// which is negative if the interval opens before the unix epoch.
+    fn open_millis(&self, time: Timestamp) -> i64 {
+        let offset = match self {
+            Interval::I1w => WEEK_OFFSET,
+            _ => 0,
+        };
+        let time = time.millis() as i64;
+
+        time - (time - offset as i64).rem_euclid(self.millis() as i64)
+    }
+
+    /// Returns true if candlesticks of this interval can be aggregated into `other`.
+    pub fn divides(&self, other: Interval) -> bool {
+        other.millis().is_multiple_of(self.millis())
+            && (other != Interval::I1w || WEEK_OFFSET.is_multiple_of(self.millis()))
+    }
 }
 
 impl fmt::Display for Interval {
@@ -13,8 +93,92 @@ impl fmt::Display for Interval {
             "{}",
             match self {
                 Interval::I1m => "1m",
+                Interval::I3m => "3m",
                 Interval::I5m => "5m",
+                Interval::I15m => "15m",
+                Interval::I30m => "30m",
+                Interval::I1h => "1h",
+                Interval::I2h => "2h",
+                Interval::I4h => "4h",
+                Interval::I6h => "6h",
+                Interval::I8h => "8h",
+                Interval::I12h => "12h",
+                Interval::I1d => "1d",
+                Interval::I3d => "3d",
+                Interval::I1w => "1w",
             }
         )
     }
 }
+
+impl FromStr for Interval {
+    type Err = ();
+
+    fn from_str(string: &str) -> Result<Self, Self::Err> {
+        Ok(match string {
+            "1m" => Interval::I1m,
+            "3m" => Interval::I3m,
+            "5m" => Interval::I5m,
+            "15m" => Interval::I15m,
+            "30m" => Interval::I30m,
+            "1h" => Interval::I1h,
+            "2h" => Interval::I2h,
+            "4h" => Interval::I4h,
+            "6h" => Interval::I6h,
+            "8h" => Interval::I8h,
+            "12h" => Interval::I12h,
+            "1d" => Interval::I1d,
+            "3d" => Interval::I3d,
+            "1w" => Interval::I1w,
+            _ => return Err(()),
+        })
+    }
+}
+
+/// Intervals are represented like they are displayed, for example `5m`.
+impl Serialize for Interval {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Interval {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let string = String::deserialize(deserializer)?;
+        string.parse().map_err(|_| {
+            de::Error::invalid_value(de::Unexpected::Str(&string), &"an interval like 5m")
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_open_time() {
+        const DAY: u64 = 24 * 60 * 60 * 1000;
+        let time = Timestamp::from_millis(2 * DAY + 5);
+
+        assert_eq!(Interval::I1d.open_time(time).millis(), 2 * DAY);
+        assert_eq!(Interval::I3d.open_time(time).millis(), 0);
+        // The first days of the epoch belong to a week that opened before it.
+        assert_eq!(Interval::I1w.open_time(time).millis(), 0);
+        assert_eq!(
+            Interval::I1w.open_time(Timestamp::from_millis(0)).millis(),
+            0
+        );
+        assert_eq!(
+            Interval::I1w
+                .open_time(Timestamp::from_millis(10 * DAY))
+                .millis(),
+            4 * DAY
+        );
+        assert_eq!(
+            Interval::I1w
+                .close_time(Timestamp::from_millis(DAY))
+                .millis(),
+            4 * DAY - 1
+        );
+    }
+}
@@ -0,0 +1,486 @@
+use crate::{
+    check_position, Api, Asset, Candlestick, DepthStream, Error, Filters, Interval, Market, Order,
+    OrderError, OrderResponse, OrderState, PositionError, PositionResponse, Price, Quantity, Side,
+    Storage, Subscription, Timestamp, TradeStream,
+};
+use futures_core::stream::Stream;
+use rand::distributions::Alphanumeric;
+use rand::Rng;
+use std::collections::HashSet;
+use std::sync::Arc;
+
+/// Length of the generated client ids, short enough for the limits of the exchanges.
+const CLIENT_ID_LENGTH: usize = 24;
+
+/// Failure to record the outcome of an order that the exchange already accepted.
+#[derive(Debug)]
+pub struct JournalError {
+    /// Id of the stored order whose outcome is missing.
+    pub order_id: i64,
+    pub error: Error,
+}
+
+/// Records every order of the wrapped API with its outcome in the storage.
+///
+/// Orders are stored as new before they are sent, so an order is never placed without being recorded.
+/// Once the exchange accepted an order, its exchange id and the fills of the response are stored,
+/// so later updates can be recorded with `Orders::apply`.
+/// Fully executed orders are marked as filled, partially executed ones as partially filled
+/// and orders that the exchange refused as rejected.
+/// Orders that failed otherwise, for example because of a connection error, stay new,
+/// as the exchange may still have received them.
+///
+/// The response of an accepted order is returned even if its outcome could not be stored,
+/// the failure is kept until it is taken with `take_errors`.
+/// Positions are stored with their entry and exit orders.
+pub struct Journaled<API, S>
+where
+    API: Api<S> + Send + Sync,
+    S: Stream<Item = Candlestick> + Unpin + Send + 'static,
+{
+    api: API,
+    storage: Arc<Storage>,
+    errors: Vec<JournalError>,
+    _phantom: std::marker::PhantomData<fn() -> S>,
+}
+
+impl<API, S> Journaled<API, S>
+where
+    API: Api<S> + Send + Sync,
+    S: Stream<Item = Candlestick> + Unpin + Send + 'static,
+{
+    pub fn new(api: API, storage: Arc<Storage>) -> Self {
+        Self {
+            api,
+            storage,
+            errors: Vec::new(),
+            _phantom: std::marker::PhantomData,
+        }
+    }
+
+    pub fn storage(&self) -> &Arc<Storage> {
+        &self.storage
+    }
+
+    /// Returns the failures to store the outcome of accepted orders since the last call.
+    pub fn take_errors(&mut self) -> Vec<JournalError> {
+        std::mem::take(&mut self.errors)
+    }
+
+    /// Returns a random client id, so ids of orders stored by different instances
+    /// or processes in the same storage do not collide.
+    fn client_id() -> String {
+        rand::thread_rng()
+            .sample_iter(&Alphanumeric)
+            .take(CLIENT_ID_LENGTH)
+            .collect()
+    }
+
+    fn report(&mut self, order_id: i64, result: Result<(), Error>) {
+        if let Err(error) = result {
+            self.errors.push(JournalError { order_id, error });
+        }
+    }
+
+    /// Stores and sends the order and returns its id in the storage with the response.
+    async fn journal(&mut self, order: Order) -> Result<(i64, OrderResponse), OrderError> {
+        let quantity = match order {
+            Order::Limit(_, quantity, _)
+            | Order::Stop(_, quantity, _)
+            | Order::Oco(_, quantity, _, _) => quantity,
+        };
+        let id = self
+            .storage
+            .orders()
+            .insert(&Self::client_id(), &order, Timestamp::now())
+            .map_err(OrderError::Other)?;
+
+        match self.api.order(order).await {
+            Ok(response) => {
+                let result = self.accepted(id, quantity, &response);
+                self.report(id, result);
+                Ok((id, response))
+            }
+            Err(error) => {
+                let refused = match error {
+                    OrderError::InsufficientFunds
+                    | OrderError::Invalid
+                    | OrderError::UnknownOrder => true,
+                    OrderError::Other(_) => false,
+                };
+                if refused {
+                    let result = self.storage.orders().transition(
+                        id,
+                        OrderState::Rejected,
+                        Quantity::zero(quantity.asset),
+                        Timestamp::now(),
+                    );
+                    self.report(id, result);
+                }
+                Err(error)
+            }
+        }
+    }
+
+    /// Stores the exchange id, the fills and the state of an accepted order.
+    fn accepted(&self, id: i64, quantity: Quantity, response: &OrderResponse) -> Result<(), Error> {
+        let orders = self.storage.orders();
+        orders.set_exchange_id(id, &response.id)?;
+        for fill in &response.fills {
+            self.storage.fills().insert(id, fill)?;
+        }
+        let executed = response.executed_quantity;
+        if executed.quantity >= quantity.quantity {
+            orders.transition(id, OrderState::Filled, executed, response.time)?;
+        } else if executed.quantity > 0.0 {
+            orders.transition(id, OrderState::PartiallyFilled, executed, response.time)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[async_trait::async_trait]
+impl<API, S> Api<S> for Journaled<API, S>
+where
+    API: Api<S> + Send + Sync,
+    S: Stream<Item = Candlestick> + Unpin + Send + 'static,
+{
+    const NAME: &'static str = API::NAME;
+
+    async fn update(&mut self) -> Result<(), Error> {
+        self.api.update().await
+    }
+
+    fn get_markets(&self) -> &HashSet<&'static Market> {
+        self.api.get_markets()
+    }
+
+    fn get_assets(&self) -> &HashSet<&'static Asset> {
+        self.api.get_assets()
+    }
+
+    fn filters(&self) -> Filters {
+        self.api.filters()
+    }
+
+    async fn subscribe(&self, market: &'static Market, interval: Interval) -> Subscription<S> {
+        self.api.subscribe(market, interval).await
+    }
+
+    async fn history(
+        &self,
+        market: &'static Market,
+        interval: Interval,
+        start: Timestamp,
+        end: Timestamp,
+    ) -> Result<Vec<Candlestick>, Error> {
+        self.api.history(market, interval, start, end).await
+    }
+
+    async fn subscribe_trades(&self, market: &'static Market) -> Result<TradeStream, Error> {
+        self.api.subscribe_trades(market).await
+    }
+
+    async fn subscribe_depth(&self, market: &'static Market) -> Result<DepthStream, Error> {
+        self.api.subscribe_depth(market).await
+    }
+
+    async fn order(&mut self, order: Order) -> Result<OrderResponse, OrderError> {
+        self.journal(order).await.map(|(_, response)| response)
+    }
+
+    async fn enter_position(
+        &mut self,
+        side: Side,
+        enter_quantity: Quantity,
+        enter_price: Price,
+        take_profit: Price,
+        stop_loss: Price,
+    ) -> Result<PositionResponse, PositionError> {
+        check_position(side, enter_quantity, enter_price, take_profit, stop_loss)?;
+
+        let (entry_id, entering_response) = self
+            .journal(Order::Limit(side, enter_quantity, enter_price))
+            .await?;
+        let position =
+            self.storage
+                .positions()
+                .open(entry_id, take_profit, stop_loss, entering_response.time);
+        let position = match position {
+            Ok(position) => Some(position),
+            Err(error) => {
+                self.report(entry_id, Err(error));
+                None
+            }
+        };
+
+        let executed = entering_response.executed_quantity;
+        let (exit_id, leaving_response) = self
+            .journal(Order::Oco(side.reverse(), executed, stop_loss, take_profit))
+            .await?;
+        if let Some(position) = position {
+            let positions = self.storage.positions();
+            let mut result = positions.leave(position, exit_id);
+            if result.is_ok() && leaving_response.executed_quantity.quantity >= executed.quantity {
+                result = positions.close_by_exit(exit_id, leaving_response.time);
+            }
+            self.report(exit_id, result);
+        }
+
+        Ok(PositionResponse::from((
+            entering_response,
+            leaving_response,
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Fill, OrderUpdate};
+    use futures::stream::{self, BoxStream, StreamExt};
+
+    struct Mock {
+        markets: HashSet<&'static Market>,
+        assets: HashSet<&'static Asset>,
+        orders: u64,
+    }
+
+    #[async_trait::async_trait]
+    impl Api<BoxStream<'static, Candlestick>> for Mock {
+        async fn update(&mut self) -> Result<(), Error> {
+            Ok(())
+        }
+
+        fn get_markets(&self) -> &HashSet<&'static Market> {
+            &self.markets
+        }
+
+        fn get_assets(&self) -> &HashSet<&'static Asset> {
+            &self.assets
+        }
+
+        async fn subscribe(
+            &self,
+            market: &'static Market,
+            interval: Interval,
+        ) -> Subscription<BoxStream<'static, Candlestick>> {
+            Subscription::new(market, interval, stream::empty().boxed())
+        }
+
+        async fn history(
+            &self,
+            _market: &'static Market,
+            _interval: Interval,
+            _start: Timestamp,
+            _end: Timestamp,
+        ) -> Result<Vec<Candlestick>, Error> {
+            Ok(vec![])
+        }
+
+        /// Executes half of buy orders and rests OCO orders,
+        /// loses the connection for sell orders and refuses stop orders.
+        async fn order(&mut self, order: Order) -> Result<OrderResponse, OrderError> {
+            self.orders += 1;
+            let time = Timestamp::from_millis(self.orders);
+            match order {
+                Order::Limit(Side::Buy, quantity, price) => Ok(OrderResponse {
+                    id: self.orders.to_string(),
+                    executed_quantity: quantity * 0.5,
+                    fills: vec![Fill {
+                        price,
+                        quantity: quantity * 0.5,
+                        commission: Quantity::zero(quantity.asset),
+                        time,
+                    }],
+                    time,
+                }),
+                Order::Oco(_, quantity, _, _) => Ok(OrderResponse {
+                    id: self.orders.to_string(),
+                    executed_quantity: Quantity::zero(quantity.asset),
+                    fills: vec![],
+                    time,
+                }),
+                Order::Limit(Side::Sell, _, _) => Err(OrderError::Other(Error::ConnectionError)),
+                Order::Stop(_, _, _) => Err(OrderError::Invalid),
+            }
+        }
+    }
+
+    fn journaled() -> (
+        Journaled<Mock, BoxStream<'static, Candlestick>>,
+        Arc<Storage>,
+    ) {
+        let storage = Arc::new(Storage::in_memory().unwrap());
+        let api = Journaled::new(
+            Mock {
+                markets: HashSet::new(),
+                assets: HashSet::new(),
+                orders: 0,
+            },
+            Arc::clone(&storage),
+        );
+
+        (api, storage)
+    }
+
+    fn eth(quantity: f64) -> Quantity {
+        Quantity {
+            quantity,
+            asset: Asset::intern("ETH"),
+        }
+    }
+
+    fn price(price: f64) -> Price {
+        Price {
+            price,
+            market: Market::intern(Asset::intern("ETH"), Asset::intern("BTC")),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_journaled_accepted_order() {
+        let (mut api, storage) = journaled();
+
+        let response = api
+            .order(Order::Limit(Side::Buy, eth(2.0), price(0.05)))
+            .await
+            .unwrap();
+        assert_eq!(response.executed_quantity, eth(1.0));
+
+        let buy = storage.orders().get(1).unwrap().unwrap();
+        assert_eq!(buy.state, OrderState::PartiallyFilled);
+        assert_eq!(buy.exchange_id.as_deref(), Some("1"));
+        assert_eq!(buy.executed_quantity, eth(1.0));
+        assert_eq!(
+            storage.orders().transitions(1).unwrap()[1],
+            (OrderState::PartiallyFilled, Timestamp::from_millis(1))
+        );
+        assert_eq!(storage.fills().of_order(1).unwrap().len(), 1);
+        assert!(api.take_errors().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_journaled_client_ids() {
+        let (mut first, storage) = journaled();
+        let mut second = Journaled::new(
+            Mock {
+                markets: HashSet::new(),
+                assets: HashSet::new(),
+                orders: 0,
+            },
+            Arc::clone(&storage),
+        );
+
+        // Both instances store orders in the same storage at the same time.
+        for _ in 0..3 {
+            for api in &mut [&mut first, &mut second] {
+                api.order(Order::Limit(Side::Buy, eth(2.0), price(0.05)))
+                    .await
+                    .unwrap();
+            }
+        }
+
+        let ids = (1..=6)
+            .map(|id| storage.orders().get(id).unwrap().unwrap().client_id)
+            .collect::<HashSet<_>>();
+        assert_eq!(ids.len(), 6);
+        assert!(ids.iter().all(|id| id.len() == CLIENT_ID_LENGTH));
+        assert!(first.take_errors().is_empty());
+        assert!(second.take_errors().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_journaled_refused_order() {
+        let (mut api, storage) = journaled();
+
+        assert!(matches!(
+            api.order(Order::Stop(Side::Sell, eth(2.0), price(0.04)))
+                .await,
+            Err(OrderError::Invalid)
+        ));
+        let stop = storage.orders().get(1).unwrap().unwrap();
+        assert_eq!(stop.state, OrderState::Rejected);
+    }
+
+    #[tokio::test]
+    async fn test_journaled_failed_order() {
+        let (mut api, storage) = journaled();
+
+        // The exchange may have received the order, so it is not marked as rejected.
+        assert!(matches!(
+            api.order(Order::Limit(Side::Sell, eth(2.0), price(0.05)))
+                .await,
+            Err(OrderError::Other(Error::ConnectionError))
+        ));
+        let sell = storage.orders().get(1).unwrap().unwrap();
+        assert_eq!(sell.state, OrderState::New);
+        assert_eq!(storage.orders().transitions(1).unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_journaled_storage_failure() {
+        let (mut api, storage) = journaled();
+        storage
+            .connection()
+            .execute_batch("DROP TABLE fills")
+            .unwrap();
+
+        // The order is live, so its response is returned although its fills are lost.
+        let response = api
+            .order(Order::Limit(Side::Buy, eth(2.0), price(0.05)))
+            .await
+            .unwrap();
+        assert_eq!(response.id, "1");
+
+        let errors = api.take_errors();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].order_id, 1);
+        assert!(api.take_errors().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_journaled_position() {
+        let (mut api, storage) = journaled();
+
+        let response = api
+            .enter_position(Side::Buy, eth(2.0), price(0.05), price(0.06), price(0.04))
+            .await
+            .unwrap();
+        assert_eq!(response.executed_quantity, eth(1.0));
+
+        let position = storage.positions().get(1).unwrap().unwrap();
+        assert_eq!(position.entry_order_id, 1);
+        assert_eq!(position.exit_order_id, Some(2));
+        assert_eq!(position.take_profit, price(0.06));
+        assert_eq!(position.stop_loss, price(0.04));
+        assert_eq!(position.closed_time, None);
+        let exit = storage.orders().get(2).unwrap().unwrap();
+        assert_eq!(exit.side, Side::Sell);
+        assert_eq!(exit.quantity, eth(1.0));
+
+        // The position is closed once its exit order is reported as filled.
+        let update = OrderUpdate {
+            exchange_id: String::from("2"),
+            state: OrderState::Filled,
+            executed_quantity: eth(1.0),
+            fill: None,
+            time: Timestamp::from_millis(3),
+        };
+        assert_eq!(storage.orders().apply(&update).unwrap(), Some(2));
+        let position = storage.positions().get(1).unwrap().unwrap();
+        assert_eq!(position.closed_time, Some(Timestamp::from_millis(3)));
+    }
+
+    #[tokio::test]
+    async fn test_journaled_trades() {
+        let (api, _) = journaled();
+        let market = Market::intern(Asset::intern("ETH"), Asset::intern("BTC"));
+
+        assert!(matches!(
+            api.subscribe_trades(market).await,
+            Err(Error::Unsupported)
+        ));
+    }
+}
@@ -1,34 +1,89 @@
 mod asset;
+mod backfill;
+mod bar;
+mod broadcast;
 mod candlestick;
+mod closed;
+mod columnar;
+mod converter;
+mod costs;
+mod csv_layout;
+mod depth;
 mod error;
+mod filter;
+#[cfg(test)]
+mod fixtures;
+mod heikin_ashi;
 mod interval;
+mod journaled;
+mod managed;
 mod market;
+mod matching;
+mod merge;
 mod price;
+mod quality;
 mod quantity;
+mod range_bars;
+mod recorder;
+mod renko;
+mod repository;
+mod resample;
 mod simulated;
+mod storage;
 mod subscription;
+mod timestamp;
+mod trade;
+mod transform;
+mod wallet;
 
 pub use asset::*;
+pub use backfill::*;
+pub use bar::*;
+pub use broadcast::*;
 pub use candlestick::*;
+pub use closed::*;
+pub use columnar::*;
+pub use converter::*;
+pub use costs::*;
+pub use csv_layout::*;
+pub use depth::*;
 pub use error::*;
+pub use filter::*;
+pub use heikin_ashi::*;
 pub use interval::*;
+pub use journaled::*;
+pub use managed::*;
 pub use market::*;
+pub use matching::*;
+pub use merge::*;
 pub use price::*;
+pub use quality::*;
 pub use quantity::*;
+pub use range_bars::*;
+pub use recorder::*;
+pub use renko::*;
+pub use repository::*;
+pub use resample::*;
 pub use simulated::*;
+pub use storage::*;
 pub use subscription::*;
+pub use timestamp::*;
+pub use trade::*;
+pub use transform::*;
+pub use wallet::*;
 
 use futures_core::stream::Stream;
+use serde::{Deserialize, Serialize};
 use std::collections::HashSet;
 use std::fmt::Debug;
 
 pub type Monetary = f64;
 
 pub trait Filter: Debug + Send + Sync {
-    fn apply(&self, order: Order) -> Result<Order, ()>;
+    fn apply(&self, order: Order) -> Result<Order, FilterError>;
 }
 
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
 pub enum Side {
     Buy,
     Sell,
@@ -37,16 +92,19 @@ pub enum Side {
 impl Side {
     pub fn reverse(&self) -> Self {
         match self {
-            Buy => Self::Sell,
-            Sell => Self::Buy,
+            Side::Buy => Self::Sell,
+            Side::Sell => Self::Buy,
         }
     }
 }
 
+#[derive(Debug, Serialize, Deserialize)]
 pub enum Order {
     Limit(Side, Quantity, Price),
     //StopLoss(&'static Market, Quantity),
     //TakeProfit(&'static Market, Quantity),
+    /// Market order that is placed once the price reaches the stop price.
+    Stop(Side, Quantity, Price),
     Oco(Side, Quantity, Price, Price),
 }
 
@@ -111,11 +169,26 @@ impl From<OrderError> for PositionError {
 }
 */
 
+#[derive(Debug)]
 pub struct OrderResponse {
+    /// Id that the exchange assigned to the order.
+    pub id: String,
     pub executed_quantity: Quantity,
+    /// Executions of the order while it was placed.
+    pub fills: Vec<Fill>,
+    /// Time at which the exchange processed the order.
+    pub time: Timestamp,
 }
 
+#[derive(Debug)]
 pub enum OrderError {
+    /// The free balance does not cover the order.
+    InsufficientFunds,
+    /// The order cannot be placed as given, for example because it would trigger immediately
+    /// or its prices belong to different markets.
+    Invalid,
+    /// No open order has the given id.
+    UnknownOrder,
     Other(Error),
 }
 
@@ -131,10 +204,12 @@ impl From<(OrderResponse, OrderResponse)> for PositionResponse {
     }
 }
 
+#[derive(Debug)]
 pub enum PositionError {
     DifferentMarkets,
     PriceRestrictions,
     WrongAsset,
+    Order(OrderError),
     Other(Error),
 }
 
@@ -148,10 +223,43 @@ impl From<OrderError> for PositionError {
     fn from(error: OrderError) -> Self {
         match error {
             OrderError::Other(error) => Self::Other(error),
+            error => Self::Order(error),
         }
     }
 }
 
+/// Checks that the prices and the quantity of a position to enter fit together.
+pub(crate) fn check_position(
+    side: Side,
+    enter_quantity: Quantity,
+    enter_price: Price,
+    take_profit: Price,
+    stop_loss: Price,
+) -> Result<(), PositionError> {
+    // Check if the same markets were chosen.
+    if !(take_profit.market == enter_price.market && stop_loss.market == enter_price.market) {
+        return Err(PositionError::DifferentMarkets);
+    }
+
+    // Check if the price restrictions apply.
+    if !match side {
+        Side::Buy => take_profit > enter_price && enter_price > stop_loss,
+        Side::Sell => take_profit < enter_price && enter_price < stop_loss,
+    } {
+        return Err(PositionError::PriceRestrictions);
+    }
+
+    // Check if the correct asset was chosen.
+    if !match side {
+        Side::Buy => enter_quantity.asset == enter_price.market.base,
+        Side::Sell => enter_quantity.asset == enter_price.market.quote,
+    } {
+        return Err(PositionError::WrongAsset);
+    }
+
+    Ok(())
+}
+
 #[async_trait::async_trait]
 pub trait Api<S>
 where
@@ -169,8 +277,36 @@ where
     fn get_assets(&self) -> &HashSet<&'static Asset>;
 
     /// Subscribe to market events.
+    /// Every update of a forming candlestick is passed on,
+    /// use `Subscription::closed` to only receive closed candlesticks.
     async fn subscribe(&self, market: &'static Market, interval: Interval) -> Subscription<S>;
 
+    /// Returns the candlesticks with open times between `start` and `end`, both inclusive.
+    async fn history(
+        &self,
+        market: &'static Market,
+        interval: Interval,
+        start: Timestamp,
+        end: Timestamp,
+    ) -> Result<Vec<Candlestick>, Error>;
+
+    /// Subscribe to the trades of the market, for example to build bars with `Bars`.
+    /// APIs without a trade stream return `Error::Unsupported`.
+    async fn subscribe_trades(&self, _market: &'static Market) -> Result<TradeStream, Error> {
+        Err(Error::Unsupported)
+    }
+
+    /// Returns the trading rules of the markets, which are empty for APIs without rules.
+    fn filters(&self) -> Filters {
+        Filters::default()
+    }
+
+    /// Subscribe to snapshots of the best levels of the order book of the market.
+    /// APIs without an order book stream return `Error::Unsupported`.
+    async fn subscribe_depth(&self, _market: &'static Market) -> Result<DepthStream, Error> {
+        Err(Error::Unsupported)
+    }
+
     //async fn next(&mut self, market: &Market) -> Candlestick;
     //async fn get_markets<'a>(&mut self) -> Vec<Market<'a>>;
     //async fn get_previous_candlesticks<'a>(&mut self, market: &Market<'a>) -> Vec<Candlestick<'a>>;
@@ -190,26 +326,7 @@ where
         take_profit: Price,
         stop_loss: Price,
     ) -> Result<PositionResponse, PositionError> {
-        // Check if the same markets were chosen.
-        if !(take_profit.market == enter_price.market && stop_loss.market == enter_price.market) {
-            return Err(PositionError::DifferentMarkets);
-        }
-
-        // Check if the price restrictions apply.
-        if !match side {
-            Side::Buy => take_profit > enter_price && enter_price > stop_loss,
-            Side::Sell => take_profit < enter_price && enter_price < stop_loss,
-        } {
-            return Err(PositionError::PriceRestrictions);
-        }
-
-        // Check if the correct asset was chosen.
-        if !match side {
-            Side::Buy => enter_quantity.asset == enter_price.market.base,
-            Side::Sell => enter_quantity.asset == enter_price.market.quote,
-        } {
-            return Err(PositionError::WrongAsset);
-        }
+        check_position(side, enter_quantity, enter_price, take_profit, stop_loss)?;
 
         let reverse = side.reverse();
 
@@ -0,0 +1,347 @@
+use crate::{Candlestick, Timestamp};
+use chrono::Duration;
+use futures_core::{
+    stream::Stream,
+    task::{Context, Poll, Waker},
+};
+use std::collections::VecDeque;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use tokio::stream::StreamExt;
+use tokio::sync::Notify;
+
+/// Decides what happens to new candlesticks when the consumer falls behind.
+#[derive(Debug, Copy, Clone)]
+pub enum Backpressure {
+    /// Stops reading from the upstream subscription until the consumer catches up.
+    Block,
+    /// Drops the oldest buffered candlestick to make room for the new one.
+    DropOldest,
+    /// Replaces a buffered version of the same candlestick with its update,
+    /// otherwise blocks like `Block`.
+    Coalesce,
+}
+
+struct State {
+    buffer: VecDeque<Candlestick>,
+    waker: Option<Waker>,
+    paused: bool,
+    closed: bool,
+    /// True once the upstream subscription ended.
+    done: bool,
+    dropped: u64,
+}
+
+impl State {
+    fn wake(&mut self) {
+        if let Some(waker) = self.waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+struct Shared {
+    state: Mutex<State>,
+    /// Notifies the producer that there is space in the buffer or that the subscription was closed.
+    notify: Notify,
+}
+
+/// Controls a managed subscription and reports its metrics.
+#[derive(Clone)]
+pub struct SubscriptionHandle {
+    shared: Arc<Shared>,
+}
+
+impl SubscriptionHandle {
+    /// Stops passing on candlesticks to the consumer, while new ones are still buffered.
+    pub fn pause(&self) {
+        self.shared.state.lock().unwrap().paused = true;
+    }
+
+    /// Continues passing on candlesticks to the consumer.
+    pub fn resume(&self) {
+        let mut state = self.shared.state.lock().unwrap();
+        state.paused = false;
+        state.wake();
+    }
+
+    /// Closes the upstream subscription and ends the stream of the consumer.
+    pub fn close(&self) {
+        close(&self.shared);
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.shared.state.lock().unwrap().paused
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.shared.state.lock().unwrap().closed
+    }
+
+    /// Returns the number of candlesticks that wait for the consumer.
+    pub fn buffered(&self) -> usize {
+        self.shared.state.lock().unwrap().buffer.len()
+    }
+
+    /// Returns the number of candlesticks that were dropped or coalesced.
+    pub fn dropped(&self) -> u64 {
+        self.shared.state.lock().unwrap().dropped
+    }
+
+    /// Returns how far the consumer is behind the exchange,
+    /// measured from the close time of the oldest candlestick that waits for the consumer until now.
+    /// Forming candlesticks close in the future and do not add to the lag.
+    pub fn lag(&self) -> Duration {
+        self.lag_at(Timestamp::now())
+    }
+
+    fn lag_at(&self, now: Timestamp) -> Duration {
+        let state = self.shared.state.lock().unwrap();
+        match state.buffer.front() {
+            Some(oldest) => (now - oldest.close_time).max(Duration::zero()),
+            None => Duration::zero(),
+        }
+    }
+}
+
+fn close(shared: &Shared) {
+    let mut state = shared.state.lock().unwrap();
+    state.closed = true;
+    state.buffer.clear();
+    state.wake();
+    shared.notify.notify();
+}
+
+/// Candlestick stream of a managed subscription.
+/// A background task reads the upstream subscription into a bounded buffer.
+pub struct Managed {
+    shared: Arc<Shared>,
+}
+
+impl Managed {
+    pub fn new<S>(
+        mut stream: S,
+        capacity: usize,
+        backpressure: Backpressure,
+    ) -> (Self, SubscriptionHandle)
+    where
+        S: Stream<Item = Candlestick> + Unpin + Send + 'static,
+    {
+        let shared = Arc::new(Shared {
+            state: Mutex::new(State {
+                buffer: VecDeque::with_capacity(capacity),
+                waker: None,
+                paused: false,
+                closed: false,
+                done: false,
+                dropped: 0,
+            }),
+            notify: Notify::new(),
+        });
+        let capacity = capacity.max(1);
+
+        let producer = Arc::clone(&shared);
+        tokio::spawn(async move {
+            'receive: loop {
+                if producer.state.lock().unwrap().closed {
+                    break;
+                }
+
+                let candlestick = tokio::select! {
+                    candlestick = stream.next() => match candlestick {
+                        Some(candlestick) => candlestick,
+                        None => {
+                            let mut state = producer.state.lock().unwrap();
+                            state.done = true;
+                            state.wake();
+                            break;
+                        }
+                    },
+                    _ = producer.notify.notified() => continue,
+                };
+
+                loop {
+                    {
+                        let mut state = producer.state.lock().unwrap();
+                        if state.closed {
+                            break 'receive;
+                        }
+
+                        if let Backpressure::Coalesce = backpressure {
+                            if let Some(buffered) = state
+                                .buffer
+                                .iter_mut()
+                                .rev()
+                                .find(|buffered| buffered.open_time == candlestick.open_time)
+                            {
+                                *buffered = candlestick;
+                                state.dropped += 1;
+                                break;
+                            }
+                        }
+
+                        if state.buffer.len() < capacity {
+                            state.buffer.push_back(candlestick);
+                            state.wake();
+                            break;
+                        }
+
+                        if let Backpressure::DropOldest = backpressure {
+                            state.buffer.pop_front();
+                            state.buffer.push_back(candlestick);
+                            state.dropped += 1;
+                            state.wake();
+                            break;
+                        }
+                    }
+
+                    producer.notify.notified().await;
+                }
+            }
+        });
+
+        (
+            Self {
+                shared: Arc::clone(&shared),
+            },
+            SubscriptionHandle { shared },
+        )
+    }
+}
+
+impl Stream for Managed {
+    type Item = Candlestick;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut state = self.shared.state.lock().unwrap();
+
+        if state.closed {
+            return Poll::Ready(None);
+        }
+
+        if !state.paused {
+            if let Some(candlestick) = state.buffer.pop_front() {
+                self.shared.notify.notify();
+                return Poll::Ready(Some(candlestick));
+            }
+
+            if state.done {
+                return Poll::Ready(None);
+            }
+        }
+
+        state.waker = Some(cx.waker().clone());
+        Poll::Pending
+    }
+}
+
+impl Drop for Managed {
+    fn drop(&mut self) {
+        close(&self.shared);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{candlestick, ethbtc, minute};
+    use futures::stream;
+
+    fn candlesticks() -> Vec<Candlestick> {
+        (0..5).map(|minute| candlestick(ethbtc(), minute)).collect()
+    }
+
+    fn open_times(candlesticks: Vec<Candlestick>) -> Vec<Timestamp> {
+        candlesticks
+            .iter()
+            .map(|candlestick| candlestick.open_time)
+            .collect()
+    }
+
+    /// Lets the producer run until the condition holds.
+    async fn until(handle: &SubscriptionHandle, condition: impl Fn(&SubscriptionHandle) -> bool) {
+        while !condition(handle) {
+            let () = tokio::task::yield_now().await;
+        }
+    }
+
+    #[tokio::test]
+    async fn test_managed_drop_oldest() {
+        let (mut managed, handle) =
+            Managed::new(stream::iter(candlesticks()), 2, Backpressure::DropOldest);
+        handle.pause();
+        until(&handle, |handle| handle.dropped() == 3).await;
+        assert_eq!(handle.buffered(), 2);
+
+        handle.resume();
+        assert_eq!(managed.next().await.unwrap().open_time, minute(3));
+        assert_eq!(managed.next().await.unwrap().open_time, minute(4));
+        assert!(managed.next().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_managed_block() {
+        let (managed, handle) = Managed::new(stream::iter(candlesticks()), 2, Backpressure::Block);
+        handle.pause();
+        until(&handle, |handle| handle.buffered() == 2).await;
+        let () = tokio::task::yield_now().await;
+        assert_eq!(handle.buffered(), 2);
+        assert_eq!(handle.dropped(), 0);
+
+        handle.resume();
+        let received: Vec<Candlestick> = managed.collect().await;
+        assert_eq!(open_times(received), open_times(candlesticks()));
+    }
+
+    #[tokio::test]
+    async fn test_managed_coalesce() {
+        let forming = Candlestick {
+            closed: false,
+            ..candlestick(ethbtc(), 0)
+        };
+        let upstream = vec![forming, candlestick(ethbtc(), 0), candlestick(ethbtc(), 1)];
+        let (managed, handle) = Managed::new(stream::iter(upstream), 2, Backpressure::Coalesce);
+        handle.pause();
+        until(&handle, |handle| handle.buffered() == 2).await;
+        assert_eq!(handle.dropped(), 1);
+
+        handle.resume();
+        let received: Vec<Candlestick> = managed.collect().await;
+        assert_eq!(open_times(received.clone()), vec![minute(0), minute(1)]);
+        assert!(received[0].closed);
+    }
+
+    #[tokio::test]
+    async fn test_managed_close() {
+        let (mut managed, handle) =
+            Managed::new(stream::iter(candlesticks()), 2, Backpressure::Block);
+        assert_eq!(managed.next().await.unwrap().open_time, minute(0));
+        handle.close();
+        assert!(handle.is_closed());
+        assert_eq!(handle.buffered(), 0);
+        assert!(managed.next().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_managed_lag() {
+        let (mut managed, handle) =
+            Managed::new(stream::iter(candlesticks()), 2, Backpressure::DropOldest);
+        handle.pause();
+        assert_eq!(handle.lag_at(minute(10)), Duration::zero());
+        until(&handle, |handle| handle.dropped() == 3).await;
+
+        // The oldest waiting candlestick closed just before the fourth minute ended.
+        assert_eq!(
+            handle.lag_at(minute(10)),
+            Duration::minutes(6) + Duration::milliseconds(1)
+        );
+        assert_eq!(handle.lag_at(minute(3)), Duration::zero());
+
+        handle.resume();
+        managed.next().await.unwrap();
+        assert_eq!(
+            handle.lag_at(minute(10)),
+            Duration::minutes(5) + Duration::milliseconds(1)
+        );
+    }
+}
@@ -1,12 +1,71 @@
-use crate::{Asset, Filter};
+use crate::Asset;
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use std::collections::HashMap;
 use std::fmt;
 use std::hash::{Hash, Hasher};
+use std::sync::Mutex;
+
+/// Base and quote asset of a market.
+type Key = (&'static Asset, &'static Asset);
+
+/// All markets that were interned.
+#[derive(Default)]
+struct Registry {
+    /// Markets by their base and quote asset.
+    markets: HashMap<Key, &'static Market>,
+    /// Markets by their symbol, which several markets may share.
+    symbols: HashMap<String, Vec<&'static Market>>,
+}
+
+lazy_static::lazy_static! {
+    static ref MARKETS: Mutex<Registry> = Mutex::new(Registry::default());
+}
+
+/// Error of looking up a registered market by its symbol.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum SymbolError {
+    /// No registered market has the symbol.
+    Unknown,
+    /// Several registered markets have the symbol, for example `ABC` if both
+    /// `AB`/`C` and `A`/`BC` are registered.
+    Ambiguous,
+}
 
 #[derive(Debug)]
 pub struct Market {
     pub base: &'static Asset,
     pub quote: &'static Asset,
-    filters: Vec<Box<dyn Filter>>,
+}
+
+impl Market {
+    /// Returns the registered market of the assets, which is registered first if it is new.
+    pub fn intern(base: &'static Asset, quote: &'static Asset) -> &'static Market {
+        let mut registry = MARKETS.lock().unwrap();
+        if let Some(market) = registry.markets.get(&(base, quote)) {
+            return market;
+        }
+
+        let market: &'static Market = Box::leak(Box::new(Market::from((base, quote))));
+        registry.markets.insert((base, quote), market);
+        registry
+            .symbols
+            .entry(market.to_string())
+            .or_default()
+            .push(market);
+
+        market
+    }
+
+    /// Returns the registered market with the given symbol, like `ETHBTC`.
+    pub fn from_symbol(symbol: &str) -> Result<&'static Market, SymbolError> {
+        let registry = MARKETS.lock().unwrap();
+
+        match registry.symbols.get(symbol).map(Vec::as_slice) {
+            Some([market]) => Ok(market),
+            Some([_, _, ..]) => Err(SymbolError::Ambiguous),
+            _ => Err(SymbolError::Unknown),
+        }
+    }
 }
 
 impl fmt::Display for Market {
@@ -32,10 +91,51 @@ impl Hash for Market {
 
 impl From<(&'static Asset, &'static Asset)> for Market {
     fn from((base, quote): (&'static Asset, &'static Asset)) -> Market {
-        Market {
-            base,
-            quote,
-            filters: Vec::new(),
-        }
+        Market { base, quote }
+    }
+}
+
+/// Markets are represented by their symbol.
+impl Serialize for Market {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+/// Only registered markets can be deserialized, since the symbol does not tell
+/// where the base asset ends and the quote asset begins.
+impl<'de> Deserialize<'de> for &'static Market {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let symbol = String::deserialize(deserializer)?;
+        Market::from_symbol(&symbol).map_err(|error| match error {
+            SymbolError::Unknown => {
+                de::Error::invalid_value(de::Unexpected::Str(&symbol), &"a registered market")
+            }
+            SymbolError::Ambiguous => {
+                de::Error::invalid_value(de::Unexpected::Str(&symbol), &"an unambiguous market")
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_intern_ambiguous_symbol() {
+        let ab = Market::intern(Asset::intern("INTERNAB"), Asset::intern("C"));
+        assert_eq!(Market::from_symbol("INTERNABC"), Ok(ab));
+
+        let a = Market::intern(Asset::intern("INTERNA"), Asset::intern("BC"));
+        assert_ne!(ab, a);
+        assert_eq!(ab.base.to_string(), "INTERNAB");
+        assert_eq!(a.base.to_string(), "INTERNA");
+        assert_eq!(
+            Market::from_symbol("INTERNABC"),
+            Err(SymbolError::Ambiguous)
+        );
+        assert!(serde_json::from_str::<&'static Market>("\"INTERNABC\"").is_err());
+        assert_eq!(Market::from_symbol("INTERNXYZ"), Err(SymbolError::Unknown));
     }
 }
@@ -0,0 +1,989 @@
+use crate::{
+    Asset, Candlestick, FeeSchedule, Fill, FixedSlippage, Liquidity, Market, Monetary, Order,
+    OrderError, OrderResponse, OrderState, OrderUpdate, Price, Quantity, Side, Slippage, Timestamp,
+    Trade, Wallet, WalletError,
+};
+use chrono::Duration;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+/// Assumption about the path of the price within a candlestick, as it only tells the extremes.
+///
+/// The price is assumed to move from the open to the low and then to the high for rising candlesticks,
+/// and to the high and then to the low for falling ones, before it closes.
+/// Resting orders are filled in the order in which the path reaches their prices.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum FillAssumption {
+    /// Follows the path, touching the limit price fills a limit order.
+    Path,
+    /// Follows the path, but if both legs of an OCO order are reached within a candlestick,
+    /// the limit order is filled.
+    Optimistic,
+    /// Follows the path, but if both legs of an OCO order are reached within a candlestick,
+    /// the stop order is triggered.
+    /// Limit orders are only filled if the price moves beyond the limit price.
+    Pessimistic,
+}
+
+#[derive(Debug, Copy, Clone)]
+enum Trigger {
+    Limit(Price),
+    Stop(Price),
+    Oco {
+        stop: Price,
+        limit: Price,
+    },
+    /// Stop order that was triggered and keeps filling at the price of the following market data.
+    Market,
+}
+
+#[derive(Debug, Copy, Clone)]
+enum Leg {
+    Limit,
+    Stop,
+}
+
+#[derive(Debug, Clone)]
+struct Resting {
+    id: u64,
+    side: Side,
+    market: &'static Market,
+    quantity: Quantity,
+    /// Quantity of the order in the base asset of the market.
+    base: Quantity,
+    /// Executed quantity of the order in the base asset of the market.
+    executed: Quantity,
+    /// Quantity that rests ahead of the order at its limit price.
+    queue: Quantity,
+    trigger: Trigger,
+    /// Quantity that is locked in the wallet while the order is open.
+    locked: Quantity,
+    /// Time at which the order reaches the exchange, `None` once it arrived.
+    pending: Option<Timestamp>,
+}
+
+/// Order book of a simulated exchange that fills resting orders against market data.
+///
+/// Limit orders that are marketable when placed are filled immediately at the latest price,
+/// all others rest until the price reaches them. Stop orders are filled at the stop price,
+/// or at the open if the price gapped beyond it.
+/// Order quantities in the quote asset are converted at the limit or stop price.
+/// The wallet locks the funds of resting orders and orders that cannot be covered
+/// once they are filled expire.
+///
+/// Filled orders pay the maker or taker fee of the fee schedule,
+/// and fills that take liquidity are moved against the order by the slippage model,
+/// though limit orders never fill beyond their limit price.
+/// With a latency, orders only take part in matching once market data of their arrival time is pushed.
+/// Arriving limit orders that are marketable are filled at the first price of that data,
+/// while stop and OCO orders that would trigger immediately are rejected.
+///
+/// With a participation, every fill only takes a fraction of the volume that trades at the price
+/// of the order, so orders are filled partially across several candlesticks or trades.
+/// Resting limit orders only get the share of the volume of a candlestick that corresponds to
+/// the share of its range beyond their limit price, after the queue ahead of them traded.
+/// Once a leg of an OCO order is partially filled, the other leg is canceled,
+/// and triggered stop orders keep filling at the first price of the following market data.
+pub struct MatchingEngine {
+    wallet: Wallet,
+    assumption: FillAssumption,
+    fees: FeeSchedule,
+    slippage: Box<dyn Slippage>,
+    /// Time between the decision to place an order and its arrival at the exchange.
+    latency: Duration,
+    /// Fraction of the volume that a single fill may take, unlimited if `None`.
+    participation: Option<Monetary>,
+    /// Multiple of the volume of the latest candlestick that rests ahead of new limit orders.
+    queue: Monetary,
+    orders: Vec<Resting>,
+    next_id: u64,
+    /// Latest candlestick of every market, used to only match the movement since.
+    candlesticks: HashMap<&'static Market, Candlestick>,
+    prices: HashMap<&'static Market, f64>,
+    /// Time of the latest market data.
+    time: Option<Timestamp>,
+}
+
+impl MatchingEngine {
+    pub fn new(wallet: Wallet) -> Self {
+        Self {
+            wallet,
+            assumption: FillAssumption::Path,
+            fees: FeeSchedule::default(),
+            slippage: Box::new(FixedSlippage(0.0)),
+            latency: Duration::zero(),
+            participation: None,
+            queue: 0.0,
+            orders: Vec::new(),
+            next_id: 1,
+            candlesticks: HashMap::new(),
+            prices: HashMap::new(),
+            time: None,
+        }
+    }
+
+    pub fn assumption(mut self, assumption: FillAssumption) -> Self {
+        self.assumption = assumption;
+        self
+    }
+
+    pub fn fees(mut self, fees: FeeSchedule) -> Self {
+        self.fees = fees;
+        self
+    }
+
+    pub fn slippage<T>(mut self, slippage: T) -> Self
+    where
+        T: Slippage + 'static,
+    {
+        self.slippage = Box::new(slippage);
+        self
+    }
+
+    pub fn latency(mut self, latency: Duration) -> Self {
+        self.latency = latency;
+        self
+    }
+
+    pub fn participation(mut self, participation: Monetary) -> Self {
+        self.participation = Some(participation);
+        self
+    }
+
+    /// Assumes that the given multiple of the volume of the latest candlestick rests ahead
+    /// of new limit orders at their price, which only applies together with a participation.
+    pub fn queue(mut self, queue: Monetary) -> Self {
+        self.queue = queue;
+        self
+    }
+
+    pub fn wallet(&self) -> &Wallet {
+        &self.wallet
+    }
+
+    /// Returns the ids of all resting orders.
+    pub fn open_orders(&self) -> Vec<String> {
+        self.orders
+            .iter()
+            .map(|order| order.id.to_string())
+            .collect()
+    }
+
+    /// Places an order, which is either filled immediately or rests in the order book.
+    /// With a latency, the order is never filled immediately, but pending until it arrives.
+    pub fn place(&mut self, order: Order) -> Result<OrderResponse, OrderError> {
+        // Buy orders lock their funds at the highest price they may be filled at.
+        let (side, quantity, trigger, conversion, lock) = match order {
+            Order::Limit(side, quantity, price) => {
+                (side, quantity, Trigger::Limit(price), price, price)
+            }
+            Order::Stop(side, quantity, stop) => (side, quantity, Trigger::Stop(stop), stop, stop),
+            Order::Oco(side, quantity, stop, limit) => {
+                if stop.market != limit.market {
+                    return Err(OrderError::Invalid);
+                }
+                let lock = if stop > limit { stop } else { limit };
+                (side, quantity, Trigger::Oco { stop, limit }, limit, lock)
+            }
+        };
+        let market = conversion.market;
+        let base = if quantity.asset == market.base {
+            quantity
+        } else {
+            quantity
+                .checked_div(conversion)
+                .map_err(|_| OrderError::Invalid)?
+        };
+        if base.quantity <= 0.0 {
+            return Err(OrderError::Invalid);
+        }
+
+        let mut order = Resting {
+            id: self.next_id,
+            side,
+            market,
+            quantity,
+            base,
+            executed: Quantity::zero(base.asset),
+            queue: Quantity::zero(base.asset),
+            trigger,
+            locked: match side {
+                Side::Buy => base * lock,
+                Side::Sell => base,
+            },
+            pending: None,
+        };
+        let time = self.now();
+        let price = match self.prices.get(market) {
+            Some(&price) if self.latency <= Duration::zero() => Some(price),
+            _ => None,
+        };
+
+        if let Some(price) = price {
+            let limit = matches!(trigger, Trigger::Limit(_));
+            if !limit && self.triggered(&order, &[price]).is_some() {
+                return Err(OrderError::Invalid);
+            }
+        }
+        self.wallet
+            .lock(order.locked)
+            .map_err(|_| OrderError::InsufficientFunds)?;
+        if self.latency > Duration::zero() {
+            order.pending = Some(time + self.latency);
+        } else {
+            order.queue = self.queue_ahead(market);
+        }
+
+        let mut fills = Vec::new();
+        let marketable = price.filter(|&price| self.reach(&order, Leg::Limit, &[price]).is_some());
+        if let Some(price) = marketable {
+            let volume = self
+                .candlesticks
+                .get(market)
+                .map(|candlestick| candlestick.volume.quantity);
+            let quantity = self.fillable(&mut order, Liquidity::Taker, volume, &[price]);
+            if let Some(update) = self.fill(&mut order, quantity, price, Liquidity::Taker, time) {
+                if update.state == OrderState::Expired {
+                    return Err(OrderError::InsufficientFunds);
+                }
+                fills.extend(update.fill);
+            }
+        }
+
+        self.next_id += 1;
+        let response = OrderResponse {
+            id: order.id.to_string(),
+            executed_quantity: executed(&order),
+            fills,
+            time,
+        };
+        if order.executed.quantity < order.base.quantity {
+            self.orders.push(order);
+        }
+
+        Ok(response)
+    }
+
+    /// Cancels a resting order and releases its locked funds.
+    pub fn cancel(&mut self, id: &str) -> Result<OrderUpdate, OrderError> {
+        let index = self
+            .orders
+            .iter()
+            .position(|order| order.id.to_string() == id)
+            .ok_or(OrderError::UnknownOrder)?;
+        let order = self.orders.remove(index);
+        self.wallet
+            .unlock(order.locked)
+            .map_err(|_| OrderError::InsufficientFunds)?;
+
+        Ok(self.update(&order, OrderState::Canceled, None))
+    }
+
+    /// Matches the resting orders of the market against the candlestick.
+    /// Updates of a forming candlestick are only matched against the movement since the previous update.
+    /// Closed candlesticks advance the time to their close time, forming ones to the current time
+    /// bounded to their interval.
+    pub fn push_candlestick(&mut self, candlestick: &Candlestick) -> Vec<OrderUpdate> {
+        let volume = match self.candlesticks.get(candlestick.market) {
+            Some(previous) if previous.open_time == candlestick.open_time => {
+                candlestick.volume.quantity - previous.volume.quantity
+            }
+            _ => candlestick.volume.quantity,
+        };
+        let points = match self.candlesticks.get(candlestick.market) {
+            Some(previous) if previous.open_time == candlestick.open_time => {
+                let low = Some(candlestick.low.price).filter(|_| candlestick.low < previous.low);
+                let high =
+                    Some(candlestick.high.price).filter(|_| candlestick.high > previous.high);
+                let mut points = vec![previous.close.price];
+                if candlestick.close >= previous.close {
+                    points.extend(low);
+                    points.extend(high);
+                } else {
+                    points.extend(high);
+                    points.extend(low);
+                }
+                points.push(candlestick.close.price);
+                points
+            }
+            _ => {
+                let (first, second) = if candlestick.close >= candlestick.open {
+                    (candlestick.low, candlestick.high)
+                } else {
+                    (candlestick.high, candlestick.low)
+                };
+                vec![
+                    candlestick.open.price,
+                    first.price,
+                    second.price,
+                    candlestick.close.price,
+                ]
+            }
+        };
+        self.candlesticks.insert(candlestick.market, *candlestick);
+        // Updates of a forming candlestick happen before its close time, which is taken
+        // from the clock within the interval of the candlestick.
+        let time = if candlestick.closed {
+            candlestick.close_time
+        } else {
+            Timestamp::now().clamp(candlestick.open_time, candlestick.close_time)
+        };
+
+        self.advance(candlestick.market, &points, volume, time)
+    }
+
+    /// Matches the resting orders of the market against the price and quantity of the trade.
+    pub fn push_trade(&mut self, trade: &Trade) -> Vec<OrderUpdate> {
+        self.advance(
+            trade.market,
+            &[trade.price.price],
+            trade.quantity.quantity,
+            trade.time,
+        )
+    }
+
+    fn advance(
+        &mut self,
+        market: &'static Market,
+        points: &[f64],
+        volume: f64,
+        time: Timestamp,
+    ) -> Vec<OrderUpdate> {
+        self.time = Some(time);
+        if let Some(&close) = points.last() {
+            self.prices.insert(market, close);
+        }
+
+        let mut updates = match points.first() {
+            Some(&price) => self.arrive(market, price, volume, time),
+            None => Vec::new(),
+        };
+
+        let mut triggered: Vec<(f64, usize, Leg)> = self
+            .orders
+            .iter()
+            .enumerate()
+            .filter(|(_, order)| order.market == market && order.pending.is_none())
+            .filter_map(|(index, order)| {
+                self.triggered(order, points)
+                    .map(|(position, leg)| (position, index, leg))
+            })
+            .collect();
+        triggered.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(Ordering::Equal));
+
+        let mut closed = Vec::new();
+        for (position, index, leg) in triggered {
+            let mut order = self.orders[index].clone();
+            let price = match (leg, level(&order, leg)) {
+                (Leg::Stop, _) if position == 0.0 => points[0],
+                (_, Some(level)) => level,
+                (_, None) => continue,
+            };
+            let liquidity = match leg {
+                Leg::Limit => Liquidity::Maker,
+                Leg::Stop => Liquidity::Taker,
+            };
+
+            let quantity = self.fillable(&mut order, liquidity, Some(volume), points);
+            let update = self.fill(&mut order, quantity, price, liquidity, time);
+            order.trigger = match (leg, order.trigger) {
+                (Leg::Stop, _) => Trigger::Market,
+                (Leg::Limit, Trigger::Oco { limit, .. }) if update.is_some() => {
+                    Trigger::Limit(limit)
+                }
+                (_, trigger) => trigger,
+            };
+            if let Some(update) = update {
+                if update.state != OrderState::PartiallyFilled {
+                    closed.push(index);
+                }
+                updates.push(update);
+            }
+            self.orders[index] = order;
+        }
+
+        closed.sort_unstable();
+        for index in closed.into_iter().rev() {
+            self.orders.remove(index);
+        }
+
+        updates
+    }
+
+    /// Lets the pending orders of the market that arrived by the given time take part in matching.
+    fn arrive(
+        &mut self,
+        market: &'static Market,
+        price: f64,
+        volume: f64,
+        time: Timestamp,
+    ) -> Vec<OrderUpdate> {
+        let queue = self.queue_ahead(market);
+        let mut updates = Vec::new();
+        let mut index = 0;
+        while index < self.orders.len() {
+            let mut order = self.orders[index].clone();
+            match order.pending {
+                Some(arrival) if order.market == market && arrival <= time => {
+                    order.pending = None;
+                    order.queue = queue;
+                }
+                _ => {
+                    index += 1;
+                    continue;
+                }
+            }
+
+            let update = match (order.trigger, self.triggered(&order, &[price])) {
+                (_, None) => None,
+                (Trigger::Limit(_), Some(_)) => {
+                    let quantity =
+                        self.fillable(&mut order, Liquidity::Taker, Some(volume), &[price]);
+                    self.fill(&mut order, quantity, price, Liquidity::Taker, time)
+                }
+                (_, Some(_)) => {
+                    self.wallet.unlock(order.locked).ok();
+                    Some(self.update(&order, OrderState::Rejected, None))
+                }
+            };
+
+            match update {
+                Some(update) if update.state != OrderState::PartiallyFilled => {
+                    self.orders.remove(index);
+                    updates.push(update);
+                }
+                update => {
+                    self.orders[index] = order;
+                    updates.extend(update);
+                    index += 1;
+                }
+            }
+        }
+
+        updates
+    }
+
+    /// Returns the position on the path at which the order is triggered and the leg that triggers.
+    fn triggered(&self, order: &Resting, points: &[f64]) -> Option<(f64, Leg)> {
+        match order.trigger {
+            Trigger::Limit(_) => self
+                .reach(order, Leg::Limit, points)
+                .map(|p| (p, Leg::Limit)),
+            Trigger::Stop(_) => self.reach(order, Leg::Stop, points).map(|p| (p, Leg::Stop)),
+            Trigger::Market => Some((0.0, Leg::Stop)),
+            Trigger::Oco { .. } => {
+                let limit = self.reach(order, Leg::Limit, points);
+                let stop = self.reach(order, Leg::Stop, points);
+                match (limit, stop) {
+                    (Some(limit), Some(stop)) => Some(match self.assumption {
+                        FillAssumption::Path if limit <= stop => (limit, Leg::Limit),
+                        FillAssumption::Path => (stop, Leg::Stop),
+                        FillAssumption::Optimistic => (limit, Leg::Limit),
+                        FillAssumption::Pessimistic => (stop, Leg::Stop),
+                    }),
+                    (Some(limit), None) => Some((limit, Leg::Limit)),
+                    (None, Some(stop)) => Some((stop, Leg::Stop)),
+                    (None, None) => None,
+                }
+            }
+        }
+    }
+
+    /// Returns the position on the path, counted in segments, at which the price of the leg is reached.
+    fn reach(&self, order: &Resting, leg: Leg, points: &[f64]) -> Option<f64> {
+        let level = level(order, leg)?;
+        // Limit orders wait for the price to come to them, stop orders for it to move away.
+        let rising = match leg {
+            Leg::Limit => order.side == Side::Sell,
+            Leg::Stop => order.side == Side::Buy,
+        };
+        let strict = matches!(leg, Leg::Limit) && self.assumption == FillAssumption::Pessimistic;
+        let reached = |price: f64| match (rising, strict) {
+            (true, false) => price >= level,
+            (true, true) => price > level,
+            (false, false) => price <= level,
+            (false, true) => price < level,
+        };
+
+        if reached(*points.first()?) {
+            return Some(0.0);
+        }
+        points
+            .windows(2)
+            .enumerate()
+            .find(|(_, segment)| reached(segment[1]))
+            .map(|(index, segment)| index as f64 + (level - segment[0]) / (segment[1] - segment[0]))
+    }
+
+    /// Returns the base quantity of the order that can be filled from the volume
+    /// traded at the given path, once the queue ahead of the order traded.
+    fn fillable(
+        &self,
+        order: &mut Resting,
+        liquidity: Liquidity,
+        volume: Option<f64>,
+        points: &[f64],
+    ) -> Quantity {
+        let remaining = order.base - order.executed;
+        let (participation, mut volume) = match (self.participation, volume) {
+            (Some(participation), Some(volume)) => (participation, volume),
+            _ => return remaining,
+        };
+
+        if liquidity == Liquidity::Maker {
+            if let Some(limit) = level(order, Leg::Limit) {
+                let low = points.iter().cloned().fold(f64::INFINITY, f64::min);
+                let high = points.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+                if high > low {
+                    let beyond = match order.side {
+                        Side::Buy => limit - low,
+                        Side::Sell => high - limit,
+                    };
+                    volume *= (beyond / (high - low)).clamp(0.0, 1.0);
+                }
+            }
+            let queued = order.queue.quantity.min(volume);
+            order.queue.quantity -= queued;
+            volume -= queued;
+        }
+
+        Quantity {
+            quantity: (volume * participation).min(remaining.quantity),
+            asset: remaining.asset,
+        }
+    }
+
+    /// Fills the base quantity of the order and returns its update, unless the quantity is zero.
+    /// Orders that cannot be covered expire.
+    fn fill(
+        &mut self,
+        order: &mut Resting,
+        quantity: Quantity,
+        price: f64,
+        liquidity: Liquidity,
+        time: Timestamp,
+    ) -> Option<OrderUpdate> {
+        if quantity.quantity <= 0.0 {
+            return None;
+        }
+
+        Some(
+            match self.execute(order, quantity, price, liquidity, time) {
+                Ok(fill) if order.executed.quantity >= order.base.quantity => {
+                    self.update(order, OrderState::Filled, Some(fill))
+                }
+                Ok(fill) => self.update(order, OrderState::PartiallyFilled, Some(fill)),
+                Err(_) => {
+                    self.wallet.unlock(order.locked).ok();
+                    order.locked = Quantity::zero(order.locked.asset);
+                    self.update(order, OrderState::Expired, None)
+                }
+            },
+        )
+    }
+
+    /// Exchanges the assets of the base quantity of the order in the wallet and pays the fee.
+    fn execute(
+        &mut self,
+        order: &mut Resting,
+        quantity: Quantity,
+        price: f64,
+        liquidity: Liquidity,
+        time: Timestamp,
+    ) -> Result<Fill, WalletError> {
+        let mut price = Price {
+            price,
+            market: order.market,
+        };
+        if liquidity == Liquidity::Taker {
+            let volume = self
+                .candlesticks
+                .get(order.market)
+                .map(|candlestick| candlestick.volume);
+            let bps = self.slippage.bps(quantity, price, volume);
+            let slipped = match order.side {
+                Side::Buy => price * (1.0 + bps / 10_000.0),
+                Side::Sell => price * (1.0 - bps / 10_000.0),
+            };
+            price = match (order.side, order.trigger) {
+                (Side::Buy, Trigger::Limit(limit)) if slipped > limit => limit,
+                (Side::Sell, Trigger::Limit(limit)) if slipped < limit => limit,
+                _ => slipped,
+            };
+        }
+
+        let remaining = order.base - order.executed;
+        let complete = quantity.quantity >= remaining.quantity;
+        let quantity = if complete { remaining } else { quantity };
+        let unlocked = if complete {
+            order.locked
+        } else {
+            order.locked * (quantity.quantity / remaining.quantity)
+        };
+        let quote = quantity.checked_mul(price)?;
+        let (spent, received) = match order.side {
+            Side::Buy => (quote, quantity),
+            Side::Sell => (quantity, quote),
+        };
+
+        let mut wallet = self.wallet.clone();
+        wallet.unlock(unlocked)?;
+        wallet.withdraw(spent)?;
+        wallet.deposit(received)?;
+        let commission = self.fee(&wallet, quote, received, self.fees.rate(liquidity));
+        if commission.quantity > 0.0 {
+            wallet.withdraw(commission)?;
+        }
+        self.wallet = wallet;
+
+        order.locked = order.locked - unlocked;
+        order.executed = if complete {
+            order.base
+        } else {
+            order.executed + quantity
+        };
+
+        Ok(Fill {
+            price,
+            quantity: order.quantity * (quantity.quantity / order.base.quantity),
+            commission,
+            time,
+        })
+    }
+
+    /// Returns the fee of a fill of the given value, paid in the discount asset if the wallet holds enough.
+    fn fee(&self, wallet: &Wallet, value: Quantity, received: Quantity, rate: f64) -> Quantity {
+        if let Some((asset, discount)) = self.fees.discount {
+            if let Some(fee) = self.convert(value * (rate * (1.0 - discount)), asset) {
+                if rate > 0.0 && wallet.free(asset) >= fee {
+                    return fee;
+                }
+            }
+        }
+
+        received * rate
+    }
+
+    /// Converts the quantity into the asset at the latest price of a market that trades both.
+    fn convert(&self, quantity: Quantity, asset: &'static Asset) -> Option<Quantity> {
+        if quantity.asset == asset {
+            return Some(quantity);
+        }
+
+        self.prices.iter().find_map(|(market, &price)| {
+            let price = Price { price, market };
+            if market.base == asset && market.quote == quantity.asset {
+                quantity.checked_div(price).ok()
+            } else if market.base == quantity.asset && market.quote == asset {
+                quantity.checked_mul(price).ok()
+            } else {
+                None
+            }
+        })
+    }
+
+    fn update(&self, order: &Resting, state: OrderState, fill: Option<Fill>) -> OrderUpdate {
+        OrderUpdate {
+            exchange_id: order.id.to_string(),
+            state,
+            executed_quantity: executed(order),
+            fill,
+            time: self.now(),
+        }
+    }
+
+    /// Returns the quantity that rests ahead of new limit orders of the market.
+    fn queue_ahead(&self, market: &'static Market) -> Quantity {
+        let volume = self
+            .candlesticks
+            .get(market)
+            .map(|candlestick| candlestick.volume.quantity)
+            .unwrap_or(0.0);
+
+        Quantity {
+            quantity: self.queue * volume,
+            asset: market.base,
+        }
+    }
+
+    fn now(&self) -> Timestamp {
+        self.time.unwrap_or_else(Timestamp::now)
+    }
+}
+
+/// Returns the executed quantity of the order in the asset of its quantity.
+fn executed(order: &Resting) -> Quantity {
+    order.quantity * (order.executed.quantity / order.base.quantity)
+}
+
+/// Returns the price at which the leg of the order is triggered.
+fn level(order: &Resting, leg: Leg) -> Option<f64> {
+    match (leg, order.trigger) {
+        (Leg::Limit, Trigger::Limit(price))
+        | (Leg::Limit, Trigger::Oco { limit: price, .. })
+        | (Leg::Stop, Trigger::Stop(price))
+        | (Leg::Stop, Trigger::Oco { stop: price, .. }) => Some(price.price),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{btc, eth, ethbtc, market, ohlc, price, trade};
+    use crate::Asset;
+
+    /// Returns a candlestick with a base volume of ten.
+    fn candlestick(market: &'static Market, minute: u64, prices: [f64; 4]) -> Candlestick {
+        Candlestick {
+            volume: Quantity {
+                quantity: 10.0,
+                asset: market.base,
+            },
+            ..ohlc(market, minute, prices)
+        }
+    }
+
+    /// Returns an engine that holds ten BTC and knows the price of ETHBTC to be one.
+    fn engine(assumption: FillAssumption) -> MatchingEngine {
+        let mut wallet = Wallet::new();
+        wallet.deposit(btc(10.0)).unwrap();
+        let mut engine = MatchingEngine::new(wallet).assumption(assumption);
+        engine.push_candlestick(&candlestick(ethbtc(), 0, [1.0, 1.0, 1.0, 1.0]));
+
+        engine
+    }
+
+    #[test]
+    fn test_resting_limit() {
+        // A resting buy locks its funds and fills at the limit once the price comes down.
+        let mut matching = engine(FillAssumption::Path);
+        let response = matching
+            .place(Order::Limit(Side::Buy, eth(4.0), price(0.9)))
+            .unwrap();
+        assert_eq!(response.executed_quantity, eth(0.0));
+        assert_eq!(matching.wallet().locked(ethbtc().quote), btc(3.6));
+
+        let updates = matching.push_candlestick(&candlestick(ethbtc(), 1, [1.0, 1.1, 0.9, 1.0]));
+        assert_eq!(updates.len(), 1);
+        assert_eq!(updates[0].exchange_id, response.id);
+        assert_eq!(updates[0].state, OrderState::Filled);
+        assert_eq!(updates[0].fill.unwrap().price, price(0.9));
+        assert_eq!(matching.wallet().total(ethbtc().quote), btc(6.4));
+        assert_eq!(matching.wallet().free(ethbtc().base), eth(4.0));
+        assert!(matching.open_orders().is_empty());
+    }
+
+    #[test]
+    fn test_rejected() {
+        let mut matching = engine(FillAssumption::Path);
+        assert!(matches!(
+            matching.place(Order::Limit(Side::Buy, eth(20.0), price(0.9))),
+            Err(OrderError::InsufficientFunds)
+        ));
+        // The stop would trigger immediately.
+        assert!(matches!(
+            matching.place(Order::Stop(Side::Buy, eth(1.0), price(0.95))),
+            Err(OrderError::Invalid)
+        ));
+    }
+
+    #[test]
+    fn test_marketable_limit() {
+        // A marketable limit order fills immediately at the latest price.
+        let mut matching = engine(FillAssumption::Path);
+        matching.wallet.deposit(eth(1.0)).unwrap();
+        let response = matching
+            .place(Order::Limit(Side::Sell, eth(1.0), price(0.8)))
+            .unwrap();
+        assert_eq!(response.executed_quantity, eth(1.0));
+        assert_eq!(response.fills[0].price, price(1.0));
+    }
+
+    #[test]
+    fn test_stop_gap() {
+        // Stop orders fill at the open if the price gapped beyond the stop.
+        let mut matching = engine(FillAssumption::Path);
+        matching.wallet.deposit(eth(1.0)).unwrap();
+        let stop = matching
+            .place(Order::Stop(Side::Sell, eth(1.0), price(0.95)))
+            .unwrap();
+        let updates = matching.push_candlestick(&candlestick(ethbtc(), 1, [0.9, 0.92, 0.85, 0.9]));
+        assert_eq!(updates[0].exchange_id, stop.id);
+        assert_eq!(updates[0].fill.unwrap().price, price(0.9));
+    }
+
+    #[test]
+    fn test_cancel() {
+        // Canceling releases the locked funds.
+        let mut matching = engine(FillAssumption::Path);
+        matching.wallet.deposit(eth(2.0)).unwrap();
+        let resting = matching
+            .place(Order::Limit(Side::Sell, eth(2.0), price(1.5)))
+            .unwrap();
+        assert_eq!(matching.wallet().locked(ethbtc().base), eth(2.0));
+        let update = matching.cancel(&resting.id).unwrap();
+        assert_eq!(update.state, OrderState::Canceled);
+        assert_eq!(matching.wallet().locked(ethbtc().base), eth(0.0));
+        assert!(matches!(
+            matching.cancel(&resting.id),
+            Err(OrderError::UnknownOrder)
+        ));
+    }
+
+    #[test]
+    fn test_fill_assumptions() {
+        // Both legs of the OCO order are reached by a rising candlestick,
+        // which first moves down to the stop on its path.
+        let rising = candlestick(ethbtc(), 1, [1.0, 1.2, 0.7, 1.1]);
+        for &(assumption, expected) in &[
+            (FillAssumption::Path, 0.8),
+            (FillAssumption::Optimistic, 1.1),
+            (FillAssumption::Pessimistic, 0.8),
+        ] {
+            let mut matching = engine(assumption);
+            matching.wallet.deposit(eth(1.0)).unwrap();
+            matching
+                .place(Order::Oco(Side::Sell, eth(1.0), price(0.8), price(1.1)))
+                .unwrap();
+            let updates = matching.push_candlestick(&rising);
+            assert_eq!(updates.len(), 1);
+            assert_eq!(updates[0].fill.unwrap().price, price(expected));
+            assert_eq!(matching.wallet().total(ethbtc().base), eth(0.0));
+        }
+    }
+
+    #[test]
+    fn test_pessimistic_touch() {
+        // Pessimistically, touching the limit price does not fill.
+        let mut matching = engine(FillAssumption::Pessimistic);
+        matching
+            .place(Order::Limit(Side::Buy, eth(1.0), price(0.9)))
+            .unwrap();
+        assert!(matching
+            .push_trade(&trade(ethbtc(), 1, 60_000, 0.9, 1.0))
+            .is_empty());
+        assert_eq!(
+            matching
+                .push_trade(&trade(ethbtc(), 2, 60_000, 0.89, 1.0))
+                .len(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_maker_fee() {
+        // Resting orders pay the maker fee in the received asset without a discount asset.
+        let mut matching = engine(FillAssumption::Path).fees(FeeSchedule::new(0.001, 0.002));
+        matching
+            .place(Order::Limit(Side::Buy, eth(1.0), price(0.9)))
+            .unwrap();
+        let updates = matching.push_candlestick(&candlestick(ethbtc(), 1, [1.0, 1.0, 0.8, 0.9]));
+        assert_eq!(updates[0].fill.unwrap().commission, eth(0.001));
+        assert_eq!(matching.wallet().free(ethbtc().base), eth(0.999));
+    }
+
+    #[test]
+    fn test_latency_slippage() {
+        // Orders arrive after the latency, then taker fills slip and pay the discounted fee in BNB.
+        let bnb = Asset::intern("BNB");
+        let bnbbtc = market("BNB", "BTC");
+        let mut matching = engine(FillAssumption::Path)
+            .fees(FeeSchedule::new(0.001, 0.002).discount(bnb, 0.25))
+            .slippage(FixedSlippage(10.0))
+            .latency(Duration::minutes(1));
+        matching
+            .wallet
+            .deposit(Quantity {
+                quantity: 1.0,
+                asset: bnb,
+            })
+            .unwrap();
+        matching.push_candlestick(&candlestick(bnbbtc, 0, [0.01, 0.01, 0.01, 0.01]));
+        let marketable = matching
+            .place(Order::Limit(Side::Buy, eth(1.0), price(1.1)))
+            .unwrap();
+        assert_eq!(marketable.executed_quantity, eth(0.0));
+        matching
+            .place(Order::Stop(Side::Buy, eth(1.0), price(1.05)))
+            .unwrap();
+
+        let updates = matching.push_candlestick(&candlestick(ethbtc(), 1, [1.0, 1.2, 1.0, 1.1]));
+        assert_eq!(updates.len(), 2);
+        let fills: Vec<Fill> = updates.iter().map(|update| update.fill.unwrap()).collect();
+        assert!((fills[0].price.price - 1.001).abs() < 1e-9);
+        assert!((fills[1].price.price - 1.05105).abs() < 1e-9);
+        assert_eq!(fills[0].commission.asset, bnb);
+        assert!((fills[0].commission.quantity - 0.002 * 1.001 * 0.75 / 0.01).abs() < 1e-9);
+        assert_eq!(matching.wallet().free(ethbtc().base), eth(2.0));
+    }
+
+    #[test]
+    fn test_forming_latency() {
+        // Orders placed on an update of a forming candlestick arrive after the latency
+        // from the time of the update, not from the close of the candlestick.
+        let mut matching = engine(FillAssumption::Path).latency(Duration::minutes(1));
+        let current = Timestamp::now().millis() / 60_000;
+        let forming = Candlestick {
+            closed: false,
+            ..candlestick(ethbtc(), current, [1.0, 1.0, 1.0, 1.0])
+        };
+        matching.push_candlestick(&forming);
+        let response = matching
+            .place(Order::Limit(Side::Buy, eth(1.0), price(1.0)))
+            .unwrap();
+        assert!(response.time >= forming.open_time);
+        assert!(response.time <= Timestamp::now());
+
+        let closed = candlestick(ethbtc(), current, [1.0, 1.0, 1.0, 1.0]);
+        assert!(matching.push_candlestick(&closed).is_empty());
+        let next = candlestick(ethbtc(), current + 1, [1.0, 1.0, 1.0, 1.0]);
+        assert_eq!(matching.push_candlestick(&next).len(), 1);
+    }
+
+    #[test]
+    fn test_participation() {
+        // Limit orders take a share of the volume beyond their price once the queue ahead traded.
+        let mut matching = engine(FillAssumption::Path).participation(0.1).queue(0.5);
+        matching
+            .place(Order::Limit(Side::Buy, eth(2.0), price(0.9)))
+            .unwrap();
+        let dip = |minute| candlestick(ethbtc(), minute, [1.0, 1.0, 0.8, 0.9]);
+        assert!(matching.push_candlestick(&dip(1)).is_empty());
+        let updates = matching.push_candlestick(&dip(2));
+        assert_eq!(updates[0].state, OrderState::PartiallyFilled);
+        assert_eq!(updates[0].executed_quantity, eth(0.5));
+        assert_eq!(matching.wallet().locked(ethbtc().quote), btc(1.35));
+        let states: Vec<OrderState> = (3..6)
+            .flat_map(|minute| matching.push_candlestick(&dip(minute)))
+            .map(|update| update.state)
+            .collect();
+        assert_eq!(
+            states,
+            vec![
+                OrderState::PartiallyFilled,
+                OrderState::PartiallyFilled,
+                OrderState::Filled
+            ]
+        );
+        assert_eq!(matching.wallet().free(ethbtc().base), eth(2.0));
+        assert_eq!(matching.wallet().locked(ethbtc().quote), btc(0.0));
+    }
+
+    #[test]
+    fn test_triggered_stop() {
+        // Triggered stop orders keep filling at the price of the following trades.
+        let mut matching = engine(FillAssumption::Path).participation(0.5);
+        matching.wallet.deposit(eth(1.0)).unwrap();
+        matching
+            .place(Order::Stop(Side::Sell, eth(1.0), price(0.95)))
+            .unwrap();
+        let updates = matching.push_trade(&trade(ethbtc(), 1, 60_000, 0.94, 1.0));
+        assert_eq!(updates[0].state, OrderState::PartiallyFilled);
+        assert_eq!(updates[0].fill.unwrap().price, price(0.94));
+        let updates = matching.push_trade(&trade(ethbtc(), 2, 60_000, 0.9, 2.0));
+        assert_eq!(updates[0].state, OrderState::Filled);
+        assert_eq!(updates[0].fill.unwrap().price, price(0.9));
+        assert_eq!(matching.wallet().total(ethbtc().base), eth(0.0));
+    }
+}
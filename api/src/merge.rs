@@ -0,0 +1,333 @@
+use crate::{Candlestick, Timestamp};
+use chrono::Duration;
+use futures_core::{
+    stream::Stream,
+    task::{Context, Poll},
+};
+use std::cmp::{Ordering, Reverse};
+use std::collections::BinaryHeap;
+use std::future::Future;
+use std::pin::Pin;
+use tokio::time::{Delay, Instant};
+
+/// Decides how strictly merged candlesticks are ordered by their close time.
+#[derive(Debug, Copy, Clone)]
+pub enum MergeOrder {
+    /// Waits for every stream to provide its next candlestick before emitting one.
+    /// Use this for historical data to get a deterministic order.
+    Strict,
+    /// Buffers candlesticks for the given duration after their close time before emitting them.
+    /// A candlestick is emitted once a stream provides a candlestick that closes
+    /// a window later or once the window passed on the wall clock, whichever is first.
+    /// Use this for live data, where a stream may fall silent at any time.
+    /// Candlesticks arriving later than the window are emitted immediately.
+    /// Needs the Tokio timer to be running.
+    Window(Duration),
+}
+
+/// Candlestick in the reorder buffer. Ties in close time are broken by stream index
+/// and then by arrival, so the order is deterministic.
+struct Entry {
+    candlestick: Candlestick,
+    index: usize,
+    sequence: u64,
+}
+
+impl Entry {
+    fn key(&self) -> (Timestamp, usize, u64) {
+        (self.candlestick.close_time, self.index, self.sequence)
+    }
+}
+
+impl Ord for Entry {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.key().cmp(&other.key())
+    }
+}
+
+impl PartialOrd for Entry {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Entry {
+    fn eq(&self, other: &Self) -> bool {
+        self.key() == other.key()
+    }
+}
+
+impl Eq for Entry {}
+
+/// Merges several candlestick streams, for example subscriptions to different markets,
+/// into a single stream ordered by close time.
+pub struct Merge<S>
+where
+    S: Stream<Item = Candlestick> + Unpin,
+{
+    streams: Vec<S>,
+    done: Vec<bool>,
+    order: MergeOrder,
+    buffer: BinaryHeap<Reverse<Entry>>,
+    sequence: u64,
+    /// Latest close time that was seen on any stream.
+    watermark: Timestamp,
+    /// Wakes the merged stream when the window of the oldest buffered candlestick passed.
+    delay: Option<Delay>,
+}
+
+impl<S> Merge<S>
+where
+    S: Stream<Item = Candlestick> + Unpin,
+{
+    pub fn new(streams: Vec<S>, order: MergeOrder) -> Self {
+        Self {
+            done: vec![false; streams.len()],
+            streams,
+            order,
+            buffer: BinaryHeap::new(),
+            sequence: 0,
+            watermark: Timestamp::default(),
+            delay: None,
+        }
+    }
+
+    fn push(&mut self, index: usize, candlestick: Candlestick) {
+        self.watermark = self.watermark.max(candlestick.close_time);
+        self.buffer.push(Reverse(Entry {
+            candlestick,
+            index,
+            sequence: self.sequence,
+        }));
+        self.sequence += 1;
+    }
+
+    /// Registers the waker of the context to be woken at the given wall clock time.
+    fn wake_at(&mut self, time: Timestamp, cx: &mut Context<'_>) {
+        let wait = (time - Timestamp::now()).to_std().unwrap_or_default();
+        let deadline = Instant::now() + wait;
+        let delay = match &mut self.delay {
+            Some(delay) => {
+                delay.reset(deadline);
+                delay
+            }
+            None => self.delay.get_or_insert(tokio::time::delay_until(deadline)),
+        };
+
+        if Pin::new(delay).poll(cx).is_ready() {
+            cx.waker().wake_by_ref();
+        }
+    }
+
+    /// Returns true if the stream at the given index has a candlestick in the buffer.
+    fn buffered(&self, index: usize) -> bool {
+        self.buffer
+            .iter()
+            .any(|Reverse(entry)| entry.index == index)
+    }
+}
+
+impl<S> Stream for Merge<S>
+where
+    S: Stream<Item = Candlestick> + Unpin,
+{
+    type Item = Candlestick;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.as_mut().get_mut();
+        let mut waiting = false;
+
+        for index in 0..this.streams.len() {
+            if this.done[index] {
+                continue;
+            }
+
+            if let MergeOrder::Strict = this.order {
+                if this.buffered(index) {
+                    continue;
+                }
+            }
+
+            loop {
+                match Pin::new(&mut this.streams[index]).poll_next(cx) {
+                    Poll::Ready(Some(candlestick)) => {
+                        this.push(index, candlestick);
+                        if let MergeOrder::Strict = this.order {
+                            break;
+                        }
+                    }
+                    Poll::Ready(None) => {
+                        this.done[index] = true;
+                        break;
+                    }
+                    Poll::Pending => {
+                        waiting = true;
+                        break;
+                    }
+                }
+            }
+        }
+
+        let done = this.done.iter().all(|done| *done);
+        let (ready, deadline) = match this.buffer.peek() {
+            Some(Reverse(entry)) => match this.order {
+                MergeOrder::Strict => (!waiting, None),
+                MergeOrder::Window(window) => {
+                    let deadline = entry.candlestick.close_time + window;
+                    let ready = deadline <= this.watermark || deadline <= Timestamp::now();
+                    (ready || done, Some(deadline))
+                }
+            },
+            None if done => return Poll::Ready(None),
+            None => (false, None),
+        };
+
+        if ready {
+            let Reverse(entry) = this.buffer.pop().unwrap();
+            Poll::Ready(Some(entry.candlestick))
+        } else {
+            if let Some(deadline) = deadline {
+                this.wake_at(deadline, cx);
+            }
+            Poll::Pending
+        }
+    }
+}
+
+/// Merges several series of candlesticks, each ordered by close time, into a single ordered series.
+pub fn merge_series(series: Vec<Vec<Candlestick>>) -> Vec<Candlestick> {
+    let mut entries: Vec<Entry> = series
+        .into_iter()
+        .enumerate()
+        .flat_map(|(index, candlesticks)| {
+            candlesticks
+                .into_iter()
+                .enumerate()
+                .map(move |(sequence, candlestick)| Entry {
+                    candlestick,
+                    index,
+                    sequence: sequence as u64,
+                })
+        })
+        .collect();
+    entries.sort();
+
+    entries.into_iter().map(|entry| entry.candlestick).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{candlestick, ethbtc, market};
+    use crate::Market;
+    use futures::channel::mpsc;
+    use futures::{executor, poll, stream, StreamExt};
+
+    fn closing(market: &'static Market, time: Timestamp) -> Candlestick {
+        Candlestick {
+            close_time: time,
+            ..candlestick(market, 0)
+        }
+    }
+
+    fn minutes(candlesticks: &[Candlestick]) -> Vec<(&'static Market, u64)> {
+        candlesticks
+            .iter()
+            .map(|candlestick| {
+                (
+                    candlestick.market,
+                    (candlestick.close_time.millis() + 1) / 60_000,
+                )
+            })
+            .collect()
+    }
+
+    /// Returns the candlesticks that close after the given minutes of ETHBTC and ADABTC.
+    fn series() -> Vec<Vec<Candlestick>> {
+        let at = |minute: u64| Timestamp::from_millis(minute * 60_000 - 1);
+        vec![
+            vec![closing(ethbtc(), at(1)), closing(ethbtc(), at(5))],
+            vec![
+                closing(market("ADA", "BTC"), at(1)),
+                closing(market("ADA", "BTC"), at(3)),
+                closing(market("ADA", "BTC"), at(7)),
+            ],
+        ]
+    }
+
+    fn expected() -> Vec<(&'static Market, u64)> {
+        let adabtc = market("ADA", "BTC");
+        vec![
+            (ethbtc(), 1),
+            (adabtc, 1),
+            (adabtc, 3),
+            (ethbtc(), 5),
+            (adabtc, 7),
+        ]
+    }
+
+    #[test]
+    fn test_merge_strict() {
+        let merged = executor::block_on(
+            Merge::new(
+                series().into_iter().map(stream::iter).collect(),
+                MergeOrder::Strict,
+            )
+            .collect::<Vec<Candlestick>>(),
+        );
+        assert_eq!(minutes(&merged), expected());
+    }
+
+    #[test]
+    fn test_merge_series() {
+        assert_eq!(minutes(&merge_series(series())), expected());
+    }
+
+    #[tokio::test]
+    async fn test_merge_window() {
+        // The candlesticks close in the future, so only the watermark releases them.
+        let window = Duration::minutes(1);
+        let start = Timestamp::now() + Duration::hours(1);
+        let (eth, eth_receiver) = mpsc::unbounded();
+        let (ada, ada_receiver) = mpsc::unbounded();
+        let merged = Merge::new(vec![eth_receiver, ada_receiver], MergeOrder::Window(window));
+        futures::pin_mut!(merged);
+
+        eth.unbounded_send(closing(ethbtc(), start)).unwrap();
+        assert!(poll!(merged.next()).is_pending());
+
+        // A candlestick that closes a window later releases the first one.
+        let adabtc = market("ADA", "BTC");
+        ada.unbounded_send(closing(adabtc, start + window)).unwrap();
+        assert_eq!(merged.next().await.unwrap().market, ethbtc());
+        assert!(poll!(merged.next()).is_pending());
+
+        // A late candlestick is emitted immediately.
+        eth.unbounded_send(closing(ethbtc(), start)).unwrap();
+        assert_eq!(merged.next().await.unwrap().close_time, start);
+
+        // The rest is released when all streams end.
+        drop(eth);
+        drop(ada);
+        assert_eq!(merged.next().await.unwrap().market, adabtc);
+        assert!(merged.next().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_merge_window_wall_clock() {
+        // The window of candlesticks that closed long ago has passed on the wall clock,
+        // while the window of the next one passes shortly.
+        let window = Duration::milliseconds(100);
+        let (eth, eth_receiver) = mpsc::unbounded();
+        let (_ada, ada_receiver) = mpsc::unbounded();
+        let mut merged = Merge::new(vec![eth_receiver, ada_receiver], MergeOrder::Window(window));
+
+        eth.unbounded_send(candlestick(ethbtc(), 0)).unwrap();
+        assert!(poll!(merged.next()).is_ready());
+
+        eth.unbounded_send(closing(ethbtc(), Timestamp::now()))
+            .unwrap();
+        let next = tokio::time::timeout(std::time::Duration::from_secs(10), merged.next()).await;
+        assert!(next.unwrap().is_some());
+    }
+}
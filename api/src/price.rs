@@ -1,20 +1,157 @@
-use crate::{Market, Monetary};
+use crate::{ArithmeticError, Market, Monetary};
+use serde::{Deserialize, Serialize};
 use std::cmp::Ordering;
+use std::ops::{Add, Div, Mul, Sub};
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
 pub struct Price {
     pub price: Monetary,
     pub market: &'static Market,
 }
 
+impl Price {
+    pub fn checked_add(self, other: Self) -> Result<Self, ArithmeticError> {
+        if self.market != other.market {
+            return Err(ArithmeticError::WrongMarket);
+        }
+
+        Ok(Self {
+            price: self.price + other.price,
+            market: self.market,
+        })
+    }
+
+    pub fn checked_sub(self, other: Self) -> Result<Self, ArithmeticError> {
+        if self.market != other.market {
+            return Err(ArithmeticError::WrongMarket);
+        }
+
+        Ok(Self {
+            price: self.price - other.price,
+            market: self.market,
+        })
+    }
+
+    /// Returns the ratio of this price to the other one.
+    pub fn ratio(self, other: Self) -> Result<Monetary, ArithmeticError> {
+        if self.market != other.market {
+            return Err(ArithmeticError::WrongMarket);
+        }
+
+        Ok(self.price / other.price)
+    }
+
+    /// Returns the change from this price to the other one in percent.
+    pub fn percentage_change(self, other: Self) -> Result<Monetary, ArithmeticError> {
+        Ok((other.ratio(self)? - 1.0) * 100.0)
+    }
+
+    /// Returns the absolute difference between this price and the other one,
+    /// for example between the best bid and ask.
+    pub fn spread(self, other: Self) -> Result<Self, ArithmeticError> {
+        let spread = self.checked_sub(other)?;
+
+        Ok(Self {
+            price: spread.price.abs(),
+            market: self.market,
+        })
+    }
+}
+
+impl Add for Price {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        self.checked_add(other)
+            .expect("cannot add prices of different markets")
+    }
+}
+
+impl Sub for Price {
+    type Output = Self;
+
+    fn sub(self, other: Self) -> Self {
+        self.checked_sub(other)
+            .expect("cannot subtract prices of different markets")
+    }
+}
+
+impl Div for Price {
+    type Output = Monetary;
+
+    fn div(self, other: Self) -> Monetary {
+        self.ratio(other)
+            .expect("cannot divide prices of different markets")
+    }
+}
+
+impl Mul<Monetary> for Price {
+    type Output = Self;
+
+    fn mul(self, factor: Monetary) -> Self {
+        Self {
+            price: self.price * factor,
+            market: self.market,
+        }
+    }
+}
+
+impl Div<Monetary> for Price {
+    type Output = Self;
+
+    fn div(self, divisor: Monetary) -> Self {
+        Self {
+            price: self.price / divisor,
+            market: self.market,
+        }
+    }
+}
+
+/// Prices of different markets are not comparable.
 impl PartialOrd for Price {
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        if self.market != other.market {
+            return None;
+        }
+
         self.price.partial_cmp(&other.price)
     }
 }
 
 impl PartialEq for Price {
     fn eq(&self, other: &Self) -> bool {
-        self.price.eq(&other.price)
+        self.market == other.market && self.price.eq(&other.price)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{market, price};
+
+    fn ada(price: Monetary) -> Price {
+        Price {
+            price,
+            market: market("ADA", "BTC"),
+        }
+    }
+
+    #[test]
+    fn test_price() {
+        assert_eq!(price(4.0) / price(2.0), 2.0);
+        assert_eq!(price(2.0).percentage_change(price(2.5)), Ok(25.0));
+        assert_eq!(price(2.0).spread(price(2.5)), Ok(price(0.5)));
+        assert_eq!(price(2.0) * 1.5, price(3.0));
+        assert!(price(1.0) < price(2.0));
+    }
+
+    #[test]
+    fn test_price_wrong_market() {
+        assert_ne!(price(1.0), ada(1.0));
+        assert!(price(1.0).partial_cmp(&ada(2.0)).is_none());
+        assert_eq!(
+            price(1.0).ratio(ada(1.0)),
+            Err(ArithmeticError::WrongMarket)
+        );
     }
 }
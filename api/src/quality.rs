@@ -0,0 +1,517 @@
+use crate::{
+    Candlestick, CandlestickError, Gap, Interval, Monetary, Price, Quantity, Timestamp, Transform,
+};
+use std::collections::VecDeque;
+
+/// Problem found in a series of candlesticks.
+#[derive(Debug)]
+pub enum Issue {
+    /// Candlesticks are missing between two candlesticks.
+    Missing(Gap),
+    /// A candlestick has the same open time as the one before it.
+    Duplicate(Timestamp),
+    /// A candlestick opens before the one before it.
+    Unordered(Timestamp),
+    /// A candlestick violates the invariants checked by `Candlestick::validate`.
+    Invalid(Timestamp, CandlestickError),
+    /// Consecutive candlesticks without any volume.
+    ZeroVolume {
+        start: Timestamp,
+        end: Timestamp,
+        count: usize,
+    },
+    /// The return of a candlestick deviates from the recent returns by more than the maximal z-score.
+    Spike { open_time: Timestamp, z_score: f64 },
+}
+
+/// Checks closed candlesticks for missing intervals, duplicates, invalid prices,
+/// stretches without volume and price spikes, and reports the issues it finds.
+///
+/// Without repairing, all candlesticks are passed on unchanged. With repairing, duplicates
+/// and unordered candlesticks are dropped, high and low prices are widened to contain
+/// the open and close prices, missing candlesticks are filled with candlesticks
+/// without volume at the previous close price and spikes are replaced by the previous close price.
+/// Candlesticks that cannot be repaired are dropped.
+/// Forming candlesticks are passed on without being checked.
+#[derive(Debug)]
+pub struct QualityCheck {
+    interval: Interval,
+    /// Number of recent returns the z-score of a return is computed from.
+    window: usize,
+    max_z_score: f64,
+    /// Number of consecutive candlesticks without volume that are reported.
+    min_zero_volume: usize,
+    repair: bool,
+    previous: Option<Candlestick>,
+    /// Close price of the last candlestick that was no spike.
+    previous_close: Option<Price>,
+    /// Close price of the previous candlestick if it was a spike.
+    spike_close: Option<Price>,
+    returns: VecDeque<f64>,
+    zero_volume: Option<(Timestamp, Timestamp, usize)>,
+    checked: usize,
+    issues: Vec<Issue>,
+}
+
+impl QualityCheck {
+    /// Creates a check that reports spikes beyond a z-score of 5 over the last 100 returns
+    /// and stretches of at least 10 candlesticks without volume.
+    pub fn new(interval: Interval) -> Self {
+        Self {
+            interval,
+            window: 100,
+            max_z_score: 5.0,
+            min_zero_volume: 10,
+            repair: false,
+            previous: None,
+            previous_close: None,
+            spike_close: None,
+            returns: VecDeque::new(),
+            zero_volume: None,
+            checked: 0,
+            issues: Vec::new(),
+        }
+    }
+
+    pub fn window(mut self, window: usize) -> Self {
+        self.window = window.max(2);
+        self
+    }
+
+    pub fn max_z_score(mut self, max_z_score: f64) -> Self {
+        self.max_z_score = max_z_score;
+        self
+    }
+
+    pub fn min_zero_volume(mut self, min_zero_volume: usize) -> Self {
+        self.min_zero_volume = min_zero_volume.max(1);
+        self
+    }
+
+    /// Sets whether issues are repaired or only reported.
+    pub fn repair(mut self, repair: bool) -> Self {
+        self.repair = repair;
+        self
+    }
+
+    /// Returns all issues found so far.
+    pub fn issues(&self) -> &[Issue] {
+        &self.issues
+    }
+
+    /// Returns the number of checked candlesticks.
+    pub fn checked(&self) -> usize {
+        self.checked
+    }
+
+    pub fn is_clean(&self) -> bool {
+        self.issues.is_empty()
+    }
+
+    /// Checks a series of candlesticks, for example stored ones, and returns the series
+    /// that is passed on.
+    pub fn apply<I>(&mut self, candlesticks: I) -> Vec<Candlestick>
+    where
+        I: IntoIterator<Item = Candlestick>,
+    {
+        let mut checked = Vec::new();
+        for candlestick in candlesticks {
+            checked.extend(self.push(candlestick));
+        }
+        checked.extend(self.flush());
+
+        checked
+    }
+
+    fn check_order(&mut self, candlestick: &Candlestick) -> Result<Vec<Candlestick>, ()> {
+        let previous = match self.previous {
+            Some(previous) => previous,
+            None => return Ok(Vec::new()),
+        };
+
+        if candlestick.open_time <= previous.open_time {
+            self.issues
+                .push(if candlestick.open_time == previous.open_time {
+                    Issue::Duplicate(candlestick.open_time)
+                } else {
+                    Issue::Unordered(candlestick.open_time)
+                });
+            return Err(());
+        }
+
+        let expected = previous.open_time + self.interval.duration();
+        let mut filled = Vec::new();
+        if candlestick.open_time > expected {
+            self.issues.push(Issue::Missing(Gap {
+                market: candlestick.market,
+                interval: self.interval,
+                start: expected,
+                end: candlestick.open_time - self.interval.duration(),
+            }));
+
+            if self.repair {
+                let mut open_time = expected;
+                while open_time < candlestick.open_time {
+                    filled.push(flat(&previous, previous.close, open_time, self.interval));
+                    open_time = open_time + self.interval.duration();
+                }
+            }
+        }
+
+        Ok(filled)
+    }
+
+    fn check_invariants(&mut self, mut candlestick: Candlestick) -> Option<Candlestick> {
+        let error = match candlestick.validate() {
+            Ok(()) => return Some(candlestick),
+            Err(error) => error,
+        };
+        let repairable = matches!(error, CandlestickError::InvalidPrices);
+        self.issues
+            .push(Issue::Invalid(candlestick.open_time, error));
+
+        if !self.repair {
+            return Some(candlestick);
+        }
+        if !repairable {
+            return None;
+        }
+
+        let prices = [candlestick.open.price, candlestick.close.price];
+        candlestick.high.price = prices
+            .iter()
+            .fold(candlestick.high.price, |high, &price| high.max(price));
+        candlestick.low.price = prices
+            .iter()
+            .fold(candlestick.low.price, |low, &price| low.min(price));
+        candlestick.validate().ok().map(|_| candlestick)
+    }
+
+    /// Returns the z-score of the log return from the previous close to the close,
+    /// once enough returns are known.
+    fn z_score(&self, previous_close: Price, close: Price) -> Option<(f64, f64)> {
+        let log_return = match close.ratio(previous_close) {
+            Ok(ratio) if ratio > 0.0 && ratio.is_finite() => ratio.ln(),
+            _ => return None,
+        };
+        if self.returns.len() < self.window {
+            return Some((log_return, 0.0));
+        }
+
+        let count = self.returns.len() as f64;
+        let mean = self.returns.iter().sum::<f64>() / count;
+        let variance = self
+            .returns
+            .iter()
+            .map(|value| (value - mean).powi(2))
+            .sum::<f64>()
+            / count;
+        let deviation = variance.sqrt();
+        if deviation == 0.0 {
+            return Some((log_return, 0.0));
+        }
+
+        Some((log_return, (log_return - mean) / deviation))
+    }
+
+    /// Compares the close price with the close of the last candlestick that was no spike.
+    /// If a spike is followed by a candlestick that is no spike compared to it,
+    /// the price moved to a new level and that candlestick is accepted.
+    fn check_spike(&mut self, candlestick: Candlestick) -> Candlestick {
+        let previous_close = match self.previous_close {
+            Some(previous_close) => previous_close,
+            None => {
+                self.previous_close = Some(candlestick.close);
+                return candlestick;
+            }
+        };
+        let (mut log_return, z_score) = match self.z_score(previous_close, candlestick.close) {
+            Some(z_score) => z_score,
+            None => return candlestick,
+        };
+
+        if z_score.abs() > self.max_z_score {
+            let shifted = self
+                .spike_close
+                .take()
+                .and_then(|spike_close| self.z_score(spike_close, candlestick.close))
+                .filter(|(_, z_score)| z_score.abs() <= self.max_z_score);
+
+            match shifted {
+                Some((shifted_return, _)) => log_return = shifted_return,
+                None => {
+                    self.issues.push(Issue::Spike {
+                        open_time: candlestick.open_time,
+                        z_score,
+                    });
+                    self.spike_close = Some(candlestick.close);
+
+                    if self.repair {
+                        return Candlestick {
+                            volume: candlestick.volume,
+                            quote_volume: candlestick.quote_volume,
+                            taker_buy_base_volume: candlestick.taker_buy_base_volume,
+                            taker_buy_quote_volume: candlestick.taker_buy_quote_volume,
+                            trades: candlestick.trades,
+                            ..flat(
+                                &candlestick,
+                                previous_close,
+                                candlestick.open_time,
+                                self.interval,
+                            )
+                        };
+                    }
+                    return candlestick;
+                }
+            }
+        }
+
+        self.spike_close = None;
+        self.previous_close = Some(candlestick.close);
+        self.returns.push_back(log_return);
+        if self.returns.len() > self.window {
+            self.returns.pop_front();
+        }
+
+        candlestick
+    }
+
+    fn check_volume(&mut self, candlestick: &Candlestick) {
+        if candlestick.volume.quantity == 0.0 {
+            let (start, _, count) =
+                self.zero_volume
+                    .unwrap_or((candlestick.open_time, candlestick.open_time, 0));
+            self.zero_volume = Some((start, candlestick.open_time, count + 1));
+        } else {
+            self.end_zero_volume();
+        }
+    }
+
+    fn end_zero_volume(&mut self) {
+        if let Some((start, end, count)) = self.zero_volume.take() {
+            if count >= self.min_zero_volume {
+                self.issues.push(Issue::ZeroVolume { start, end, count });
+            }
+        }
+    }
+}
+
+impl Transform for QualityCheck {
+    fn push(&mut self, candlestick: Candlestick) -> Vec<Candlestick> {
+        if !candlestick.closed {
+            return vec![candlestick];
+        }
+        self.checked += 1;
+
+        let mut passed = match self.check_order(&candlestick) {
+            Ok(filled) => filled,
+            Err(()) if self.repair => return Vec::new(),
+            Err(()) => return vec![candlestick],
+        };
+
+        let candlestick = match self.check_invariants(candlestick) {
+            Some(candlestick) => candlestick,
+            None => return passed,
+        };
+        self.check_volume(&candlestick);
+        let candlestick = self.check_spike(candlestick);
+
+        self.previous = Some(candlestick);
+        passed.push(candlestick);
+        passed
+    }
+
+    fn flush(&mut self) -> Option<Candlestick> {
+        self.end_zero_volume();
+        None
+    }
+}
+
+/// Creates a closed candlestick without volume, whose prices are all at the given price.
+fn flat(
+    template: &Candlestick,
+    price: Price,
+    open_time: Timestamp,
+    interval: Interval,
+) -> Candlestick {
+    let market = template.market;
+    let zero = |asset| Quantity {
+        quantity: 0.0 as Monetary,
+        asset,
+    };
+
+    Candlestick {
+        market,
+        open_time,
+        close_time: interval.close_time(open_time),
+        high: price,
+        low: price,
+        open: price,
+        close: price,
+        volume: zero(market.base),
+        quote_volume: zero(market.quote),
+        taker_buy_base_volume: zero(market.base),
+        taker_buy_quote_volume: zero(market.quote),
+        trades: 0,
+        closed: true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{ethbtc, minute, ohlc};
+    use std::ops::Range;
+
+    /// Returns a candlestick with all prices at the price and the given volume.
+    fn flat(minute: u64, price: f64, volume: f64) -> Candlestick {
+        let market = ethbtc();
+        Candlestick {
+            volume: Quantity {
+                quantity: volume,
+                asset: market.base,
+            },
+            quote_volume: Quantity {
+                quantity: volume * price,
+                asset: market.quote,
+            },
+            ..ohlc(market, minute, [price; 4])
+        }
+    }
+
+    /// Returns candlesticks whose prices alternate slightly, so that their returns vary.
+    fn calm(minutes: Range<u64>) -> Vec<Candlestick> {
+        minutes
+            .map(|minute| flat(minute, 1.0 + (minute % 2) as f64 * 0.01, 1.0))
+            .collect()
+    }
+
+    fn check() -> QualityCheck {
+        QualityCheck::new(Interval::I1m).window(8).max_z_score(4.0)
+    }
+
+    #[test]
+    fn test_quality_clean() {
+        let mut check = check();
+        let passed = check.apply(calm(0..20));
+        assert_eq!(passed.len(), 20);
+        assert_eq!(check.checked(), 20);
+        assert!(check.is_clean(), "{:?}", check.issues());
+    }
+
+    #[test]
+    fn test_quality_spike() {
+        let mut series = calm(0..17);
+        series[12] = flat(12, 2.0, 1.0);
+
+        let mut check = check();
+        let passed = check.apply(series);
+        assert_eq!(passed.len(), 17);
+        assert_eq!(check.issues().len(), 1, "{:?}", check.issues());
+        assert!(
+            matches!(check.issues()[0], Issue::Spike { open_time, .. } if open_time == minute(12))
+        );
+    }
+
+    #[test]
+    fn test_quality_duplicate() {
+        let mut series = calm(0..10);
+        series.insert(6, series[5]);
+
+        let mut check = check();
+        assert_eq!(check.apply(series).len(), 11);
+        assert_eq!(check.issues().len(), 1, "{:?}", check.issues());
+        assert!(matches!(check.issues()[0], Issue::Duplicate(time) if time == minute(5)));
+    }
+
+    #[test]
+    fn test_quality_gap() {
+        let mut series = calm(0..17);
+        series.extend(calm(19..24));
+
+        let mut check = check();
+        check.apply(series);
+        assert_eq!(check.issues().len(), 1, "{:?}", check.issues());
+        assert!(matches!(
+            check.issues()[0],
+            Issue::Missing(gap) if gap.start == minute(17) && gap.end == minute(18)
+        ));
+    }
+
+    #[test]
+    fn test_quality_invalid() {
+        // The close price is above the high price.
+        let mut series = calm(0..10);
+        series[5].high.price = 1.0;
+
+        let mut check = check();
+        check.apply(series);
+        assert_eq!(check.issues().len(), 1, "{:?}", check.issues());
+        assert!(matches!(
+            check.issues()[0],
+            Issue::Invalid(time, CandlestickError::InvalidPrices) if time == minute(5)
+        ));
+    }
+
+    #[test]
+    fn test_quality_zero_volume() {
+        let mut series = calm(0..10);
+        series.extend((10..13).map(|minute| flat(minute, 1.0, 0.0)));
+        series.extend(calm(13..16));
+
+        let mut reported = check().min_zero_volume(3);
+        reported.apply(series.clone());
+        assert_eq!(reported.issues().len(), 1, "{:?}", reported.issues());
+        assert!(matches!(
+            reported.issues()[0],
+            Issue::ZeroVolume { start, end, count: 3 } if start == minute(10) && end == minute(12)
+        ));
+
+        // Shorter stretches than the minimum are not reported.
+        let mut unreported = check().min_zero_volume(4);
+        unreported.apply(series);
+        assert!(unreported.is_clean(), "{:?}", unreported.issues());
+    }
+
+    #[test]
+    fn test_quality_repair() {
+        // Minute 12 spikes, minute 15 is duplicated, minutes 17 and 18 are missing
+        // and minute 19 has its close above its high.
+        let mut series = calm(0..17);
+        series[12] = flat(12, 2.0, 1.0);
+        series.insert(16, series[15]);
+        let mut invalid = flat(19, 1.0, 1.0);
+        invalid.close.price = 1.02;
+        series.push(invalid);
+        series.extend(calm(20..24));
+
+        let mut check = check().repair(true);
+        let repaired = check.apply(series);
+        let minutes: Vec<u64> = repaired
+            .iter()
+            .map(|candlestick| candlestick.open_time.millis() / 60_000)
+            .collect();
+        assert_eq!(minutes, (0..24).collect::<Vec<u64>>());
+        assert_eq!(repaired[12].close, repaired[11].close);
+        assert_eq!(repaired[17].close, repaired[16].close);
+        assert_eq!(repaired[17].volume, Quantity::zero(ethbtc().base));
+        assert_eq!(repaired[19].high.price, 1.02);
+        assert!(repaired
+            .iter()
+            .all(|candlestick| candlestick.validate().is_ok()));
+    }
+
+    #[test]
+    fn test_quality_level_shift() {
+        // A price that moves to a new level and stays there is reported once.
+        let mut check = check();
+        check.apply((0..20).map(|minute| {
+            let level = if minute < 12 { 1.0 } else { 2.0 };
+            flat(minute, level + (minute % 2) as f64 * 0.01, 1.0)
+        }));
+        assert_eq!(check.issues().len(), 1, "{:?}", check.issues());
+        assert!(
+            matches!(check.issues()[0], Issue::Spike { open_time, .. } if open_time == minute(12))
+        );
+    }
+}
@@ -1,47 +1,199 @@
-use crate::{Asset, Monetary, Price};
+use crate::{ArithmeticError, Asset, Monetary, Price};
+use serde::{Deserialize, Serialize};
 use std::cmp::Ordering;
-use std::ops::{Add, Mul};
+use std::iter::Sum;
+use std::ops::{Add, Div, Mul, Neg, Sub};
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
 pub struct Quantity {
     pub quantity: Monetary,
     pub asset: &'static Asset,
 }
 
+impl Quantity {
+    pub fn zero(asset: &'static Asset) -> Self {
+        Self {
+            quantity: 0.0,
+            asset,
+        }
+    }
+
+    pub fn checked_add(self, other: Self) -> Result<Self, ArithmeticError> {
+        if self.asset != other.asset {
+            return Err(ArithmeticError::WrongAsset);
+        }
+
+        Ok(Self {
+            quantity: self.quantity + other.quantity,
+            asset: self.asset,
+        })
+    }
+
+    pub fn checked_sub(self, other: Self) -> Result<Self, ArithmeticError> {
+        self.checked_add(-other)
+    }
+
+    /// Converts a quantity of the base asset into the quote asset of the market of the price.
+    pub fn checked_mul(self, price: Price) -> Result<Self, ArithmeticError> {
+        if self.asset != price.market.base {
+            return Err(ArithmeticError::WrongAsset);
+        }
+
+        Ok(Self {
+            quantity: self.quantity * price.price,
+            asset: price.market.quote,
+        })
+    }
+
+    /// Converts a quantity of the quote asset into the base asset of the market of the price.
+    pub fn checked_div(self, price: Price) -> Result<Self, ArithmeticError> {
+        if self.asset != price.market.quote {
+            return Err(ArithmeticError::WrongAsset);
+        }
+
+        Ok(Self {
+            quantity: self.quantity / price.price,
+            asset: price.market.base,
+        })
+    }
+}
+
 impl Add for Quantity {
     type Output = Self;
 
     fn add(self, other: Self) -> Self {
-        assert_eq!(self.asset, other.asset);
+        self.checked_add(other)
+            .expect("cannot add quantities of different assets")
+    }
+}
+
+impl Sub for Quantity {
+    type Output = Self;
+
+    fn sub(self, other: Self) -> Self {
+        self.checked_sub(other)
+            .expect("cannot subtract quantities of different assets")
+    }
+}
+
+impl Mul<Price> for Quantity {
+    type Output = Self;
+
+    fn mul(self, other: Price) -> Quantity {
+        self.checked_mul(other)
+            .expect("quantity is not measured in the base asset of the price")
+    }
+}
+
+impl Div<Price> for Quantity {
+    type Output = Self;
+
+    fn div(self, other: Price) -> Quantity {
+        self.checked_div(other)
+            .expect("quantity is not measured in the quote asset of the price")
+    }
+}
+
+impl Mul<Monetary> for Quantity {
+    type Output = Self;
 
+    fn mul(self, factor: Monetary) -> Quantity {
         Self {
-            quantity: self.quantity + other.quantity,
+            quantity: self.quantity * factor,
             asset: self.asset,
         }
     }
 }
 
-impl Mul<Price> for Quantity {
+impl Div<Monetary> for Quantity {
     type Output = Self;
 
-    fn mul(self, other: Price) -> Quantity {
-        assert_eq!(self.asset, other.market.base);
+    fn div(self, divisor: Monetary) -> Quantity {
+        Self {
+            quantity: self.quantity / divisor,
+            asset: self.asset,
+        }
+    }
+}
 
+impl Neg for Quantity {
+    type Output = Self;
+
+    fn neg(self) -> Quantity {
         Self {
-            quantity: self.quantity * other.price,
-            asset: other.market.quote,
+            quantity: -self.quantity,
+            asset: self.asset,
         }
     }
 }
 
+/// Sums quantities of the same asset. The sum of no quantities is `None`,
+/// since their asset is unknown, as is the sum of quantities of different assets.
+impl Sum<Quantity> for Option<Quantity> {
+    fn sum<I: Iterator<Item = Quantity>>(mut iter: I) -> Self {
+        let first = iter.next()?;
+        iter.try_fold(first, |sum, quantity| sum.checked_add(quantity).ok())
+    }
+}
+
+/// Quantities of different assets are not comparable.
 impl PartialOrd for Quantity {
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        if self.asset != other.asset {
+            return None;
+        }
+
         self.quantity.partial_cmp(&other.quantity)
     }
 }
 
 impl PartialEq for Quantity {
     fn eq(&self, other: &Self) -> bool {
-        self.quantity.eq(&other.quantity)
+        self.asset == other.asset && self.quantity.eq(&other.quantity)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{btc, eth, price};
+
+    #[test]
+    fn test_quantity() {
+        assert_eq!(eth(3.0) - eth(1.0), eth(2.0));
+        assert_eq!(eth(2.0) * price(0.05), btc(0.1));
+        assert_eq!(btc(0.1) / price(0.05), eth(2.0));
+        assert_eq!(-eth(2.0) * 2.0, eth(-4.0));
+    }
+
+    #[test]
+    fn test_quantity_sum() {
+        assert_eq!(
+            vec![eth(1.0), eth(2.0)]
+                .into_iter()
+                .sum::<Option<Quantity>>(),
+            Some(eth(3.0))
+        );
+        assert_eq!(Vec::new().into_iter().sum::<Option<Quantity>>(), None);
+        assert_eq!(
+            vec![eth(1.0), btc(1.0), eth(2.0)]
+                .into_iter()
+                .sum::<Option<Quantity>>(),
+            None
+        );
+    }
+
+    #[test]
+    fn test_quantity_wrong_asset() {
+        assert_ne!(eth(1.0), btc(1.0));
+        assert!(eth(1.0).partial_cmp(&btc(2.0)).is_none());
+        assert_eq!(
+            eth(1.0).checked_add(btc(1.0)),
+            Err(ArithmeticError::WrongAsset)
+        );
+        assert_eq!(
+            btc(1.0).checked_mul(price(0.05)),
+            Err(ArithmeticError::WrongAsset)
+        );
     }
 }
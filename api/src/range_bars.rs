@@ -0,0 +1,81 @@
+use crate::{Candlestick, Monetary, Transform};
+
+/// Aggregates candlesticks into bars that span at least the given price range.
+/// Candlesticks that are not closed yet are ignored.
+/// The flushed remainder has not reached the range, so it is not closed either.
+#[derive(Debug, Clone)]
+pub struct RangeBars {
+    range: Monetary,
+    current: Option<Candlestick>,
+}
+
+impl RangeBars {
+    pub fn new(range: Monetary) -> Self {
+        Self {
+            range,
+            current: None,
+        }
+    }
+}
+
+impl Transform for RangeBars {
+    fn push(&mut self, candlestick: Candlestick) -> Vec<Candlestick> {
+        if !candlestick.closed {
+            return Vec::new();
+        }
+
+        let current = match self.current {
+            Some(current) => current.merge(candlestick),
+            None => candlestick,
+        };
+
+        if current.high.price - current.low.price >= self.range {
+            self.current = None;
+            vec![current]
+        } else {
+            self.current = Some(current);
+            Vec::new()
+        }
+    }
+
+    fn flush(&mut self) -> Option<Candlestick> {
+        self.current.take().map(|current| Candlestick {
+            closed: false,
+            ..current
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{ethbtc, minute, ohlc};
+
+    #[test]
+    fn test_range_bars_overshoot() {
+        let candlesticks = vec![
+            ohlc(ethbtc(), 0, [10.0, 10.5, 9.8, 10.2]),
+            // Spans more than the range together with the first one, which is neither capped
+            // nor split into several bars.
+            ohlc(ethbtc(), 1, [10.2, 12.0, 10.0, 11.8]),
+            Candlestick {
+                closed: false,
+                ..ohlc(ethbtc(), 2, [11.8, 20.0, 11.8, 20.0])
+            },
+            ohlc(ethbtc(), 3, [11.8, 12.3, 11.5, 12.0]),
+        ];
+
+        let bars = crate::transform(candlesticks, RangeBars::new(1.0));
+        assert_eq!(bars.len(), 2);
+        assert_eq!(bars[0].open_time, minute(0));
+        assert_eq!(bars[0].high.price, 12.0);
+        assert_eq!(bars[0].low.price, 9.8);
+        assert_eq!(bars[0].close.price, 11.8);
+        assert!(bars[0].closed);
+        // The remainder is flushed although it spans less than the range.
+        assert_eq!(bars[1].open_time, minute(3));
+        assert_eq!(bars[1].high.price, 12.3);
+        assert_eq!(bars[1].low.price, 11.5);
+        assert!(!bars[1].closed);
+    }
+}
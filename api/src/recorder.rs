@@ -0,0 +1,577 @@
+use crate::{
+    Api, Backfill, BackfillError, Candlestick, Closed, Error, History, Interval, Market, Storage,
+    Timestamp, Transform,
+};
+use futures_core::stream::Stream;
+use std::future::poll_fn;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::Poll;
+use tokio::stream::StreamExt;
+use tokio::sync::{broadcast, mpsc};
+
+/// Long-running service that records market data of several markets into the storage.
+///
+/// Recording the candlesticks, trades and order book depth of the exchange lets a dataset
+/// for backtesting accumulate independent of the retention of the exchange.
+///
+/// Only closed candlesticks are written, each of them once. On startup, every stream resumes
+/// after its latest stored candlestick and the candlesticks missed in the meantime are backfilled,
+/// as are gaps that open while recording, for example on reconnects.
+/// Failed history requests are retried with exponential backoff. Gaps that still cannot be
+/// backfilled are skipped, recording continues and the gaps are reported to the sender
+/// given with `gaps`.
+///
+/// Trades and depth snapshots are written as they arrive, trades that arrive while writing
+/// are written together. As there is no history for them,
+/// whatever is missed while disconnected stays missing. Recording them fails with
+/// `Error::Unsupported` for APIs without the respective subscription.
+pub struct Recorder<API, S>
+where
+    API: Api<S> + Send + Sync + 'static,
+    S: Stream<Item = Candlestick> + Unpin + Send + 'static,
+{
+    api: Arc<API>,
+    storage: Arc<Storage>,
+    recordings: Vec<Recording>,
+    /// Time from which streams without stored candlesticks are backfilled.
+    since: Option<Timestamp>,
+    /// Delay before the first retry of a failed history request.
+    backoff: std::time::Duration,
+    gaps: Option<mpsc::UnboundedSender<BackfillError>>,
+    _phantom: std::marker::PhantomData<fn() -> S>,
+}
+
+impl<API, S> Recorder<API, S>
+where
+    API: Api<S> + Send + Sync + 'static,
+    S: Stream<Item = Candlestick> + Unpin + Send + 'static,
+{
+    pub fn new(api: Arc<API>, storage: Arc<Storage>) -> Self {
+        Self {
+            api,
+            storage,
+            recordings: Vec::new(),
+            since: None,
+            backoff: std::time::Duration::from_secs(1),
+            gaps: None,
+            _phantom: std::marker::PhantomData,
+        }
+    }
+
+    /// Adds the candlesticks of the market in the given interval to the recording.
+    pub fn record(self, market: &'static Market, interval: Interval) -> Self {
+        self.add(Recording::Candlesticks(market, interval))
+    }
+
+    /// Adds the trades of the market to the recording.
+    pub fn record_trades(self, market: &'static Market) -> Self {
+        self.add(Recording::Trades(market))
+    }
+
+    /// Adds the order book depth of the market to the recording.
+    pub fn record_depth(self, market: &'static Market) -> Self {
+        self.add(Recording::Depth(market))
+    }
+
+    fn add(mut self, recording: Recording) -> Self {
+        if !self.recordings.contains(&recording) {
+            self.recordings.push(recording);
+        }
+        self
+    }
+
+    /// Backfills streams that have no stored candlesticks yet from the given time on.
+    /// Without it, they are recorded from the first candlestick of their subscription.
+    pub fn since(mut self, since: Timestamp) -> Self {
+        self.since = Some(since);
+        self
+    }
+
+    /// Sets the delay before the first retry of a failed history request, one second by default.
+    /// The delay doubles with every further retry.
+    pub fn backoff(mut self, backoff: std::time::Duration) -> Self {
+        self.backoff = backoff;
+        self
+    }
+
+    /// Reports the gaps that could not be backfilled to the sender.
+    pub fn gaps(mut self, gaps: mpsc::UnboundedSender<BackfillError>) -> Self {
+        self.gaps = Some(gaps);
+        self
+    }
+
+    /// Records all streams until their subscriptions end.
+    /// Fails with the first error of any stream, for example when the storage cannot be written,
+    /// after all other streams were stopped. Dropping the future stops all streams as well.
+    pub async fn run(self) -> Result<(), Error> {
+        let (sender, mut receiver) = mpsc::unbounded_channel();
+        let (stop, _) = broadcast::channel::<()>(1);
+        let mut tasks = Vec::with_capacity(self.recordings.len());
+
+        for &recording in &self.recordings {
+            let api = Arc::clone(&self.api);
+            let storage = Arc::clone(&self.storage);
+            let since = self.since;
+            let backoff = self.backoff;
+            let gaps = self.gaps.clone();
+            let sender = sender.clone();
+            let mut stopped = stop.subscribe();
+            tasks.push(tokio::spawn(async move {
+                tokio::select! {
+                    result = record(api, storage, recording, since, backoff, gaps) => {
+                        sender.send(result).ok();
+                    }
+                    _ = stopped.recv() => {}
+                }
+            }));
+        }
+        drop(sender);
+
+        let mut result = Ok(());
+        while let Some(recorded) = receiver.recv().await {
+            if recorded.is_err() {
+                result = recorded;
+                stop.send(()).ok();
+                break;
+            }
+        }
+        for task in tasks {
+            task.await.ok();
+        }
+
+        result
+    }
+}
+
+/// Data that is recorded from a subscription.
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum Recording {
+    Candlesticks(&'static Market, Interval),
+    Trades(&'static Market),
+    Depth(&'static Market),
+}
+
+/// Number of attempts of a history request before its gap is given up.
+const HISTORY_ATTEMPTS: u32 = 4;
+
+/// Maximum number of trades that are written in one transaction.
+const TRADE_BATCH: usize = 1_000;
+
+async fn record<API, S>(
+    api: Arc<API>,
+    storage: Arc<Storage>,
+    recording: Recording,
+    since: Option<Timestamp>,
+    backoff: std::time::Duration,
+    gaps: Option<mpsc::UnboundedSender<BackfillError>>,
+) -> Result<(), Error>
+where
+    API: Api<S> + Send + Sync + 'static,
+    S: Stream<Item = Candlestick> + Unpin + Send + 'static,
+{
+    match recording {
+        Recording::Candlesticks(market, interval) => {
+            record_candlesticks(api, storage, market, interval, since, backoff, gaps).await
+        }
+        Recording::Trades(market) => {
+            let mut trades = api.subscribe_trades(market).await?;
+            while let Some(trade) = trades.next().await {
+                // Trades that arrived while the previous batch was written are written together.
+                let mut batch = vec![trade];
+                let mut ended = false;
+                while batch.len() < TRADE_BATCH {
+                    match ready(&mut trades).await {
+                        Some(Some(trade)) => batch.push(trade),
+                        Some(None) => {
+                            ended = true;
+                            break;
+                        }
+                        None => break,
+                    }
+                }
+                blocking(&storage, move |storage| storage.insert_trades(&batch)).await?;
+                if ended {
+                    break;
+                }
+            }
+            Ok(())
+        }
+        Recording::Depth(market) => {
+            let mut snapshots = api.subscribe_depth(market).await?;
+            while let Some(depth) = snapshots.next().await {
+                blocking(&storage, move |storage| storage.insert_depth(&depth)).await?;
+            }
+            Ok(())
+        }
+    }
+}
+
+async fn record_candlesticks<API, S>(
+    api: Arc<API>,
+    storage: Arc<Storage>,
+    market: &'static Market,
+    interval: Interval,
+    since: Option<Timestamp>,
+    backoff: std::time::Duration,
+    gaps: Option<mpsc::UnboundedSender<BackfillError>>,
+) -> Result<(), Error>
+where
+    API: Api<S> + Send + Sync + 'static,
+    S: Stream<Item = Candlestick> + Unpin + Send + 'static,
+{
+    let latest = blocking(&storage, move |storage| storage.latest(market, interval)).await?;
+    let expected = match latest {
+        Some(latest) => Some(latest + interval.duration()),
+        None => since.map(|since| interval.open_time(since)),
+    };
+
+    let subscription = api.subscribe(market, interval).await;
+    let mut backfill = Backfill::new(
+        subscription,
+        market,
+        interval,
+        history(api, market, interval, backoff),
+    );
+    if let Some(expected) = expected {
+        backfill = backfill.expecting(expected);
+    }
+    let mut closed = match latest {
+        Some(latest) => Closed::after(latest),
+        None => Closed::new(),
+    };
+
+    while let Some(item) = backfill.next().await {
+        let candlestick = match item {
+            Ok(candlestick) => candlestick,
+            // Candlesticks that the exchange does not have or that could not be fetched
+            // are skipped, so that the stream keeps being recorded.
+            Err(gap) => {
+                if let Some(gaps) = &gaps {
+                    gaps.send(gap).ok();
+                }
+                continue;
+            }
+        };
+        let closed = closed.push(candlestick);
+        if !closed.is_empty() {
+            blocking(&storage, move |storage| {
+                storage.insert_all(interval, &closed)
+            })
+            .await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Runs a storage operation on the threads for blocking tasks,
+/// so that writing to the database does not stall the other streams.
+async fn blocking<T, F>(storage: &Arc<Storage>, operation: F) -> Result<T, Error>
+where
+    T: Send + 'static,
+    F: FnOnce(&Storage) -> Result<T, Error> + Send + 'static,
+{
+    let storage = Arc::clone(storage);
+    tokio::task::spawn_blocking(move || operation(&storage))
+        .await
+        .unwrap_or_else(|error| std::panic::resume_unwind(error.into_panic()))
+}
+
+/// Returns the next item of the stream if it is ready without waiting,
+/// `Some(None)` if the stream ended and `None` if no item is ready yet.
+async fn ready<T>(stream: &mut (impl Stream<Item = T> + Unpin)) -> Option<Option<T>> {
+    poll_fn(|context| match Pin::new(&mut *stream).poll_next(context) {
+        Poll::Ready(item) => Poll::Ready(Some(item)),
+        Poll::Pending => Poll::Ready(None),
+    })
+    .await
+}
+
+/// Fetches the history page by page, as exchanges limit the number of candlesticks per request.
+/// Failed requests are retried with exponential backoff.
+fn history<API, S>(
+    api: Arc<API>,
+    market: &'static Market,
+    interval: Interval,
+    backoff: std::time::Duration,
+) -> History
+where
+    API: Api<S> + Send + Sync + 'static,
+    S: Stream<Item = Candlestick> + Unpin + Send + 'static,
+{
+    Arc::new(move |mut start, end| {
+        let api = Arc::clone(&api);
+        Box::pin(async move {
+            let mut candlesticks = Vec::new();
+            while start <= end {
+                let mut delay = backoff;
+                let mut attempt = 1;
+                let page = loop {
+                    match api.history(market, interval, start, end).await {
+                        Ok(page) => break page,
+                        Err(error) if attempt == HISTORY_ATTEMPTS => return Err(error),
+                        Err(_) => {
+                            tokio::time::delay_for(delay).await;
+                            delay *= 2;
+                            attempt += 1;
+                        }
+                    }
+                };
+                match page.iter().map(|candlestick| candlestick.open_time).max() {
+                    Some(latest) if latest >= start => start = latest + interval.duration(),
+                    _ => break,
+                }
+                candlesticks.extend(page);
+            }
+
+            Ok(candlesticks)
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{candlestick, ethbtc, minute, price, trade};
+    use crate::{
+        Asset, Depth, DepthStream, Level, Order, OrderError, OrderResponse, Price, Quantity,
+        Subscription, TradeStream,
+    };
+    use futures::stream::{self, BoxStream, StreamExt};
+    use std::collections::HashSet;
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::time::Duration;
+
+    fn forming(market: &'static Market, minute: u64) -> Candlestick {
+        Candlestick {
+            closed: false,
+            ..candlestick(market, minute)
+        }
+    }
+
+    struct Mock {
+        markets: HashSet<&'static Market>,
+        assets: HashSet<&'static Asset>,
+        requests: AtomicU64,
+    }
+
+    #[async_trait::async_trait]
+    impl Api<BoxStream<'static, Candlestick>> for Mock {
+        async fn update(&mut self) -> Result<(), Error> {
+            Ok(())
+        }
+
+        fn get_markets(&self) -> &HashSet<&'static Market> {
+            &self.markets
+        }
+
+        fn get_assets(&self) -> &HashSet<&'static Asset> {
+            &self.assets
+        }
+
+        /// Replays a closed candlestick and skips the one of minute 4 in one minute intervals,
+        /// starts late in five minute intervals and never provides hourly candlesticks.
+        async fn subscribe(
+            &self,
+            market: &'static Market,
+            interval: Interval,
+        ) -> Subscription<BoxStream<'static, Candlestick>> {
+            let candlesticks = match interval {
+                Interval::I1m => stream::iter(vec![
+                    forming(market, 3),
+                    candlestick(market, 3),
+                    candlestick(market, 3),
+                    candlestick(market, 5),
+                    forming(market, 6),
+                ])
+                .boxed(),
+                Interval::I5m => stream::iter(vec![Candlestick {
+                    close_time: minute(15) - chrono::Duration::milliseconds(1),
+                    ..candlestick(market, 10)
+                }])
+                .boxed(),
+                _ => stream::pending().boxed(),
+            };
+            Subscription::new(market, interval, candlesticks)
+        }
+
+        /// Returns at most two candlesticks per request, fails every other request
+        /// and always fails for five minute intervals.
+        async fn history(
+            &self,
+            market: &'static Market,
+            interval: Interval,
+            start: Timestamp,
+            end: Timestamp,
+        ) -> Result<Vec<Candlestick>, Error> {
+            let request = self.requests.fetch_add(1, Ordering::SeqCst);
+            if interval == Interval::I5m || request.is_multiple_of(2) {
+                return Err(Error::ConnectionError);
+            }
+
+            Ok((0..6)
+                .map(|minute| candlestick(market, minute))
+                .filter(|candlestick| {
+                    candlestick.open_time >= start && candlestick.open_time <= end
+                })
+                .take(2)
+                .collect())
+        }
+
+        /// Replays the first trade, as after a reconnect.
+        async fn subscribe_trades(&self, market: &'static Market) -> Result<TradeStream, Error> {
+            Ok(stream::iter(vec![
+                trade(market, 1, 1_000, 0.05, 1.0),
+                trade(market, 2, 2_000, 0.06, 2.0),
+                trade(market, 1, 1_000, 0.05, 1.0),
+            ])
+            .boxed())
+        }
+
+        async fn subscribe_depth(&self, market: &'static Market) -> Result<DepthStream, Error> {
+            let level = |price: f64, quantity| Level {
+                price: Price { price, market },
+                quantity: Quantity {
+                    quantity,
+                    asset: market.base,
+                },
+            };
+            Ok(stream::iter(vec![Depth {
+                market,
+                time: Timestamp::from_millis(1_000),
+                bids: vec![level(0.05, 1.0), level(0.04, 2.0)],
+                asks: vec![level(0.06, 3.0)],
+            }])
+            .boxed())
+        }
+
+        async fn order(&mut self, _order: Order) -> Result<OrderResponse, OrderError> {
+            Err(OrderError::Other(Error::ConnectionError))
+        }
+    }
+
+    fn mock() -> Arc<Mock> {
+        Arc::new(Mock {
+            markets: HashSet::new(),
+            assets: HashSet::new(),
+            requests: AtomicU64::new(0),
+        })
+    }
+
+    #[tokio::test]
+    async fn test_recorder() {
+        let storage = Arc::new(Storage::in_memory().unwrap());
+        storage
+            .insert(Interval::I1m, &candlestick(ethbtc(), 0))
+            .unwrap();
+
+        Recorder::new(mock(), Arc::clone(&storage))
+            .record(ethbtc(), Interval::I1m)
+            .backoff(Duration::from_millis(1))
+            .run()
+            .await
+            .unwrap();
+
+        let stored = storage
+            .candlesticks(ethbtc(), Interval::I1m, minute(0), minute(10))
+            .unwrap();
+        let open_times: Vec<Timestamp> = stored
+            .iter()
+            .map(|candlestick| candlestick.open_time)
+            .collect();
+        assert_eq!(open_times, (0..6).map(minute).collect::<Vec<Timestamp>>());
+        assert!(stored.iter().all(|candlestick| candlestick.closed));
+    }
+
+    #[tokio::test]
+    async fn test_recorder_trades_and_depth() {
+        let storage = Arc::new(Storage::in_memory().unwrap());
+        Recorder::new(mock(), Arc::clone(&storage))
+            .record_trades(ethbtc())
+            .record_depth(ethbtc())
+            .run()
+            .await
+            .unwrap();
+
+        let trades = storage
+            .trades(
+                ethbtc(),
+                Timestamp::from_millis(0),
+                Timestamp::from_millis(2_000),
+            )
+            .unwrap();
+        assert_eq!(
+            trades.iter().map(|trade| trade.id).collect::<Vec<u64>>(),
+            vec![1, 2]
+        );
+        assert_eq!(trades[1].price, price(0.06));
+
+        let snapshots = storage
+            .depth(
+                ethbtc(),
+                Timestamp::from_millis(0),
+                Timestamp::from_millis(2_000),
+            )
+            .unwrap();
+        assert_eq!(snapshots.len(), 1);
+        assert_eq!(
+            snapshots[0]
+                .bids
+                .iter()
+                .map(|level| level.price.price)
+                .collect::<Vec<f64>>(),
+            vec![0.05, 0.04]
+        );
+        assert_eq!(snapshots[0].asks[0].quantity.quantity, 3.0);
+    }
+
+    #[tokio::test]
+    async fn test_recorder_gaps() {
+        // The history of the gap before the first five minute candlestick cannot be fetched,
+        // which is reported while the candlestick is recorded nevertheless.
+        let storage = Arc::new(Storage::in_memory().unwrap());
+        let (sender, mut gaps) = mpsc::unbounded_channel();
+        Recorder::new(mock(), Arc::clone(&storage))
+            .record(ethbtc(), Interval::I5m)
+            .since(minute(0))
+            .backoff(Duration::from_millis(1))
+            .gaps(sender)
+            .run()
+            .await
+            .unwrap();
+
+        match gaps.recv().await {
+            Some(BackfillError::History(gap, Error::ConnectionError)) => {
+                assert_eq!(gap.start, minute(0));
+                assert_eq!(gap.end, minute(5));
+            }
+            gap => panic!("unexpected gap {:?}", gap),
+        }
+        assert!(gaps.recv().await.is_none());
+        assert_eq!(
+            storage.latest(ethbtc(), Interval::I5m).unwrap(),
+            Some(minute(10))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_recorder_stops_on_error() {
+        // The trades cannot be written, which stops the hourly stream that would never end otherwise.
+        let api = mock();
+        let storage = Arc::new(Storage::in_memory().unwrap());
+        storage
+            .connection()
+            .execute_batch("DROP TABLE trades")
+            .unwrap();
+        let run = Recorder::new(Arc::clone(&api), storage)
+            .record(ethbtc(), Interval::I1h)
+            .record_trades(ethbtc())
+            .run();
+
+        let result = tokio::time::timeout(Duration::from_secs(10), run).await;
+        assert!(matches!(result, Ok(Err(Error::DatabaseError))));
+        // All tasks ended and released the API.
+        assert_eq!(Arc::strong_count(&api), 1);
+    }
+}
@@ -0,0 +1,187 @@
+use crate::{Candlestick, Monetary, Price, Quantity, Transform};
+
+/// Size of a Renko brick.
+#[derive(Debug, Copy, Clone)]
+pub enum BoxSize {
+    /// A fixed price difference.
+    Fixed(Monetary),
+    /// The average true range over the given number of candlesticks.
+    Atr(usize),
+}
+
+/// Transforms candlesticks into Renko bricks based on their close price.
+/// Candlesticks that are not closed yet are ignored.
+#[derive(Debug, Clone)]
+pub struct Renko {
+    box_size: BoxSize,
+    atr: Monetary,
+    ranges: usize,
+    previous_close: Option<Monetary>,
+    /// Top and bottom of the latest brick.
+    brick: Option<(Monetary, Monetary)>,
+    /// Aggregate of all candlesticks since the latest brick.
+    pending: Option<Candlestick>,
+}
+
+impl Renko {
+    pub fn new(box_size: BoxSize) -> Self {
+        Self {
+            box_size,
+            atr: 0.0,
+            ranges: 0,
+            previous_close: None,
+            brick: None,
+            pending: None,
+        }
+    }
+
+    fn update_atr(&mut self, candlestick: &Candlestick) {
+        let high = candlestick.high.price;
+        let low = candlestick.low.price;
+        let true_range = match self.previous_close {
+            Some(close) => (high - low)
+                .max((high - close).abs())
+                .max((low - close).abs()),
+            None => high - low,
+        };
+        self.previous_close = Some(candlestick.close.price);
+
+        let period = match self.box_size {
+            BoxSize::Atr(period) => period.max(1),
+            BoxSize::Fixed(_) => return,
+        };
+        self.ranges += 1;
+        let weight = self.ranges.min(period) as Monetary;
+        self.atr += (true_range - self.atr) / weight;
+    }
+
+    fn box_size(&self) -> Option<Monetary> {
+        let size = match self.box_size {
+            BoxSize::Fixed(size) => size,
+            BoxSize::Atr(period) if self.ranges >= period => self.atr,
+            BoxSize::Atr(_) => return None,
+        };
+
+        if size > 0.0 {
+            Some(size)
+        } else {
+            None
+        }
+    }
+
+    fn brick(&mut self, candlestick: &Candlestick, open: Monetary, close: Monetary) -> Candlestick {
+        let market = candlestick.market;
+        // Bricks after the first one that are formed by the same candlestick are empty.
+        let pending = self.pending.take().unwrap_or_else(|| Candlestick {
+            volume: Quantity::zero(market.base),
+            quote_volume: Quantity::zero(market.quote),
+            taker_buy_base_volume: Quantity::zero(market.base),
+            taker_buy_quote_volume: Quantity::zero(market.quote),
+            trades: 0,
+            ..*candlestick
+        });
+
+        Candlestick {
+            close_time: candlestick.close_time,
+            high: Price {
+                price: open.max(close),
+                market,
+            },
+            low: Price {
+                price: open.min(close),
+                market,
+            },
+            open: Price {
+                price: open,
+                market,
+            },
+            close: Price {
+                price: close,
+                market,
+            },
+            closed: true,
+            ..pending
+        }
+    }
+}
+
+impl Transform for Renko {
+    fn push(&mut self, candlestick: Candlestick) -> Vec<Candlestick> {
+        let mut bricks = Vec::new();
+
+        if !candlestick.closed {
+            return bricks;
+        }
+
+        self.update_atr(&candlestick);
+        self.pending = Some(match self.pending {
+            Some(pending) => pending.merge(candlestick),
+            None => candlestick,
+        });
+
+        let close = candlestick.close.price;
+        let (mut top, mut bottom) = *self.brick.get_or_insert((close, close));
+        let size = match self.box_size() {
+            Some(size) => size,
+            None => return bricks,
+        };
+
+        while close >= top + size {
+            bricks.push(self.brick(&candlestick, top, top + size));
+            bottom = top;
+            top += size;
+        }
+        while close <= bottom - size {
+            bricks.push(self.brick(&candlestick, bottom, bottom - size));
+            top = bottom;
+            bottom -= size;
+        }
+        self.brick = Some((top, bottom));
+
+        bricks
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{candlestick, ethbtc, price};
+
+    fn bricks() -> Vec<Candlestick> {
+        let candlesticks = [10.0, 13.5, 12.0, 9.5, 7.0]
+            .iter()
+            .enumerate()
+            .map(|(i, &close)| Candlestick {
+                high: price(close),
+                low: price(close),
+                open: price(close),
+                close: price(close),
+                ..candlestick(ethbtc(), i as u64)
+            });
+
+        crate::transform(candlesticks, Renko::new(BoxSize::Fixed(1.0)))
+    }
+
+    #[test]
+    fn test_renko() {
+        let closes: Vec<Monetary> = bricks().iter().map(|brick| brick.close.price).collect();
+        assert_eq!(closes, vec![11.0, 12.0, 13.0, 11.0, 10.0, 9.0, 8.0, 7.0]);
+    }
+
+    #[test]
+    fn test_renko_volume() {
+        // The volume of a candlestick goes into the first brick it forms.
+        let bricks = bricks();
+        assert_eq!(bricks[0].volume.quantity, 2.0);
+        assert_eq!(bricks[1].volume.quantity, 0.0);
+        assert_eq!(bricks[3].volume.quantity, 2.0);
+    }
+
+    #[test]
+    fn test_renko_reversal() {
+        // A reversal needs to move two bricks, so it opens at the bottom of the latest brick.
+        let bricks = bricks();
+        assert_eq!(bricks[3].open.price, 12.0);
+        assert_eq!(bricks[3].close.price, 11.0);
+    }
+}
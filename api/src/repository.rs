@@ -0,0 +1,653 @@
+use crate::storage::market_id;
+use crate::{
+    Asset, Balance, Error, Market, Order, Price, Quantity, Side, Storage, Timestamp, Wallet,
+};
+use rusqlite::{params, OptionalExtension, Row};
+
+/// State of an order on the exchange.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum OrderState {
+    New,
+    PartiallyFilled,
+    Filled,
+    Canceled,
+    Rejected,
+    Expired,
+}
+
+impl OrderState {
+    fn name(&self) -> &'static str {
+        match self {
+            OrderState::New => "NEW",
+            OrderState::PartiallyFilled => "PARTIALLY_FILLED",
+            OrderState::Filled => "FILLED",
+            OrderState::Canceled => "CANCELED",
+            OrderState::Rejected => "REJECTED",
+            OrderState::Expired => "EXPIRED",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "NEW" => OrderState::New,
+            "PARTIALLY_FILLED" => OrderState::PartiallyFilled,
+            "FILLED" => OrderState::Filled,
+            "CANCELED" => OrderState::Canceled,
+            "REJECTED" => OrderState::Rejected,
+            "EXPIRED" => OrderState::Expired,
+            _ => return None,
+        })
+    }
+}
+
+/// Stored order together with its latest state.
+#[derive(Debug, Clone)]
+pub struct OrderRecord {
+    pub id: i64,
+    pub client_id: String,
+    pub exchange_id: Option<String>,
+    pub side: Side,
+    pub quantity: Quantity,
+    /// Limit price of the order, or the stop price of stop orders.
+    pub price: Price,
+    /// Stop price of OCO and stop orders.
+    pub stop_price: Option<Price>,
+    pub executed_quantity: Quantity,
+    pub state: OrderState,
+    /// Time at which the order was created.
+    pub time: Timestamp,
+}
+
+/// Single execution of an order.
+#[derive(Debug, Copy, Clone)]
+pub struct Fill {
+    pub price: Price,
+    pub quantity: Quantity,
+    pub commission: Quantity,
+    pub time: Timestamp,
+}
+
+/// Change of the state of an order as reported by the exchange.
+#[derive(Debug, Clone)]
+pub struct OrderUpdate {
+    /// Id that the exchange assigned to the order.
+    pub exchange_id: String,
+    pub state: OrderState,
+    /// Executed quantity of the order in total, in the asset of its quantity.
+    pub executed_quantity: Quantity,
+    /// Execution that caused the update, if any.
+    pub fill: Option<Fill>,
+    pub time: Timestamp,
+}
+
+/// Position that was entered by an order and is left by another one.
+#[derive(Debug, Copy, Clone)]
+pub struct PositionRecord {
+    pub id: i64,
+    pub entry_order_id: i64,
+    /// Order that leaves the position, `None` until it is placed.
+    pub exit_order_id: Option<i64>,
+    pub take_profit: Price,
+    pub stop_loss: Price,
+    pub opened_time: Timestamp,
+    pub closed_time: Option<Timestamp>,
+}
+
+impl Storage {
+    pub fn orders(&self) -> Orders<'_> {
+        Orders { storage: self }
+    }
+
+    pub fn fills(&self) -> Fills<'_> {
+        Fills { storage: self }
+    }
+
+    pub fn positions(&self) -> Positions<'_> {
+        Positions { storage: self }
+    }
+
+    pub fn balances(&self) -> Balances<'_> {
+        Balances { storage: self }
+    }
+}
+
+fn side_name(side: Side) -> &'static str {
+    match side {
+        Side::Buy => "BUY",
+        Side::Sell => "SELL",
+    }
+}
+
+fn invalid(column: usize, value: String) -> rusqlite::Error {
+    rusqlite::Error::InvalidColumnType(column, value, rusqlite::types::Type::Text)
+}
+
+/// Repository of orders and their state transitions.
+pub struct Orders<'a> {
+    storage: &'a Storage,
+}
+
+impl<'a> Orders<'a> {
+    /// Inserts a new order and returns its id.
+    pub fn insert(&self, client_id: &str, order: &Order, time: Timestamp) -> Result<i64, Error> {
+        let (side, quantity, price, stop_price) = match order {
+            Order::Limit(side, quantity, price) => (side, quantity, price, None),
+            Order::Stop(side, quantity, stop_price) => {
+                (side, quantity, stop_price, Some(stop_price.price))
+            }
+            Order::Oco(side, quantity, stop_price, price) => {
+                (side, quantity, price, Some(stop_price.price))
+            }
+        };
+
+        let mut connection = self.storage.connection();
+        let transaction = connection.transaction()?;
+        let market_id = market_id(&transaction, price.market)?;
+        transaction.execute(
+            "INSERT INTO orders (
+                client_id, market_id, side, quantity, quantity_asset, price, stop_price,
+                executed_quantity, state, time
+            ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, 0, ?8, ?9)",
+            params![
+                client_id,
+                market_id,
+                side_name(*side),
+                quantity.quantity,
+                quantity.asset.to_string(),
+                price.price,
+                stop_price,
+                OrderState::New.name(),
+                time.millis() as i64,
+            ],
+        )?;
+        let id = transaction.last_insert_rowid();
+        transaction.execute(
+            "INSERT INTO order_transitions (order_id, state, time) VALUES (?1, ?2, ?3)",
+            params![id, OrderState::New.name(), time.millis() as i64],
+        )?;
+        transaction.commit()?;
+
+        Ok(id)
+    }
+
+    /// Stores the id that the exchange assigned to the order.
+    pub fn set_exchange_id(&self, id: i64, exchange_id: &str) -> Result<(), Error> {
+        self.storage.connection().execute(
+            "UPDATE orders SET exchange_id = ?2 WHERE id = ?1",
+            params![id, exchange_id],
+        )?;
+
+        Ok(())
+    }
+
+    /// Records a state transition of the order, for example as observed by an order watcher.
+    pub fn transition(
+        &self,
+        id: i64,
+        state: OrderState,
+        executed_quantity: Quantity,
+        time: Timestamp,
+    ) -> Result<(), Error> {
+        let mut connection = self.storage.connection();
+        let transaction = connection.transaction()?;
+        transaction.execute(
+            "UPDATE orders SET state = ?2, executed_quantity = ?3 WHERE id = ?1",
+            params![id, state.name(), executed_quantity.quantity],
+        )?;
+        transaction.execute(
+            "INSERT INTO order_transitions (order_id, state, time) VALUES (?1, ?2, ?3)",
+            params![id, state.name(), time.millis() as i64],
+        )?;
+        transaction.commit()?;
+
+        Ok(())
+    }
+
+    pub fn get(&self, id: i64) -> Result<Option<OrderRecord>, Error> {
+        self.find("o.id = ?1", &id)
+    }
+
+    pub fn get_by_client_id(&self, client_id: &str) -> Result<Option<OrderRecord>, Error> {
+        self.find("o.client_id = ?1", &client_id)
+    }
+
+    pub fn get_by_exchange_id(&self, exchange_id: &str) -> Result<Option<OrderRecord>, Error> {
+        self.find("o.exchange_id = ?1", &exchange_id)
+    }
+
+    /// Records an update reported by the exchange with its fill and returns the id of the order,
+    /// or `None` if no order with its exchange id is stored.
+    /// The state is only recorded if it differs from the stored one,
+    /// as it may also have been reported by the response to the order.
+    /// A filled order closes the position it leaves.
+    pub fn apply(&self, update: &OrderUpdate) -> Result<Option<i64>, Error> {
+        let order = match self.get_by_exchange_id(&update.exchange_id)? {
+            Some(order) => order,
+            None => return Ok(None),
+        };
+
+        if let Some(fill) = &update.fill {
+            self.storage.fills().insert(order.id, fill)?;
+        }
+        if order.state != update.state
+            || order.executed_quantity.quantity != update.executed_quantity.quantity
+        {
+            self.transition(
+                order.id,
+                update.state,
+                update.executed_quantity,
+                update.time,
+            )?;
+        }
+        if update.state == OrderState::Filled {
+            self.storage
+                .positions()
+                .close_by_exit(order.id, update.time)?;
+        }
+
+        Ok(Some(order.id))
+    }
+
+    /// Returns all states the order went through with the times of the transitions.
+    pub fn transitions(&self, id: i64) -> Result<Vec<(OrderState, Timestamp)>, Error> {
+        let connection = self.storage.connection();
+        let mut select = connection.prepare_cached(
+            "SELECT state, time FROM order_transitions WHERE order_id = ?1 ORDER BY rowid",
+        )?;
+        let rows = select.query_map(params![id], |row| {
+            let state: String = row.get(0)?;
+            Ok((
+                OrderState::from_name(&state).ok_or_else(|| invalid(0, state))?,
+                Timestamp::from_millis(row.get::<_, i64>(1)? as u64),
+            ))
+        })?;
+
+        Ok(rows.collect::<Result<Vec<_>, rusqlite::Error>>()?)
+    }
+
+    fn find(
+        &self,
+        condition: &str,
+        parameter: &dyn rusqlite::ToSql,
+    ) -> Result<Option<OrderRecord>, Error> {
+        let connection = self.storage.connection();
+        let mut select = connection.prepare_cached(&format!(
+            "SELECT
+                o.id, o.client_id, o.exchange_id, o.side, o.quantity, o.quantity_asset,
+                o.price, o.stop_price, o.executed_quantity, o.state, o.time, m.base, m.quote
+            FROM orders o JOIN markets m ON o.market_id = m.id
+            WHERE {}",
+            condition
+        ))?;
+
+        Ok(select.query_row(&[parameter], order_record).optional()?)
+    }
+}
+
+fn order_record(row: &Row) -> Result<OrderRecord, rusqlite::Error> {
+    let base: String = row.get(11)?;
+    let quote: String = row.get(12)?;
+    let market = Market::intern(Asset::intern(&base), Asset::intern(&quote));
+    let asset = Asset::intern(&row.get::<_, String>(5)?);
+    let side: String = row.get(3)?;
+    let state: String = row.get(9)?;
+
+    Ok(OrderRecord {
+        id: row.get(0)?,
+        client_id: row.get(1)?,
+        exchange_id: row.get(2)?,
+        side: match side.as_str() {
+            "BUY" => Side::Buy,
+            "SELL" => Side::Sell,
+            _ => return Err(invalid(3, side)),
+        },
+        quantity: Quantity {
+            quantity: row.get(4)?,
+            asset,
+        },
+        price: Price {
+            price: row.get(6)?,
+            market,
+        },
+        stop_price: row
+            .get::<_, Option<f64>>(7)?
+            .map(|price| Price { price, market }),
+        executed_quantity: Quantity {
+            quantity: row.get(8)?,
+            asset,
+        },
+        state: OrderState::from_name(&state).ok_or_else(|| invalid(9, state))?,
+        time: Timestamp::from_millis(row.get::<_, i64>(10)? as u64),
+    })
+}
+
+/// Repository of the fills of orders.
+pub struct Fills<'a> {
+    storage: &'a Storage,
+}
+
+impl<'a> Fills<'a> {
+    /// Inserts a fill of the order and returns its id.
+    pub fn insert(&self, order_id: i64, fill: &Fill) -> Result<i64, Error> {
+        let connection = self.storage.connection();
+        connection.execute(
+            "INSERT INTO fills (order_id, price, quantity, commission, commission_asset, time)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![
+                order_id,
+                fill.price.price,
+                fill.quantity.quantity,
+                fill.commission.quantity,
+                fill.commission.asset.to_string(),
+                fill.time.millis() as i64,
+            ],
+        )?;
+
+        Ok(connection.last_insert_rowid())
+    }
+
+    /// Returns all fills of the order in the order they were inserted.
+    pub fn of_order(&self, order_id: i64) -> Result<Vec<Fill>, Error> {
+        let order = self
+            .storage
+            .orders()
+            .get(order_id)?
+            .ok_or(Error::DatabaseError)?;
+        let connection = self.storage.connection();
+        let mut select = connection.prepare_cached(
+            "SELECT price, quantity, commission, commission_asset, time
+            FROM fills WHERE order_id = ?1 ORDER BY id",
+        )?;
+        let rows = select.query_map(params![order_id], |row| {
+            Ok(Fill {
+                price: Price {
+                    price: row.get(0)?,
+                    market: order.price.market,
+                },
+                quantity: Quantity {
+                    quantity: row.get(1)?,
+                    asset: order.quantity.asset,
+                },
+                commission: Quantity {
+                    quantity: row.get(2)?,
+                    asset: Asset::intern(&row.get::<_, String>(3)?),
+                },
+                time: Timestamp::from_millis(row.get::<_, i64>(4)? as u64),
+            })
+        })?;
+
+        Ok(rows.collect::<Result<Vec<Fill>, rusqlite::Error>>()?)
+    }
+}
+
+/// Repository of positions, which link their entry and exit orders.
+pub struct Positions<'a> {
+    storage: &'a Storage,
+}
+
+impl<'a> Positions<'a> {
+    /// Inserts a position that was entered by the order and returns its id.
+    pub fn open(
+        &self,
+        entry_order_id: i64,
+        take_profit: Price,
+        stop_loss: Price,
+        time: Timestamp,
+    ) -> Result<i64, Error> {
+        let connection = self.storage.connection();
+        connection.execute(
+            "INSERT INTO positions (entry_order_id, take_profit, stop_loss, opened_time)
+            VALUES (?1, ?2, ?3, ?4)",
+            params![
+                entry_order_id,
+                take_profit.price,
+                stop_loss.price,
+                time.millis() as i64
+            ],
+        )?;
+
+        Ok(connection.last_insert_rowid())
+    }
+
+    /// Links the open position with the order that leaves it.
+    pub fn leave(&self, id: i64, exit_order_id: i64) -> Result<(), Error> {
+        self.storage.connection().execute(
+            "UPDATE positions SET exit_order_id = ?2 WHERE id = ?1",
+            params![id, exit_order_id],
+        )?;
+
+        Ok(())
+    }
+
+    /// Closes the open position that is left by the order, if there is one.
+    pub fn close_by_exit(&self, exit_order_id: i64, time: Timestamp) -> Result<(), Error> {
+        self.storage.connection().execute(
+            "UPDATE positions SET closed_time = ?2
+            WHERE exit_order_id = ?1 AND closed_time IS NULL",
+            params![exit_order_id, time.millis() as i64],
+        )?;
+
+        Ok(())
+    }
+
+    /// Links the position with the order that left it and closes it.
+    pub fn close(&self, id: i64, exit_order_id: i64, time: Timestamp) -> Result<(), Error> {
+        self.storage.connection().execute(
+            "UPDATE positions SET exit_order_id = ?2, closed_time = ?3 WHERE id = ?1",
+            params![id, exit_order_id, time.millis() as i64],
+        )?;
+
+        Ok(())
+    }
+
+    pub fn get(&self, id: i64) -> Result<Option<PositionRecord>, Error> {
+        let connection = self.storage.connection();
+        let mut select = connection.prepare_cached(
+            "SELECT
+                p.id, p.entry_order_id, p.exit_order_id, p.take_profit, p.stop_loss,
+                p.opened_time, p.closed_time, m.base, m.quote
+            FROM positions p
+                JOIN orders o ON p.entry_order_id = o.id
+                JOIN markets m ON o.market_id = m.id
+            WHERE p.id = ?1",
+        )?;
+
+        Ok(select
+            .query_row(params![id], |row| {
+                let base: String = row.get(7)?;
+                let quote: String = row.get(8)?;
+                let market = Market::intern(Asset::intern(&base), Asset::intern(&quote));
+
+                Ok(PositionRecord {
+                    id: row.get(0)?,
+                    entry_order_id: row.get(1)?,
+                    exit_order_id: row.get(2)?,
+                    take_profit: Price {
+                        price: row.get(3)?,
+                        market,
+                    },
+                    stop_loss: Price {
+                        price: row.get(4)?,
+                        market,
+                    },
+                    opened_time: Timestamp::from_millis(row.get::<_, i64>(5)? as u64),
+                    closed_time: row
+                        .get::<_, Option<i64>>(6)?
+                        .map(|time| Timestamp::from_millis(time as u64)),
+                })
+            })
+            .optional()?)
+    }
+}
+
+/// Repository of snapshots of the balances of a wallet.
+pub struct Balances<'a> {
+    storage: &'a Storage,
+}
+
+impl<'a> Balances<'a> {
+    /// Stores the balances of the wallet at the given time.
+    pub fn snapshot(&self, wallet: &Wallet, time: Timestamp) -> Result<(), Error> {
+        let mut connection = self.storage.connection();
+        let transaction = connection.transaction()?;
+        for (asset, balance) in wallet.balances() {
+            transaction.execute(
+                "INSERT OR REPLACE INTO balances (time, asset, free, locked)
+                VALUES (?1, ?2, ?3, ?4)",
+                params![
+                    time.millis() as i64,
+                    asset.to_string(),
+                    balance.free.quantity,
+                    balance.locked.quantity
+                ],
+            )?;
+        }
+        transaction.commit()?;
+
+        Ok(())
+    }
+
+    /// Returns the latest snapshot that was taken at or before the given time.
+    pub fn at(&self, time: Timestamp) -> Result<Option<(Timestamp, Wallet)>, Error> {
+        let connection = self.storage.connection();
+        let snapshot: Option<i64> = connection
+            .query_row(
+                "SELECT MAX(time) FROM balances WHERE time <= ?1",
+                params![time.millis() as i64],
+                |row| row.get(0),
+            )
+            .optional()?
+            .flatten();
+        let snapshot = match snapshot {
+            Some(snapshot) => snapshot,
+            None => return Ok(None),
+        };
+
+        let mut select = connection
+            .prepare_cached("SELECT asset, free, locked FROM balances WHERE time = ?1")?;
+        let mut wallet = Wallet::new();
+        let rows = select.query_map(params![snapshot], |row| {
+            let asset = Asset::intern(&row.get::<_, String>(0)?);
+            Ok(Balance {
+                free: Quantity {
+                    quantity: row.get(1)?,
+                    asset,
+                },
+                locked: Quantity {
+                    quantity: row.get(2)?,
+                    asset,
+                },
+            })
+        })?;
+        for balance in rows {
+            wallet.set(balance?).map_err(|_| Error::DatabaseError)?;
+        }
+
+        Ok(Some((Timestamp::from_millis(snapshot as u64), wallet)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_repositories() {
+        let (eth, btc, bnb) = (
+            Asset::intern("ETH"),
+            Asset::intern("BTC"),
+            Asset::intern("BNB"),
+        );
+        let market = Market::intern(eth, btc);
+        let price = |price| Price { price, market };
+        let quantity = |quantity, asset| Quantity { quantity, asset };
+        let time = Timestamp::from_millis;
+
+        let storage = Storage::in_memory().unwrap();
+        assert_eq!(storage.version().unwrap(), 3);
+
+        let orders = storage.orders();
+        let entry = orders
+            .insert(
+                "entry",
+                &Order::Limit(Side::Buy, quantity(2.0, eth), price(0.05)),
+                time(1),
+            )
+            .unwrap();
+        orders.set_exchange_id(entry, "42").unwrap();
+        orders
+            .transition(
+                entry,
+                OrderState::PartiallyFilled,
+                quantity(1.0, eth),
+                time(2),
+            )
+            .unwrap();
+        orders
+            .transition(entry, OrderState::Filled, quantity(2.0, eth), time(3))
+            .unwrap();
+        let exit = orders
+            .insert(
+                "exit",
+                &Order::Oco(Side::Sell, quantity(2.0, eth), price(0.04), price(0.06)),
+                time(4),
+            )
+            .unwrap();
+
+        let record = orders.get_by_client_id("entry").unwrap().unwrap();
+        assert_eq!(record.id, entry);
+        assert_eq!(record.exchange_id.as_deref(), Some("42"));
+        assert_eq!(record.side, Side::Buy);
+        assert_eq!(record.executed_quantity, quantity(2.0, eth));
+        assert_eq!(record.state, OrderState::Filled);
+        assert_eq!(
+            orders.transitions(entry).unwrap(),
+            vec![
+                (OrderState::New, time(1)),
+                (OrderState::PartiallyFilled, time(2)),
+                (OrderState::Filled, time(3)),
+            ]
+        );
+        let record = orders.get(exit).unwrap().unwrap();
+        assert_eq!(record.price, price(0.06));
+        assert_eq!(record.stop_price, Some(price(0.04)));
+
+        for fill_time in 2..4 {
+            storage
+                .fills()
+                .insert(
+                    entry,
+                    &Fill {
+                        price: price(0.05),
+                        quantity: quantity(1.0, eth),
+                        commission: quantity(0.001, bnb),
+                        time: time(fill_time),
+                    },
+                )
+                .unwrap();
+        }
+        let fills = storage.fills().of_order(entry).unwrap();
+        assert_eq!(fills.len(), 2);
+        assert_eq!(fills[1].commission, quantity(0.001, bnb));
+
+        let position = storage
+            .positions()
+            .open(entry, price(0.06), price(0.04), time(3))
+            .unwrap();
+        storage.positions().close(position, exit, time(5)).unwrap();
+        let position = storage.positions().get(position).unwrap().unwrap();
+        assert_eq!(position.exit_order_id, Some(exit));
+        assert_eq!(position.closed_time, Some(time(5)));
+
+        let mut wallet = Wallet::new();
+        wallet.deposit(quantity(1.0, btc)).unwrap();
+        storage.balances().snapshot(&wallet, time(1)).unwrap();
+        wallet.deposit(quantity(2.0, eth)).unwrap();
+        storage.balances().snapshot(&wallet, time(3)).unwrap();
+        assert!(storage.balances().at(time(0)).unwrap().is_none());
+        let (snapshot, stored) = storage.balances().at(time(2)).unwrap().unwrap();
+        assert_eq!(snapshot, time(1));
+        assert_eq!(stored.total(btc), quantity(1.0, btc));
+        assert_eq!(stored.total(eth), quantity(0.0, eth));
+    }
+}
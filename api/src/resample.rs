@@ -0,0 +1,195 @@
+use crate::{Candlestick, Interval, Timestamp, Transform, Transformed};
+
+/// Aggregates candlesticks into candlesticks of a coarser interval.
+///
+/// Candlesticks have to be pushed in order of their open time, older ones are ignored.
+/// A candlestick with the same open time as the previous one is treated as an update and replaces it.
+/// A bucket is complete once the closed candlestick at its end arrives.
+/// Buckets without any input are skipped, buckets with missing inputs are aggregated from the
+/// candlesticks that are available.
+#[derive(Debug, Clone)]
+pub struct Resampler {
+    interval: Interval,
+    /// Aggregate of all candlesticks in the current bucket except the latest one.
+    base: Option<Candlestick>,
+    /// Latest candlestick in the current bucket.
+    latest: Option<Candlestick>,
+    /// Open time of the last bucket that was emitted.
+    emitted: Option<Timestamp>,
+}
+
+impl Resampler {
+    pub fn new(interval: Interval) -> Self {
+        Self {
+            interval,
+            base: None,
+            latest: None,
+            emitted: None,
+        }
+    }
+}
+
+impl Transform for Resampler {
+    fn push(&mut self, candlestick: Candlestick) -> Vec<Candlestick> {
+        let mut completed = Vec::new();
+        let bucket = self.interval.open_time(candlestick.open_time);
+
+        if let Some(emitted) = self.emitted {
+            if bucket <= emitted {
+                return completed;
+            }
+        }
+
+        if let Some(latest) = self.latest {
+            let current = self.interval.open_time(latest.open_time);
+
+            if bucket < current || candlestick.open_time < latest.open_time {
+                return completed;
+            }
+
+            if bucket > current {
+                completed.extend(self.flush());
+            } else if candlestick.open_time > latest.open_time {
+                self.base = Some(match self.base {
+                    Some(base) => base.merge(latest),
+                    None => latest,
+                });
+            }
+        }
+
+        self.latest = Some(candlestick);
+
+        if candlestick.closed && candlestick.close_time >= self.interval.close_time(bucket) {
+            completed.extend(self.flush());
+        }
+
+        completed
+    }
+
+    fn flush(&mut self) -> Option<Candlestick> {
+        let latest = self.latest.take()?;
+        let bucket = self.interval.open_time(latest.open_time);
+
+        let mut candlestick = match self.base.take() {
+            Some(base) => base.merge(latest),
+            None => latest,
+        };
+        candlestick.open_time = bucket;
+        candlestick.close_time = self.interval.close_time(bucket);
+        candlestick.closed = latest.closed && latest.close_time >= candlestick.close_time;
+        self.emitted = Some(bucket);
+
+        Some(candlestick)
+    }
+}
+
+/// Aggregates a series of candlesticks, ordered by open time, into a coarser interval.
+/// Partial buckets at the start and at the end of the series are included.
+pub fn resample<I>(candlesticks: I, interval: Interval) -> Vec<Candlestick>
+where
+    I: IntoIterator<Item = Candlestick>,
+{
+    crate::transform(candlesticks, Resampler::new(interval))
+}
+
+/// Stream adapter that aggregates candlesticks into a coarser interval.
+/// The partial last bucket is emitted when the underlying stream ends.
+pub type Resample<S> = Transformed<S, Resampler>;
+
+/// Error of resampling candlesticks into an interval that their interval does not divide.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct ResampleError {
+    pub from: Interval,
+    pub to: Interval,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{eth, ethbtc, ohlc};
+    use crate::Subscription;
+
+    fn candlestick(minute: u64, price: f64, volume: f64) -> Candlestick {
+        Candlestick {
+            volume: eth(volume),
+            ..ohlc(
+                ethbtc(),
+                minute,
+                [price, price + 1.0, price - 1.0, price + 0.5],
+            )
+        }
+    }
+
+    #[test]
+    fn test_resample() {
+        // Minute 7 is missing, the bucket is aggregated from the others.
+        let candlesticks = vec![
+            candlestick(5, 10.0, 1.0),
+            candlestick(6, 12.0, 2.0),
+            candlestick(8, 8.0, 3.0),
+            candlestick(9, 9.0, 4.0),
+        ];
+
+        let resampled = resample(candlesticks, Interval::I5m);
+        assert_eq!(resampled.len(), 1);
+        assert_eq!(resampled[0].open_time.millis(), 5 * 60_000);
+        assert_eq!(resampled[0].close_time.millis(), 10 * 60_000 - 1);
+        assert_eq!(resampled[0].open.price, 10.0);
+        assert_eq!(resampled[0].close.price, 9.5);
+        assert_eq!(resampled[0].high.price, 13.0);
+        assert_eq!(resampled[0].low.price, 7.0);
+        assert_eq!(resampled[0].volume.quantity, 10.0);
+        assert_eq!(resampled[0].trades, 4);
+        assert!(resampled[0].closed);
+    }
+
+    #[test]
+    fn test_resample_update() {
+        // The forming candlestick of minute 11 is replaced by its update.
+        let candlesticks = vec![
+            candlestick(10, 10.0, 1.0),
+            Candlestick {
+                closed: false,
+                ..candlestick(11, 20.0, 1.0)
+            },
+            candlestick(11, 30.0, 2.0),
+        ];
+
+        let resampled = resample(candlesticks, Interval::I5m);
+        assert_eq!(resampled.len(), 1);
+        assert_eq!(resampled[0].close.price, 30.5);
+        assert_eq!(resampled[0].volume.quantity, 3.0);
+        assert_eq!(resampled[0].trades, 2);
+    }
+
+    #[test]
+    fn test_resample_partial() {
+        // The last bucket is passed on although its closing candlestick is missing.
+        let resampled = resample(vec![candlestick(10, 10.0, 1.0)], Interval::I5m);
+        assert_eq!(resampled.len(), 1);
+        assert_eq!(resampled[0].open_time.millis(), 10 * 60_000);
+        assert_eq!(resampled[0].close_time.millis(), 15 * 60_000 - 1);
+        assert!(!resampled[0].closed);
+    }
+
+    #[test]
+    fn test_resample_indivisible() {
+        let subscription = |interval| {
+            Subscription::new(ethbtc(), interval, futures::stream::empty::<Candlestick>())
+        };
+
+        assert!(subscription(Interval::I5m).resample(Interval::I1h).is_ok());
+        assert_eq!(
+            subscription(Interval::I5m)
+                .resample(Interval::I3m)
+                .err()
+                .unwrap(),
+            ResampleError {
+                from: Interval::I5m,
+                to: Interval::I3m,
+            }
+        );
+        // Weeks open on Mondays, which are not aligned to 3 days.
+        assert!(subscription(Interval::I3d).resample(Interval::I1w).is_err());
+    }
+}
@@ -1,32 +1,101 @@
 use crate::{
-    Api, Asset, Candlestick, Error, Interval, Market, Order, OrderError, OrderResponse,
-    Subscription,
+    Api, Asset, Candlestick, DepthStream, Error, Filters, Interval, Market, MatchingEngine, Order,
+    OrderError, OrderResponse, OrderUpdate, Subscription, Timestamp, Trade, TradeStream, Wallet,
+};
+use futures_core::{
+    stream::Stream,
+    task::{Context, Poll},
 };
-use futures_core::stream::Stream;
 use std::collections::HashSet;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use tokio::stream::StreamExt;
+use tokio::sync::broadcast;
 
+/// Paper trading venue that forwards market data of the wrapped API,
+/// but places orders in a matching engine instead of sending them to the exchange.
+///
+/// Resting orders are matched against the candlesticks and trades of all subscriptions
+/// and those pushed explicitly, for example while backtesting.
+/// Their updates are broadcast like the order updates of an exchange
+/// and can be recorded with `Orders::apply`.
 pub struct Simulated<API, S>
 where
     API: Api<S> + Send + Sync + 'static,
-    S: Stream<Item = Candlestick> + Unpin + Send + Sync + 'static,
+    S: Stream<Item = Candlestick> + Unpin + Send + 'static,
 {
     api: API,
-    _phantom: std::marker::PhantomData<S>,
+    engine: Arc<Mutex<MatchingEngine>>,
+    updates: broadcast::Sender<OrderUpdate>,
+    _phantom: std::marker::PhantomData<fn() -> S>,
 }
 
 impl<API, S> Simulated<API, S>
 where
     API: Api<S> + Send + Sync + 'static,
-    S: Stream<Item = Candlestick> + Unpin + Send + Sync + 'static,
+    S: Stream<Item = Candlestick> + Unpin + Send + 'static,
 {
+    /// Creates a venue that buffers the given number of order updates for each receiver.
+    pub fn new(api: API, engine: MatchingEngine, capacity: usize) -> Self {
+        let (updates, _) = broadcast::channel(capacity);
+
+        Self {
+            api,
+            engine: Arc::new(Mutex::new(engine)),
+            updates,
+            _phantom: std::marker::PhantomData,
+        }
+    }
+
+    /// Returns a receiver of the updates of resting orders.
+    pub fn updates(&self) -> broadcast::Receiver<OrderUpdate> {
+        self.updates.subscribe()
+    }
+
+    pub fn wallet(&self) -> Wallet {
+        self.engine.lock().unwrap().wallet().clone()
+    }
+
+    /// Returns the ids of all resting orders.
+    pub fn open_orders(&self) -> Vec<String> {
+        self.engine.lock().unwrap().open_orders()
+    }
+
+    pub fn cancel(&self, id: &str) -> Result<(), OrderError> {
+        let update = self.engine.lock().unwrap().cancel(id)?;
+        self.updates.send(update).ok();
+
+        Ok(())
+    }
+
+    /// Matches the resting orders against the candlestick.
+    pub fn push(&self, candlestick: &Candlestick) {
+        let updates = self.engine.lock().unwrap().push_candlestick(candlestick);
+        broadcast(&self.updates, updates);
+    }
+
+    /// Matches the resting orders against the trade.
+    pub fn push_trade(&self, trade: &Trade) {
+        let updates = self.engine.lock().unwrap().push_trade(trade);
+        broadcast(&self.updates, updates);
+    }
+}
+
+fn broadcast(sender: &broadcast::Sender<OrderUpdate>, updates: Vec<OrderUpdate>) {
+    for update in updates {
+        // Updates are dropped while nobody receives them.
+        sender.send(update).ok();
+    }
 }
 
 #[async_trait::async_trait]
-impl<API, S> Api<S> for Simulated<API, S>
+impl<API, S> Api<Matched<S>> for Simulated<API, S>
 where
     API: Api<S> + Send + Sync + 'static,
-    S: Stream<Item = Candlestick> + Unpin + Send + Sync + 'static,
+    S: Stream<Item = Candlestick> + Unpin + Send + 'static,
 {
+    const NAME: &'static str = API::NAME;
+
     async fn update(&mut self) -> Result<(), Error> {
         self.api.update().await
     }
@@ -39,11 +108,216 @@ where
         self.api.get_assets()
     }
 
-    async fn subscribe(&self, market: &'static Market, interval: Interval) -> Subscription<S> {
-        self.api.subscribe(market, interval).await
+    fn filters(&self) -> Filters {
+        self.api.filters()
+    }
+
+    async fn subscribe(
+        &self,
+        market: &'static Market,
+        interval: Interval,
+    ) -> Subscription<Matched<S>> {
+        let subscription = self.api.subscribe(market, interval).await;
+        let stream = Matched {
+            subscription,
+            engine: Arc::clone(&self.engine),
+            updates: self.updates.clone(),
+        };
+
+        Subscription::new(market, interval, stream)
+    }
+
+    async fn history(
+        &self,
+        market: &'static Market,
+        interval: Interval,
+        start: Timestamp,
+        end: Timestamp,
+    ) -> Result<Vec<Candlestick>, Error> {
+        self.api.history(market, interval, start, end).await
+    }
+
+    async fn subscribe_trades(&self, market: &'static Market) -> Result<TradeStream, Error> {
+        let trades = self.api.subscribe_trades(market).await?;
+        let engine = Arc::clone(&self.engine);
+        let updates = self.updates.clone();
+
+        Ok(Box::pin(trades.map(move |trade| {
+            let matched = engine.lock().unwrap().push_trade(&trade);
+            broadcast(&updates, matched);
+            trade
+        })))
+    }
+
+    async fn subscribe_depth(&self, market: &'static Market) -> Result<DepthStream, Error> {
+        self.api.subscribe_depth(market).await
     }
 
     async fn order(&mut self, order: Order) -> Result<OrderResponse, OrderError> {
-        Err(OrderError::Other(Error::ConnectionError))
+        self.engine.lock().unwrap().place(order)
+    }
+}
+
+/// Subscription of a simulated venue, which matches the resting orders
+/// against every candlestick before passing it on.
+pub struct Matched<S>
+where
+    S: Stream<Item = Candlestick> + Unpin,
+{
+    subscription: Subscription<S>,
+    engine: Arc<Mutex<MatchingEngine>>,
+    updates: broadcast::Sender<OrderUpdate>,
+}
+
+impl<S> Stream for Matched<S>
+where
+    S: Stream<Item = Candlestick> + Unpin,
+{
+    type Item = Candlestick;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        let candlestick = match Pin::new(&mut this.subscription).poll_next(cx) {
+            Poll::Ready(Some(candlestick)) => candlestick,
+            poll => return poll,
+        };
+
+        let updates = this.engine.lock().unwrap().push_candlestick(&candlestick);
+        broadcast(&this.updates, updates);
+
+        Poll::Ready(Some(candlestick))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{btc, eth, ethbtc, ohlc, price, trade};
+    use crate::{BarType, Bars, Journaled, OrderState, Side, Storage};
+    use futures::stream::{self, BoxStream, StreamExt};
+
+    struct Mock {
+        markets: HashSet<&'static Market>,
+        assets: HashSet<&'static Asset>,
+    }
+
+    #[async_trait::async_trait]
+    impl Api<BoxStream<'static, Candlestick>> for Mock {
+        async fn update(&mut self) -> Result<(), Error> {
+            Ok(())
+        }
+
+        fn get_markets(&self) -> &HashSet<&'static Market> {
+            &self.markets
+        }
+
+        fn get_assets(&self) -> &HashSet<&'static Asset> {
+            &self.assets
+        }
+
+        async fn subscribe(
+            &self,
+            market: &'static Market,
+            interval: Interval,
+        ) -> Subscription<BoxStream<'static, Candlestick>> {
+            let candlesticks = vec![
+                ohlc(market, 0, [1.0, 1.0, 1.0, 1.0]),
+                ohlc(market, 1, [1.0, 1.3, 0.95, 1.0]),
+            ];
+            Subscription::new(market, interval, stream::iter(candlesticks).boxed())
+        }
+
+        async fn history(
+            &self,
+            _market: &'static Market,
+            _interval: Interval,
+            _start: Timestamp,
+            _end: Timestamp,
+        ) -> Result<Vec<Candlestick>, Error> {
+            Ok(vec![])
+        }
+
+        async fn subscribe_trades(&self, market: &'static Market) -> Result<TradeStream, Error> {
+            let trades: Vec<Trade> = [1.0, 0.85, 0.9]
+                .iter()
+                .enumerate()
+                .map(|(i, &price)| trade(market, i as u64, i as u64 * 1000, price, 10.0))
+                .collect();
+            Ok(stream::iter(trades).boxed())
+        }
+
+        async fn order(&mut self, _order: Order) -> Result<OrderResponse, OrderError> {
+            Err(OrderError::Other(Error::ConnectionError))
+        }
+    }
+
+    /// Returns a simulation of the mock with ten BTC.
+    fn simulated() -> Simulated<Mock, BoxStream<'static, Candlestick>> {
+        let mut wallet = Wallet::new();
+        wallet.deposit(btc(10.0)).unwrap();
+        Simulated::new(
+            Mock {
+                markets: HashSet::new(),
+                assets: HashSet::new(),
+            },
+            MatchingEngine::new(wallet),
+            16,
+        )
+    }
+
+    #[tokio::test]
+    async fn test_simulated() {
+        let market = ethbtc();
+        let storage = Arc::new(Storage::in_memory().unwrap());
+        let simulated = simulated();
+        let mut updates = simulated.updates();
+        let mut api = Journaled::new(simulated, Arc::clone(&storage));
+
+        // The first candlestick tells the engine the latest price.
+        let mut subscription = api.subscribe(market, Interval::I1m).await;
+        subscription.next().await.unwrap();
+
+        // The entry is marketable and fills immediately, the exit rests.
+        let response = api
+            .enter_position(Side::Buy, eth(2.0), price(1.0), price(1.2), price(0.9))
+            .await
+            .unwrap();
+        assert_eq!(response.executed_quantity, eth(2.0));
+        let entry = storage.orders().get(1).unwrap().unwrap();
+        assert_eq!(entry.state, OrderState::Filled);
+        assert_eq!(storage.fills().of_order(1).unwrap().len(), 1);
+        let exit = storage.orders().get(2).unwrap().unwrap();
+        assert_eq!(exit.side, Side::Sell);
+        assert_eq!(exit.state, OrderState::New);
+
+        // The second candlestick reaches the take profit,
+        // its update is recorded like the one of an exchange.
+        subscription.next().await.unwrap();
+        let update = updates.recv().await.unwrap();
+        assert_eq!(update.fill.unwrap().price, price(1.2));
+        assert_eq!(storage.orders().apply(&update).unwrap(), Some(2));
+        let exit = storage.orders().get(2).unwrap().unwrap();
+        assert_eq!(exit.state, OrderState::Filled);
+        assert_eq!(storage.fills().of_order(2).unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_simulated_trades() {
+        let market = ethbtc();
+        let mut simulated = simulated();
+        let mut updates = simulated.updates();
+        simulated
+            .order(Order::Limit(Side::Buy, eth(1.0), price(0.9)))
+            .await
+            .unwrap();
+
+        // Bars are built from the trades, which also match the resting order.
+        let trades = simulated.subscribe_trades(market).await.unwrap();
+        let bars: Vec<Candlestick> = Bars::new(trades, BarType::Tick(2)).collect().await;
+        assert_eq!(bars.len(), 2);
+        assert_eq!(bars[0].low.price, 0.85);
+        assert_eq!(bars[0].volume.quantity, 20.0);
+        let update = updates.recv().await.unwrap();
+        assert_eq!(update.state, OrderState::Filled);
     }
 }
@@ -0,0 +1,540 @@
+use crate::{
+    Asset, Candlestick, Depth, Error, Interval, Level, Market, Price, Quantity, Timestamp, Trade,
+};
+use rusqlite::{params, Connection, Row};
+use std::path::Path;
+use std::sync::{Mutex, MutexGuard};
+
+/// Migrations of the database schema, in order. The schema version of a database
+/// is the number of migrations that were applied to it.
+const MIGRATIONS: &[&str] = &[
+    include_str!("../migrations/0001_candlesticks.sql"),
+    include_str!("../migrations/0002_trading_history.sql"),
+    include_str!("../migrations/0003_market_data.sql"),
+];
+
+impl From<rusqlite::Error> for Error {
+    fn from(_: rusqlite::Error) -> Self {
+        Error::DatabaseError
+    }
+}
+
+/// Persistent storage of market data and the trading history, backed by SQLite.
+///
+/// Candlesticks are identified by their market, interval and open time,
+/// so inserting a candlestick again replaces the stored version.
+/// Trades are identified by their market and id and are only stored once.
+pub struct Storage {
+    connection: Mutex<Connection>,
+}
+
+impl Storage {
+    /// Opens the database at the given path, which is created if it does not exist.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        Self::new(Connection::open(path)?)
+    }
+
+    /// Opens a database that only lives in memory, for example for tests.
+    pub fn in_memory() -> Result<Self, Error> {
+        Self::new(Connection::open_in_memory()?)
+    }
+
+    fn new(mut connection: Connection) -> Result<Self, Error> {
+        migrate(&mut connection)?;
+
+        Ok(Self {
+            connection: Mutex::new(connection),
+        })
+    }
+
+    /// Returns the number of migrations that were applied to the database.
+    pub fn version(&self) -> Result<usize, Error> {
+        Ok(version(&self.connection())?)
+    }
+
+    pub(crate) fn connection(&self) -> MutexGuard<'_, Connection> {
+        self.connection.lock().unwrap()
+    }
+
+    /// Inserts or updates a candlestick.
+    /// A forming candlestick never replaces the closed version of the same candlestick.
+    pub fn insert(&self, interval: Interval, candlestick: &Candlestick) -> Result<(), Error> {
+        self.insert_all(interval, std::slice::from_ref(candlestick))
+    }
+
+    /// Inserts or updates several candlesticks in a single transaction.
+    /// Forming candlesticks never replace closed ones.
+    pub fn insert_all(
+        &self,
+        interval: Interval,
+        candlesticks: &[Candlestick],
+    ) -> Result<(), Error> {
+        let mut connection = self.connection();
+        let transaction = connection.transaction()?;
+        transaction.execute(
+            "INSERT OR IGNORE INTO intervals (name, duration) VALUES (?1, ?2)",
+            params![interval.to_string(), interval.duration().num_milliseconds()],
+        )?;
+
+        {
+            let mut insert = transaction.prepare_cached(
+                "INSERT INTO candlesticks (
+                    market_id, interval, open_time, close_time, high, low, open, close,
+                    volume, quote_volume, taker_buy_base_volume, taker_buy_quote_volume, trades, closed
+                ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14)
+                ON CONFLICT (market_id, interval, open_time) DO UPDATE SET
+                    close_time = excluded.close_time,
+                    high = excluded.high,
+                    low = excluded.low,
+                    open = excluded.open,
+                    close = excluded.close,
+                    volume = excluded.volume,
+                    quote_volume = excluded.quote_volume,
+                    taker_buy_base_volume = excluded.taker_buy_base_volume,
+                    taker_buy_quote_volume = excluded.taker_buy_quote_volume,
+                    trades = excluded.trades,
+                    closed = excluded.closed
+                WHERE NOT candlesticks.closed OR excluded.closed",
+            )?;
+
+            for candlestick in candlesticks {
+                let market_id = market_id(&transaction, candlestick.market)?;
+
+                insert.execute(params![
+                    market_id,
+                    interval.to_string(),
+                    candlestick.open_time.millis() as i64,
+                    candlestick.close_time.millis() as i64,
+                    candlestick.high.price,
+                    candlestick.low.price,
+                    candlestick.open.price,
+                    candlestick.close.price,
+                    candlestick.volume.quantity,
+                    candlestick.quote_volume.quantity,
+                    candlestick.taker_buy_base_volume.quantity,
+                    candlestick.taker_buy_quote_volume.quantity,
+                    candlestick.trades as i64,
+                    candlestick.closed,
+                ])?;
+            }
+        }
+
+        transaction.commit()?;
+        Ok(())
+    }
+
+    /// Returns the candlesticks with open times between `start` and `end`, both inclusive,
+    /// ordered by open time.
+    pub fn candlesticks(
+        &self,
+        market: &'static Market,
+        interval: Interval,
+        start: Timestamp,
+        end: Timestamp,
+    ) -> Result<Vec<Candlestick>, Error> {
+        let connection = self.connection();
+        let mut select = connection.prepare_cached(
+            "SELECT
+                c.open_time, c.close_time, c.high, c.low, c.open, c.close,
+                c.volume, c.quote_volume, c.taker_buy_base_volume, c.taker_buy_quote_volume,
+                c.trades, c.closed
+            FROM candlesticks c JOIN markets m ON c.market_id = m.id
+            WHERE m.base = ?1 AND m.quote = ?2 AND c.interval = ?3
+                AND c.open_time BETWEEN ?4 AND ?5
+            ORDER BY c.open_time",
+        )?;
+
+        let rows = select.query_map(
+            params![
+                market.base.to_string(),
+                market.quote.to_string(),
+                interval.to_string(),
+                start.millis() as i64,
+                end.millis() as i64,
+            ],
+            |row| candlestick(market, row),
+        )?;
+
+        Ok(rows.collect::<Result<Vec<Candlestick>, rusqlite::Error>>()?)
+    }
+
+    /// Returns the open time of the latest stored closed candlestick.
+    pub fn latest(
+        &self,
+        market: &'static Market,
+        interval: Interval,
+    ) -> Result<Option<Timestamp>, Error> {
+        let connection = self.connection();
+        let latest: Option<i64> = connection.query_row(
+            "SELECT MAX(c.open_time)
+            FROM candlesticks c JOIN markets m ON c.market_id = m.id
+            WHERE m.base = ?1 AND m.quote = ?2 AND c.interval = ?3 AND c.closed",
+            params![
+                market.base.to_string(),
+                market.quote.to_string(),
+                interval.to_string(),
+            ],
+            |row| row.get(0),
+        )?;
+
+        Ok(latest.map(|latest| Timestamp::from_millis(latest as u64)))
+    }
+
+    /// Returns all markets that have stored candlesticks.
+    pub fn markets(&self) -> Result<Vec<&'static Market>, Error> {
+        let connection = self.connection();
+        let mut select = connection.prepare_cached("SELECT base, quote FROM markets")?;
+        let rows = select.query_map(params![], |row| {
+            let base: String = row.get(0)?;
+            let quote: String = row.get(1)?;
+            Ok(Market::intern(Asset::intern(&base), Asset::intern(&quote)))
+        })?;
+
+        Ok(rows.collect::<Result<Vec<&'static Market>, rusqlite::Error>>()?)
+    }
+
+    /// Inserts trades in a single transaction, skipping trades that are already stored.
+    pub fn insert_trades(&self, trades: &[Trade]) -> Result<(), Error> {
+        let mut connection = self.connection();
+        let transaction = connection.transaction()?;
+
+        {
+            let mut insert = transaction.prepare_cached(
+                "INSERT OR IGNORE INTO trades (market_id, id, time, price, quantity, buyer_maker)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            )?;
+
+            for trade in trades {
+                let market_id = market_id(&transaction, trade.market)?;
+
+                insert.execute(params![
+                    market_id,
+                    trade.id as i64,
+                    trade.time.millis() as i64,
+                    trade.price.price,
+                    trade.quantity.quantity,
+                    trade.buyer_maker,
+                ])?;
+            }
+        }
+
+        transaction.commit()?;
+        Ok(())
+    }
+
+    /// Returns the trades with times between `start` and `end`, both inclusive, ordered by id.
+    pub fn trades(
+        &self,
+        market: &'static Market,
+        start: Timestamp,
+        end: Timestamp,
+    ) -> Result<Vec<Trade>, Error> {
+        let connection = self.connection();
+        let mut select = connection.prepare_cached(
+            "SELECT t.id, t.time, t.price, t.quantity, t.buyer_maker
+            FROM trades t JOIN markets m ON t.market_id = m.id
+            WHERE m.base = ?1 AND m.quote = ?2 AND t.time BETWEEN ?3 AND ?4
+            ORDER BY t.id",
+        )?;
+
+        let rows = select.query_map(
+            params![
+                market.base.to_string(),
+                market.quote.to_string(),
+                start.millis() as i64,
+                end.millis() as i64,
+            ],
+            |row| {
+                Ok(Trade {
+                    market,
+                    id: row.get::<_, i64>(0)? as u64,
+                    time: Timestamp::from_millis(row.get::<_, i64>(1)? as u64),
+                    price: Price {
+                        price: row.get(2)?,
+                        market,
+                    },
+                    quantity: Quantity {
+                        quantity: row.get(3)?,
+                        asset: market.base,
+                    },
+                    buyer_maker: row.get(4)?,
+                })
+            },
+        )?;
+
+        Ok(rows.collect::<Result<Vec<Trade>, rusqlite::Error>>()?)
+    }
+
+    /// Inserts or replaces a snapshot of the order book.
+    pub fn insert_depth(&self, depth: &Depth) -> Result<(), Error> {
+        let mut connection = self.connection();
+        let transaction = connection.transaction()?;
+        let market_id = market_id(&transaction, depth.market)?;
+        let time = depth.time.millis() as i64;
+        transaction.execute(
+            "DELETE FROM depth WHERE market_id = ?1 AND time = ?2",
+            params![market_id, time],
+        )?;
+
+        {
+            let mut insert = transaction.prepare_cached(
+                "INSERT INTO depth (market_id, time, side, level, price, quantity)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            )?;
+
+            for (side, levels) in &[(BID, &depth.bids), (ASK, &depth.asks)] {
+                for (index, level) in levels.iter().enumerate() {
+                    insert.execute(params![
+                        market_id,
+                        time,
+                        side,
+                        index as i64,
+                        level.price.price,
+                        level.quantity.quantity,
+                    ])?;
+                }
+            }
+        }
+
+        transaction.commit()?;
+        Ok(())
+    }
+
+    /// Returns the snapshots of the order book with times between `start` and `end`,
+    /// both inclusive, ordered by time.
+    pub fn depth(
+        &self,
+        market: &'static Market,
+        start: Timestamp,
+        end: Timestamp,
+    ) -> Result<Vec<Depth>, Error> {
+        let connection = self.connection();
+        let mut select = connection.prepare_cached(
+            "SELECT d.time, d.side, d.price, d.quantity
+            FROM depth d JOIN markets m ON d.market_id = m.id
+            WHERE m.base = ?1 AND m.quote = ?2 AND d.time BETWEEN ?3 AND ?4
+            ORDER BY d.time, d.side, d.level",
+        )?;
+
+        let mut rows = select.query(params![
+            market.base.to_string(),
+            market.quote.to_string(),
+            start.millis() as i64,
+            end.millis() as i64,
+        ])?;
+
+        let mut snapshots: Vec<Depth> = Vec::new();
+        while let Some(row) = rows.next()? {
+            let time = Timestamp::from_millis(row.get::<_, i64>(0)? as u64);
+            let side: String = row.get(1)?;
+            let level = Level {
+                price: Price {
+                    price: row.get(2)?,
+                    market,
+                },
+                quantity: Quantity {
+                    quantity: row.get(3)?,
+                    asset: market.base,
+                },
+            };
+
+            if snapshots.last().map(|depth| depth.time) != Some(time) {
+                snapshots.push(Depth {
+                    market,
+                    time,
+                    bids: Vec::new(),
+                    asks: Vec::new(),
+                });
+            }
+            let depth = snapshots.last_mut().unwrap();
+            match side.as_str() {
+                BID => depth.bids.push(level),
+                ASK => depth.asks.push(level),
+                _ => return Err(Error::DatabaseError),
+            }
+        }
+
+        Ok(snapshots)
+    }
+}
+
+const BID: &str = "BID";
+const ASK: &str = "ASK";
+
+fn version(connection: &Connection) -> Result<usize, rusqlite::Error> {
+    connection
+        .query_row("PRAGMA user_version", params![], |row| row.get::<_, i64>(0))
+        .map(|version| version as usize)
+}
+
+/// Applies all migrations that were not applied yet, each in its own transaction.
+fn migrate(connection: &mut Connection) -> Result<(), rusqlite::Error> {
+    for (index, migration) in MIGRATIONS.iter().enumerate().skip(version(connection)?) {
+        let transaction = connection.transaction()?;
+        transaction.execute_batch(migration)?;
+        transaction.execute_batch(&format!("PRAGMA user_version = {}", index + 1))?;
+        transaction.commit()?;
+    }
+
+    Ok(())
+}
+
+/// Returns the id of the market, which is inserted first if it is new.
+pub(crate) fn market_id(
+    connection: &Connection,
+    market: &'static Market,
+) -> Result<i64, rusqlite::Error> {
+    connection.execute(
+        "INSERT OR IGNORE INTO markets (base, quote) VALUES (?1, ?2)",
+        params![market.base.to_string(), market.quote.to_string()],
+    )?;
+    connection.query_row(
+        "SELECT id FROM markets WHERE base = ?1 AND quote = ?2",
+        params![market.base.to_string(), market.quote.to_string()],
+        |row| row.get(0),
+    )
+}
+
+fn candlestick(market: &'static Market, row: &Row) -> Result<Candlestick, rusqlite::Error> {
+    let price = |index| -> Result<Price, rusqlite::Error> {
+        Ok(Price {
+            price: row.get(index)?,
+            market,
+        })
+    };
+    let quantity = |index, asset| -> Result<Quantity, rusqlite::Error> {
+        Ok(Quantity {
+            quantity: row.get(index)?,
+            asset,
+        })
+    };
+
+    Ok(Candlestick {
+        market,
+        open_time: Timestamp::from_millis(row.get::<_, i64>(0)? as u64),
+        close_time: Timestamp::from_millis(row.get::<_, i64>(1)? as u64),
+        high: price(2)?,
+        low: price(3)?,
+        open: price(4)?,
+        close: price(5)?,
+        volume: quantity(6, market.base)?,
+        quote_volume: quantity(7, market.quote)?,
+        taker_buy_base_volume: quantity(8, market.base)?,
+        taker_buy_quote_volume: quantity(9, market.quote)?,
+        trades: row.get::<_, i64>(10)? as u64,
+        closed: row.get(11)?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{candlestick, ethbtc, minute, ohlc, price, trade};
+
+    #[test]
+    fn test_storage() {
+        let storage = Storage::in_memory().unwrap();
+        let flat = |minute, price, closed| Candlestick {
+            trades: minute,
+            closed,
+            ..ohlc(ethbtc(), minute, [price; 4])
+        };
+
+        storage
+            .insert_all(
+                Interval::I1m,
+                &[flat(1, 1.0, true), flat(2, 2.0, false), flat(3, 3.0, true)],
+            )
+            .unwrap();
+        // Inserting the closed version of a candlestick replaces the forming one.
+        flat(2, 2.5, true).insert(&storage, Interval::I1m).unwrap();
+        storage.insert(Interval::I5m, &flat(0, 9.0, true)).unwrap();
+
+        let stored = storage
+            .candlesticks(ethbtc(), Interval::I1m, minute(1), minute(2))
+            .unwrap();
+        assert_eq!(stored.len(), 2);
+        assert_eq!(stored[0].close.price, 1.0);
+        assert_eq!(stored[1].close.price, 2.5);
+        assert_eq!(stored[1].trades, 2);
+        assert!(stored[1].closed);
+        assert_eq!(
+            stored[1].close_time,
+            minute(3) - chrono::Duration::milliseconds(1)
+        );
+
+        assert_eq!(
+            storage.latest(ethbtc(), Interval::I1m).unwrap(),
+            Some(minute(3))
+        );
+        assert_eq!(storage.latest(ethbtc(), Interval::I1h).unwrap(), None);
+        assert_eq!(storage.markets().unwrap(), vec![ethbtc()]);
+    }
+
+    #[test]
+    fn test_storage_forming_after_closed() {
+        let storage = Storage::in_memory().unwrap();
+        storage
+            .insert(Interval::I1m, &ohlc(ethbtc(), 0, [1.0, 2.0, 1.0, 2.0]))
+            .unwrap();
+        // A late update of the forming candlestick must not overwrite the closed one.
+        storage
+            .insert(
+                Interval::I1m,
+                &Candlestick {
+                    closed: false,
+                    ..candlestick(ethbtc(), 0)
+                },
+            )
+            .unwrap();
+
+        let stored = storage
+            .candlesticks(ethbtc(), Interval::I1m, minute(0), minute(0))
+            .unwrap();
+        assert_eq!(stored.len(), 1);
+        assert!(stored[0].closed);
+        assert_eq!(stored[0].close.price, 2.0);
+    }
+
+    #[test]
+    fn test_storage_trades_and_depth() {
+        let storage = Storage::in_memory().unwrap();
+        let time = Timestamp::from_millis;
+        storage
+            .insert_trades(&[
+                trade(ethbtc(), 2, 2_000, 0.06, 1.0),
+                trade(ethbtc(), 1, 1_000, 0.05, 1.0),
+                trade(ethbtc(), 3, 3_000, 0.07, 1.0),
+            ])
+            .unwrap();
+        let trades = storage.trades(ethbtc(), time(1_000), time(2_000)).unwrap();
+        assert_eq!(
+            trades.iter().map(|trade| trade.id).collect::<Vec<u64>>(),
+            vec![1, 2]
+        );
+
+        let level = |price: f64| Level {
+            price: Price {
+                price,
+                market: ethbtc(),
+            },
+            quantity: Quantity {
+                quantity: 1.0,
+                asset: ethbtc().base,
+            },
+        };
+        let depth = |bids: Vec<f64>| Depth {
+            market: ethbtc(),
+            time: time(1_000),
+            bids: bids.into_iter().map(level).collect(),
+            asks: vec![level(0.06)],
+        };
+        storage.insert_depth(&depth(vec![0.05, 0.04])).unwrap();
+        // A snapshot with the same time replaces all levels of the previous one.
+        storage.insert_depth(&depth(vec![0.03])).unwrap();
+
+        let snapshots = storage.depth(ethbtc(), time(0), time(1_000)).unwrap();
+        assert_eq!(snapshots, vec![depth(vec![0.03])]);
+        assert_eq!(snapshots[0].asks[0].price, price(0.06));
+    }
+}
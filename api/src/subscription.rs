@@ -1,4 +1,7 @@
-use crate::{Candlestick, Interval, Market};
+use crate::{
+    Backfill, Backpressure, Candlestick, Closed, History, Interval, Managed, Market, Resample,
+    ResampleError, Resampler, SubscriptionHandle, Transform, Transformed,
+};
 use futures_core::{
     stream::Stream,
     task::{Context, Poll},
@@ -28,6 +31,71 @@ where
             stream,
         }
     }
+
+    /// Returns a reference to the underlying stream.
+    pub fn get_ref(&self) -> &S {
+        &self.stream
+    }
+
+    /// Reads this subscription in the background into a buffer of the given capacity
+    /// and returns a handle to control it.
+    pub fn managed(
+        self,
+        capacity: usize,
+        backpressure: Backpressure,
+    ) -> (Subscription<Managed>, SubscriptionHandle)
+    where
+        S: Send + 'static,
+    {
+        let (stream, handle) = Managed::new(self.stream, capacity, backpressure);
+
+        (
+            Subscription::new(self.market, self.interval, stream),
+            handle,
+        )
+    }
+
+    /// Fetches candlesticks that are missing in this subscription from the history
+    /// and passes on gaps in place of those that cannot be fetched.
+    pub fn backfill(self, history: History) -> Backfill<Self> {
+        let (market, interval) = (self.market, self.interval);
+
+        Backfill::new(self, market, interval, history)
+    }
+
+    /// Only passes on closed candlesticks, each of them exactly once.
+    pub fn closed(self) -> Subscription<Transformed<S, Closed>> {
+        self.transform(Closed::new())
+    }
+
+    /// Aggregates the candlesticks of this subscription into a coarser interval.
+    /// Fails if the interval of this subscription does not divide the given one.
+    pub fn resample(self, interval: Interval) -> Result<Subscription<Resample<S>>, ResampleError> {
+        if !self.interval.divides(interval) {
+            return Err(ResampleError {
+                from: self.interval,
+                to: interval,
+            });
+        }
+
+        Ok(Subscription::new(
+            self.market,
+            interval,
+            Transformed::new(self.stream, Resampler::new(interval)),
+        ))
+    }
+
+    /// Applies a transform to the candlesticks of this subscription.
+    pub fn transform<T>(self, transform: T) -> Subscription<Transformed<S, T>>
+    where
+        T: Transform + Unpin,
+    {
+        Subscription::new(
+            self.market,
+            self.interval,
+            Transformed::new(self.stream, transform),
+        )
+    }
 }
 
 impl<S> fmt::Display for Subscription<S>
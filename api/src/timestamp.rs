@@ -0,0 +1,125 @@
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+use std::convert::TryFrom;
+use std::fmt;
+use std::ops::{Add, Sub};
+
+/// Point in time in UTC with the millisecond precision used by exchanges,
+/// which is represented by the milliseconds since the unix epoch.
+#[derive(
+    Copy, Clone, Debug, Default, Eq, PartialEq, Ord, PartialOrd, Hash, Serialize, Deserialize,
+)]
+pub struct Timestamp(u64);
+
+impl Timestamp {
+    /// Creates a timestamp from milliseconds since the unix epoch.
+    pub fn from_millis(millis: u64) -> Self {
+        Self(millis)
+    }
+
+    /// Returns the milliseconds since the unix epoch.
+    pub fn millis(&self) -> u64 {
+        self.0
+    }
+
+    pub fn now() -> Self {
+        Self::from(Utc::now())
+    }
+
+    /// Converts to a date and time, where timestamps beyond the range of `DateTime`
+    /// are clamped to its maximum.
+    pub fn datetime(&self) -> DateTime<Utc> {
+        self.checked_datetime().unwrap_or(DateTime::<Utc>::MAX_UTC)
+    }
+
+    fn checked_datetime(&self) -> Option<DateTime<Utc>> {
+        let millis = i64::try_from(self.0).ok()?;
+        Utc.timestamp_millis_opt(millis).single()
+    }
+}
+
+impl From<DateTime<Utc>> for Timestamp {
+    fn from(datetime: DateTime<Utc>) -> Self {
+        Self(datetime.timestamp_millis().max(0) as u64)
+    }
+}
+
+impl From<Timestamp> for DateTime<Utc> {
+    fn from(timestamp: Timestamp) -> Self {
+        timestamp.datetime()
+    }
+}
+
+impl Add<Duration> for Timestamp {
+    type Output = Self;
+
+    fn add(self, duration: Duration) -> Self {
+        Self((self.0 as i64 + duration.num_milliseconds()).max(0) as u64)
+    }
+}
+
+impl Sub<Duration> for Timestamp {
+    type Output = Self;
+
+    fn sub(self, duration: Duration) -> Self {
+        self + -duration
+    }
+}
+
+impl Sub for Timestamp {
+    type Output = Duration;
+
+    fn sub(self, other: Self) -> Duration {
+        Duration::milliseconds(self.0 as i64 - other.0 as i64)
+    }
+}
+
+impl fmt::Display for Timestamp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.checked_datetime() {
+            Some(datetime) => write!(f, "{}", datetime.format("%Y-%m-%d %H:%M:%S%.3f")),
+            None => write!(f, "{} ms", self.0),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Interval;
+
+    #[test]
+    fn test_timestamp() {
+        let time = Timestamp::from(Utc.with_ymd_and_hms(2021, 3, 4, 5, 6, 7).unwrap());
+        assert_eq!(time.to_string(), "2021-03-04 05:06:07.000");
+
+        let open_time = Interval::I1h.open_time(time);
+        assert_eq!(open_time.to_string(), "2021-03-04 05:00:00.000");
+        assert_eq!(
+            Interval::I1h.close_time(time) - open_time,
+            Duration::hours(1) - Duration::milliseconds(1)
+        );
+        assert_eq!(
+            open_time + Interval::I1h.duration() - time,
+            Duration::seconds(3233)
+        );
+
+        // Weeks start on Mondays.
+        assert_eq!(
+            Interval::I1w.open_time(time).to_string(),
+            "2021-03-01 00:00:00.000"
+        );
+    }
+
+    #[test]
+    fn test_timestamp_out_of_range() {
+        let time = Timestamp::from_millis(u64::MAX);
+        assert_eq!(time.to_string(), format!("{} ms", u64::MAX));
+        assert_eq!(time.datetime(), DateTime::<Utc>::MAX_UTC);
+        // Fits into the milliseconds of chrono but not into its range of years.
+        assert_eq!(
+            Timestamp::from_millis(i64::MAX as u64).datetime(),
+            DateTime::<Utc>::MAX_UTC
+        );
+    }
+}
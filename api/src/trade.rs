@@ -0,0 +1,17 @@
+use crate::{Market, Price, Quantity, Timestamp};
+use futures_core::stream::BoxStream;
+use serde::{Deserialize, Serialize};
+
+/// Stream of the trades of a market as they are executed.
+pub type TradeStream = BoxStream<'static, Trade>;
+
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+pub struct Trade {
+    pub market: &'static Market,
+    pub id: u64,
+    pub time: Timestamp,
+    pub price: Price,
+    pub quantity: Quantity,
+    /// True if the buyer placed the resting order, so the taker sold.
+    pub buyer_maker: bool,
+}
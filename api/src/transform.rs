@@ -0,0 +1,100 @@
+use crate::Candlestick;
+use futures_core::{
+    stream::Stream,
+    task::{Context, Poll},
+};
+use std::collections::VecDeque;
+use std::pin::Pin;
+
+/// Turns a series of candlesticks into another series of candlesticks.
+pub trait Transform {
+    /// Pushes a candlestick and returns the candlesticks that were completed by it.
+    fn push(&mut self, candlestick: Candlestick) -> Vec<Candlestick>;
+
+    /// Returns the current, possibly partial, candlestick at the end of the series.
+    fn flush(&mut self) -> Option<Candlestick> {
+        None
+    }
+}
+
+/// Applies a transform to a series of candlesticks, ordered by open time.
+pub fn transform<I, T>(candlesticks: I, mut transform: T) -> Vec<Candlestick>
+where
+    I: IntoIterator<Item = Candlestick>,
+    T: Transform,
+{
+    let mut transformed = Vec::new();
+
+    for candlestick in candlesticks {
+        transformed.extend(transform.push(candlestick));
+    }
+    transformed.extend(transform.flush());
+
+    transformed
+}
+
+/// Stream adapter that applies a transform to a stream of candlesticks.
+/// The partial last candlestick is emitted when the underlying stream ends.
+pub struct Transformed<S, T>
+where
+    S: Stream<Item = Candlestick> + Unpin,
+    T: Transform + Unpin,
+{
+    stream: S,
+    transform: T,
+    buffer: VecDeque<Candlestick>,
+    done: bool,
+}
+
+impl<S, T> Transformed<S, T>
+where
+    S: Stream<Item = Candlestick> + Unpin,
+    T: Transform + Unpin,
+{
+    pub fn new(stream: S, transform: T) -> Self {
+        Self {
+            stream,
+            transform,
+            buffer: VecDeque::new(),
+            done: false,
+        }
+    }
+
+    /// Returns a reference to the transform, for example to inspect its state.
+    pub fn transform(&self) -> &T {
+        &self.transform
+    }
+}
+
+impl<S, T> Stream for Transformed<S, T>
+where
+    S: Stream<Item = Candlestick> + Unpin,
+    T: Transform + Unpin,
+{
+    type Item = Candlestick;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.as_mut().get_mut();
+
+        loop {
+            if let Some(candlestick) = this.buffer.pop_front() {
+                return Poll::Ready(Some(candlestick));
+            }
+
+            if this.done {
+                return Poll::Ready(None);
+            }
+
+            match Pin::new(&mut this.stream).poll_next(cx) {
+                Poll::Ready(Some(candlestick)) => {
+                    this.buffer.extend(this.transform.push(candlestick));
+                }
+                Poll::Ready(None) => {
+                    this.done = true;
+                    this.buffer.extend(this.transform.flush());
+                }
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
@@ -0,0 +1,344 @@
+use crate::{ArithmeticError, Asset, Price, Quantity, Side};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::ops::{Add, Sub};
+
+#[derive(Debug)]
+pub enum WalletError {
+    /// The free or locked amount of the asset is smaller than the requested quantity.
+    InsufficientFunds(Quantity),
+    /// The quantity to deposit or withdraw is zero or negative.
+    InvalidQuantity(Quantity),
+    Arithmetic(ArithmeticError),
+}
+
+impl From<ArithmeticError> for WalletError {
+    fn from(error: ArithmeticError) -> Self {
+        Self::Arithmetic(error)
+    }
+}
+
+/// Holdings of a single asset.
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Balance {
+    /// Quantity that is available for new orders.
+    pub free: Quantity,
+    /// Quantity that is reserved by open orders.
+    pub locked: Quantity,
+}
+
+impl Balance {
+    pub fn zero(asset: &'static Asset) -> Self {
+        Self {
+            free: Quantity::zero(asset),
+            locked: Quantity::zero(asset),
+        }
+    }
+
+    pub fn total(&self) -> Quantity {
+        self.free + self.locked
+    }
+}
+
+impl Add for Balance {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        Self {
+            free: self.free + other.free,
+            locked: self.locked + other.locked,
+        }
+    }
+}
+
+impl Sub for Balance {
+    type Output = Self;
+
+    fn sub(self, other: Self) -> Self {
+        Self {
+            free: self.free - other.free,
+            locked: self.locked - other.locked,
+        }
+    }
+}
+
+/// Balances of several assets, for example the holdings of an account.
+///
+/// Deposits, withdrawals and fills never let a balance become negative,
+/// while the difference of two wallets may contain negative balances.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Wallet {
+    balances: HashMap<&'static Asset, Balance>,
+}
+
+impl Wallet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the balance of the asset, which is zero if the asset was never held.
+    pub fn balance(&self, asset: &'static Asset) -> Balance {
+        self.balances
+            .get(asset)
+            .copied()
+            .unwrap_or_else(|| Balance::zero(asset))
+    }
+
+    pub fn free(&self, asset: &'static Asset) -> Quantity {
+        self.balance(asset).free
+    }
+
+    pub fn locked(&self, asset: &'static Asset) -> Quantity {
+        self.balance(asset).locked
+    }
+
+    pub fn total(&self, asset: &'static Asset) -> Quantity {
+        self.balance(asset).total()
+    }
+
+    /// Returns all assets with their balances.
+    pub fn balances(&self) -> impl Iterator<Item = (&'static Asset, Balance)> + '_ {
+        self.balances
+            .iter()
+            .map(|(asset, balance)| (*asset, *balance))
+    }
+
+    /// Overwrites the balance of an asset, for example with the state reported by the exchange.
+    pub fn set(&mut self, balance: Balance) -> Result<(), WalletError> {
+        if balance.free.asset != balance.locked.asset {
+            return Err(ArithmeticError::WrongAsset.into());
+        }
+        self.balances.insert(balance.free.asset, balance);
+
+        Ok(())
+    }
+
+    /// Adds the quantity to the free amount of its asset.
+    pub fn deposit(&mut self, quantity: Quantity) -> Result<(), WalletError> {
+        if quantity.quantity <= 0.0 {
+            return Err(WalletError::InvalidQuantity(quantity));
+        }
+        let balance = self.entry(quantity.asset);
+        balance.free = balance.free + quantity;
+
+        Ok(())
+    }
+
+    /// Removes the quantity from the free amount of its asset.
+    pub fn withdraw(&mut self, quantity: Quantity) -> Result<(), WalletError> {
+        if quantity.quantity <= 0.0 {
+            return Err(WalletError::InvalidQuantity(quantity));
+        }
+        let free = self.free(quantity.asset);
+        if free < quantity {
+            return Err(WalletError::InsufficientFunds(free));
+        }
+        let balance = self.entry(quantity.asset);
+        balance.free = balance.free - quantity;
+
+        Ok(())
+    }
+
+    /// Moves the quantity from the free to the locked amount of its asset,
+    /// for example when an order is placed.
+    pub fn lock(&mut self, quantity: Quantity) -> Result<(), WalletError> {
+        let free = self.free(quantity.asset);
+        if free < quantity {
+            return Err(WalletError::InsufficientFunds(free));
+        }
+        let balance = self.entry(quantity.asset);
+        balance.free = balance.free - quantity;
+        balance.locked = balance.locked + quantity;
+
+        Ok(())
+    }
+
+    /// Moves the quantity from the locked back to the free amount of its asset,
+    /// for example when an order is canceled.
+    pub fn unlock(&mut self, quantity: Quantity) -> Result<(), WalletError> {
+        let locked = self.locked(quantity.asset);
+        if locked < quantity {
+            return Err(WalletError::InsufficientFunds(locked));
+        }
+        let balance = self.entry(quantity.asset);
+        balance.locked = balance.locked - quantity;
+        balance.free = balance.free + quantity;
+
+        Ok(())
+    }
+
+    /// Exchanges assets according to a filled order of the given base quantity at the given price
+    /// and pays the fee from the free amount.
+    ///
+    /// `locked` is the part of the quantity locked by the order that the fill releases,
+    /// which is unlocked before the spent asset is taken from the free amount.
+    /// Quantities locked by other orders are left untouched.
+    /// Nothing is changed if the wallet cannot cover the fill.
+    pub fn apply_fill(
+        &mut self,
+        side: Side,
+        quantity: Quantity,
+        price: Price,
+        locked: Quantity,
+        fee: Quantity,
+    ) -> Result<(), WalletError> {
+        let quote_quantity = quantity.checked_mul(price)?;
+        let (spent, received) = match side {
+            Side::Buy => (quote_quantity, quantity),
+            Side::Sell => (quantity, quote_quantity),
+        };
+
+        let mut wallet = self.clone();
+        wallet.unlock(locked)?;
+        wallet.withdraw(spent)?;
+        wallet.deposit(received)?;
+        if fee.quantity > 0.0 {
+            wallet.withdraw(fee)?;
+        }
+
+        *self = wallet;
+        Ok(())
+    }
+
+    fn entry(&mut self, asset: &'static Asset) -> &mut Balance {
+        self.balances
+            .entry(asset)
+            .or_insert_with(|| Balance::zero(asset))
+    }
+}
+
+impl Add for Wallet {
+    type Output = Self;
+
+    fn add(mut self, other: Self) -> Self {
+        for (asset, balance) in other.balances {
+            let sum = self.balance(asset) + balance;
+            self.balances.insert(asset, sum);
+        }
+
+        self
+    }
+}
+
+impl Sub for Wallet {
+    type Output = Self;
+
+    fn sub(mut self, other: Self) -> Self {
+        for (asset, balance) in other.balances {
+            let difference = self.balance(asset) - balance;
+            self.balances.insert(asset, difference);
+        }
+
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{btc, eth, ethbtc, price};
+
+    fn bnb(quantity: f64) -> Quantity {
+        Quantity {
+            quantity,
+            asset: Asset::intern("BNB"),
+        }
+    }
+
+    /// Returns a wallet that holds one BTC and one BNB.
+    fn wallet() -> Wallet {
+        let mut wallet = Wallet::new();
+        wallet.deposit(btc(1.0)).unwrap();
+        wallet.deposit(bnb(1.0)).unwrap();
+
+        wallet
+    }
+
+    #[test]
+    fn test_wallet_fill() {
+        let mut wallet = wallet();
+        wallet.lock(btc(0.5)).unwrap();
+        assert_eq!(wallet.free(ethbtc().quote), btc(0.5));
+        assert_eq!(wallet.locked(ethbtc().quote), btc(0.5));
+
+        wallet
+            .apply_fill(Side::Buy, eth(2.0), price(0.25), btc(0.5), bnb(0.5))
+            .unwrap();
+        assert_eq!(wallet.total(ethbtc().quote), btc(0.5));
+        assert_eq!(wallet.locked(ethbtc().quote), btc(0.0));
+        assert_eq!(wallet.free(ethbtc().base), eth(2.0));
+        assert_eq!(wallet.free(Asset::intern("BNB")), bnb(0.5));
+    }
+
+    #[test]
+    fn test_wallet_fill_other_orders() {
+        // The fill of one order leaves the quantity locked by another order untouched.
+        let mut wallet = wallet();
+        wallet.lock(btc(0.25)).unwrap();
+        wallet.lock(btc(0.5)).unwrap();
+
+        wallet
+            .apply_fill(Side::Buy, eth(1.0), price(0.25), btc(0.25), bnb(0.0))
+            .unwrap();
+        assert_eq!(wallet.locked(ethbtc().quote), btc(0.5));
+        assert_eq!(wallet.free(ethbtc().quote), btc(0.25));
+        assert_eq!(wallet.free(ethbtc().base), eth(1.0));
+
+        // The order cannot release more than is locked.
+        assert!(wallet
+            .apply_fill(Side::Buy, eth(1.0), price(0.1), btc(1.0), bnb(0.0))
+            .is_err());
+        assert_eq!(wallet.locked(ethbtc().quote), btc(0.5));
+    }
+
+    #[test]
+    fn test_wallet_failure() {
+        // A failing fill leaves the wallet unchanged.
+        let mut wallet = wallet();
+        assert!(wallet
+            .apply_fill(Side::Sell, eth(3.0), price(0.25), eth(0.0), bnb(0.0))
+            .is_err());
+        assert!(wallet.withdraw(bnb(2.0)).is_err());
+        assert_eq!(wallet.total(ethbtc().quote), btc(1.0));
+        assert_eq!(wallet.total(Asset::intern("BNB")), bnb(1.0));
+    }
+
+    #[test]
+    fn test_wallet_invalid_quantity() {
+        let mut wallet = wallet();
+        assert!(matches!(
+            wallet.deposit(btc(-1.0)),
+            Err(WalletError::InvalidQuantity(_))
+        ));
+        assert!(matches!(
+            wallet.withdraw(btc(0.0)),
+            Err(WalletError::InvalidQuantity(_))
+        ));
+        assert_eq!(wallet.total(ethbtc().quote), btc(1.0));
+    }
+
+    #[test]
+    fn test_wallet_untouched_on_error() {
+        // Failing to withdraw an asset that was never held does not add it to the balances.
+        let mut wallet = wallet();
+        assert!(wallet.withdraw(eth(1.0)).is_err());
+        assert!(wallet.lock(eth(1.0)).is_err());
+        assert!(wallet.unlock(eth(1.0)).is_err());
+        assert!(wallet.balances().all(|(asset, _)| asset != ethbtc().base));
+    }
+
+    #[test]
+    fn test_wallet_change() {
+        let initial = wallet();
+        let mut wallet = initial.clone();
+        wallet.lock(btc(0.5)).unwrap();
+        wallet
+            .apply_fill(Side::Buy, eth(2.0), price(0.25), btc(0.5), bnb(0.5))
+            .unwrap();
+
+        let change = wallet - initial;
+        assert_eq!(change.total(ethbtc().quote), btc(-0.5));
+        assert_eq!(change.total(ethbtc().base), eth(2.0));
+        assert_eq!(change.total(Asset::intern("BNB")), bnb(-0.5));
+    }
+}
@@ -0,0 +1,547 @@
+use crate::model::{self, IntoCandlestick, Value};
+use api::{
+    Asset, BarType, Candlestick, Gap, Interval, Market, Price, Quantity, Storage, SymbolError,
+    Timestamp, Trade,
+};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{Read, Seek};
+use std::path::Path;
+use zip::ZipArchive;
+
+#[derive(Debug)]
+pub enum DumpError {
+    Io(std::io::Error),
+    Zip(zip::result::ZipError),
+    Csv(csv::Error),
+    /// The file name does not name a market and an interval.
+    UnknownFile(String),
+    Storage(api::Error),
+}
+
+/// Row of an archive that could not be parsed or does not describe a valid candlestick,
+/// with the name of the file in the archive and the line.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SkippedRow {
+    pub file: String,
+    pub line: u64,
+}
+
+/// Rows that were read from an archive and the malformed rows that were skipped.
+#[derive(Debug)]
+pub struct Rows<T> {
+    pub rows: Vec<T>,
+    pub skipped: Vec<SkippedRow>,
+}
+
+impl From<std::io::Error> for DumpError {
+    fn from(error: std::io::Error) -> Self {
+        Self::Io(error)
+    }
+}
+
+impl From<zip::result::ZipError> for DumpError {
+    fn from(error: zip::result::ZipError) -> Self {
+        Self::Zip(error)
+    }
+}
+
+impl From<csv::Error> for DumpError {
+    fn from(error: csv::Error) -> Self {
+        Self::Csv(error)
+    }
+}
+
+impl From<api::Error> for DumpError {
+    fn from(error: api::Error) -> Self {
+        Self::Storage(error)
+    }
+}
+
+/// Imports the kline and aggTrades archives of the public data dumps of Binance
+/// into the candlestick storage, without using the REST API.
+///
+/// Archives of the same market and interval have to be imported in chronological order,
+/// as the continuity of the candlesticks is checked across archives.
+/// Malformed rows are skipped and collected.
+pub struct DumpImporter<'a> {
+    storage: &'a Storage,
+    /// Open time of the candlestick that is expected next for each market and interval.
+    expected: HashMap<(&'static Market, Interval), Timestamp>,
+    /// Trades of the last, still forming bar of the latest aggTrades archive
+    /// of each market and interval, which are added to the next archive.
+    forming: HashMap<(&'static Market, Interval), Vec<Trade>>,
+    gaps: Vec<Gap>,
+    skipped: Vec<SkippedRow>,
+}
+
+impl<'a> DumpImporter<'a> {
+    pub fn new(storage: &'a Storage) -> Self {
+        Self {
+            storage,
+            expected: HashMap::new(),
+            forming: HashMap::new(),
+            gaps: Vec::new(),
+            skipped: Vec::new(),
+        }
+    }
+
+    /// Returns all gaps between the imported candlesticks so far.
+    pub fn gaps(&self) -> &[Gap] {
+        &self.gaps
+    }
+
+    /// Returns all malformed rows that were skipped so far.
+    pub fn skipped(&self) -> &[SkippedRow] {
+        &self.skipped
+    }
+
+    /// Imports a kline archive named like `ETHBTC-1m-2020-01.zip`
+    /// and returns the number of imported candlesticks.
+    pub fn import_klines<P: AsRef<Path>>(&mut self, path: P) -> Result<usize, DumpError> {
+        let (market, kind) = file_name(path.as_ref())?;
+        let interval = kind
+            .parse::<Interval>()
+            .map_err(|_| DumpError::UnknownFile(kind))?;
+        let candlesticks = read_klines(File::open(path)?, market, interval)?;
+        self.skipped.extend(candlesticks.skipped);
+
+        self.insert(market, interval, candlesticks.rows)
+    }
+
+    /// Imports an aggTrades archive named like `ETHBTC-aggTrades-2020-01.zip`
+    /// as candlesticks of the given interval and returns the number of imported candlesticks.
+    ///
+    /// The last candlestick is stored as forming, as the next archive may hold more of its trades.
+    /// It is rebuilt and closed when the next archive of the market is imported with the same interval.
+    pub fn import_agg_trades<P: AsRef<Path>>(
+        &mut self,
+        path: P,
+        interval: Interval,
+    ) -> Result<usize, DumpError> {
+        let (market, kind) = file_name(path.as_ref())?;
+        if kind != "aggTrades" {
+            return Err(DumpError::UnknownFile(kind));
+        }
+        let read = read_agg_trades(File::open(path)?, market)?;
+        self.skipped.extend(read.skipped);
+
+        let mut trades = self.forming.remove(&(market, interval)).unwrap_or_default();
+        trades.extend(read.rows);
+        let candlesticks = api::bars(trades.iter().copied(), BarType::Time(interval));
+        if let Some(last) = candlesticks.last() {
+            let forming = trades
+                .into_iter()
+                .filter(|trade| trade.time >= last.open_time)
+                .collect();
+            self.forming.insert((market, interval), forming);
+        }
+
+        self.insert(market, interval, candlesticks)
+    }
+
+    fn insert(
+        &mut self,
+        market: &'static Market,
+        interval: Interval,
+        mut candlesticks: Vec<Candlestick>,
+    ) -> Result<usize, DumpError> {
+        candlesticks.sort_by_key(|candlestick| candlestick.open_time);
+
+        if let Some(first) = candlesticks.first() {
+            let expected = self
+                .expected
+                .entry((market, interval))
+                .or_insert(first.open_time);
+            for candlestick in &candlesticks {
+                if candlestick.open_time > *expected {
+                    self.gaps.push(Gap {
+                        market,
+                        interval,
+                        start: *expected,
+                        end: candlestick.open_time - interval.duration(),
+                    });
+                }
+                *expected = (*expected).max(candlestick.open_time + interval.duration());
+            }
+        }
+        self.storage.insert_all(interval, &candlesticks)?;
+
+        Ok(candlesticks.len())
+    }
+}
+
+/// Assets that Binance quotes markets in, which symbols end with.
+const QUOTE_ASSETS: &[&str] = &[
+    "USDT", "FDUSD", "USDC", "TUSD", "BUSD", "DAI", "BTC", "ETH", "BNB", "XRP", "TRX", "DOGE",
+    "EUR", "GBP", "TRY", "BRL", "AUD", "JPY", "RUB", "UAH", "ZAR", "PLN", "ARS", "MXN",
+];
+
+/// Returns the market of the symbol, which is interned if it is not registered yet.
+/// The symbol is split at the longest quote asset that it ends with.
+fn market(symbol: &str) -> Option<&'static Market> {
+    match Market::from_symbol(symbol) {
+        Ok(market) => return Some(market),
+        Err(SymbolError::Ambiguous) => return None,
+        Err(SymbolError::Unknown) => {}
+    }
+
+    let quote = QUOTE_ASSETS
+        .iter()
+        .filter(|quote| symbol.len() > quote.len() && symbol.ends_with(*quote))
+        .max_by_key(|quote| quote.len())?;
+    let base = &symbol[..symbol.len() - quote.len()];
+
+    Some(Market::intern(Asset::intern(base), Asset::intern(quote)))
+}
+
+/// Splits a file name like `ETHBTC-1m-2020-01.zip` into the market and the kind of the archive.
+fn file_name(path: &Path) -> Result<(&'static Market, String), DumpError> {
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let mut parts = name.split('-');
+
+    match (parts.next().and_then(market), parts.next()) {
+        (Some(market), Some(kind)) => Ok((market, kind.to_string())),
+        _ => Err(DumpError::UnknownFile(name)),
+    }
+}
+
+/// Reads all klines of the CSV files in the archive,
+/// which are mapped like the klines of the REST API.
+/// Rows that are malformed or not aligned to the interval are skipped.
+pub fn read_klines<R: Read + Seek>(
+    archive: R,
+    market: &'static Market,
+    interval: Interval,
+) -> Result<Rows<Candlestick>, DumpError> {
+    let now = Timestamp::now();
+
+    read_rows(archive, |row| {
+        let candlestick = model::Candlestick::from(kline(row)?)
+            .candlestick(market, now)
+            .ok()?;
+        if interval.open_time(candlestick.open_time) != candlestick.open_time {
+            return None;
+        }
+
+        Some(candlestick)
+    })
+}
+
+/// Reads all aggregated trades of the CSV files in the archive, ordered by time.
+/// Malformed rows are skipped.
+pub fn read_agg_trades<R: Read + Seek>(
+    archive: R,
+    market: &'static Market,
+) -> Result<Rows<Trade>, DumpError> {
+    let mut trades = read_rows(archive, |row| agg_trade(market, row))?;
+    trades.rows.sort_by_key(|trade| (trade.time, trade.id));
+
+    Ok(trades)
+}
+
+/// Parses every row of every CSV file in the archive, where rows that cannot be parsed are skipped.
+/// Header rows, which newer archives start with, are skipped without being collected.
+fn read_rows<R, T, F>(archive: R, mut parse: F) -> Result<Rows<T>, DumpError>
+where
+    R: Read + Seek,
+    F: FnMut(&csv::StringRecord) -> Option<T>,
+{
+    let mut archive = ZipArchive::new(archive)?;
+    let mut rows = Rows {
+        rows: Vec::new(),
+        skipped: Vec::new(),
+    };
+
+    for index in 0..archive.len() {
+        let file = archive.by_index(index)?;
+        let name = file.name().to_string();
+        if !name.ends_with(".csv") {
+            continue;
+        }
+
+        let mut reader = csv::ReaderBuilder::new()
+            .has_headers(false)
+            .flexible(true)
+            .from_reader(file);
+        for row in reader.records() {
+            let row = row?;
+            let line = row.position().map_or(0, |position| position.line());
+            let header = row
+                .get(0)
+                .and_then(|field| field.parse::<u64>().ok())
+                .is_none();
+            if line == 1 && header {
+                continue;
+            }
+
+            match parse(&row) {
+                Some(parsed) => rows.rows.push(parsed),
+                None => rows.skipped.push(SkippedRow {
+                    file: name.clone(),
+                    line,
+                }),
+            }
+        }
+    }
+
+    Ok(rows)
+}
+
+/// Timestamps of archives since 2025 are given in microseconds instead of milliseconds.
+fn millis(value: u64) -> u64 {
+    if value >= 100_000_000_000_000 {
+        value / 1000
+    } else {
+        value
+    }
+}
+
+/// Converts a row of a kline archive into the representation of the REST API.
+fn kline(row: &csv::StringRecord) -> Option<IntoCandlestick> {
+    if row.len() < 11 {
+        return None;
+    }
+
+    row.iter()
+        .take(11)
+        .enumerate()
+        .map(|(index, field)| match index {
+            0 | 6 => Some(Value::Integer(millis(field.parse().ok()?) as i64)),
+            8 => Some(Value::Integer(field.parse().ok()?)),
+            _ => {
+                field.parse::<f64>().ok()?;
+                Some(Value::Float(field.to_string()))
+            }
+        })
+        .collect()
+}
+
+/// Converts a row of an aggTrades archive, which holds the id, price, quantity, first and last
+/// trade id, time and whether the buyer was the maker.
+fn agg_trade(market: &'static Market, row: &csv::StringRecord) -> Option<Trade> {
+    Some(Trade {
+        market,
+        id: row.get(0)?.parse().ok()?,
+        time: Timestamp::from_millis(millis(row.get(5)?.parse().ok()?)),
+        price: Price {
+            price: row.get(1)?.parse().ok()?,
+            market,
+        },
+        quantity: Quantity {
+            quantity: row.get(2)?.parse().ok()?,
+            asset: market.base,
+        },
+        buyer_maker: match row.get(6)?.to_lowercase().as_str() {
+            "true" => true,
+            "false" => false,
+            _ => return None,
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Cursor, Write};
+    use zip::write::{FileOptions, ZipWriter};
+
+    const KLINES: &str = "open_time,open,high,low,close,volume,close_time,quote_volume,count,taker_buy_volume,taker_buy_quote_volume,ignore\n\
+                          1577836800000,0.0181,0.0183,0.0180,0.0182,10.5,1577836859999,0.19,42,4.0,0.07,0\n\
+                          1577836860000000,0.0182,0.0184,0.0181,0.0183,2.0,1577836919999999,0.04,7,1.0,0.02,0\n\
+                          1577836980000,0.0183,0.0183,0.0183,0.0183,1.0,1577837039999,0.02,1,0.0,0.0,0\n";
+
+    fn ethbtc() -> &'static Market {
+        Market::intern(Asset::intern("ETH"), Asset::intern("BTC"))
+    }
+
+    fn archive(name: &str, content: &str) -> Cursor<Vec<u8>> {
+        let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+        writer.start_file(name, FileOptions::default()).unwrap();
+        writer.write_all(content.as_bytes()).unwrap();
+        let mut archive = writer.finish().unwrap();
+        archive.set_position(0);
+
+        archive
+    }
+
+    /// Writes the archive to a temporary file with the given name and imports it.
+    fn import<F>(name: &str, content: &str, import: F) -> Result<usize, DumpError>
+    where
+        F: FnOnce(&Path) -> Result<usize, DumpError>,
+    {
+        let path = std::env::temp_dir().join(format!("{}-{}.zip", name, std::process::id()));
+        std::fs::write(
+            &path,
+            archive(&format!("{}.csv", name), content).into_inner(),
+        )
+        .unwrap();
+        let imported = import(&path);
+        std::fs::remove_file(&path).unwrap();
+
+        imported
+    }
+
+    #[test]
+    fn test_read_klines() {
+        let candlesticks = read_klines(
+            archive("ETHBTC-1m-2020-01-01.csv", KLINES),
+            ethbtc(),
+            Interval::I1m,
+        )
+        .unwrap();
+        assert_eq!(candlesticks.rows.len(), 3);
+        assert!(candlesticks.skipped.is_empty());
+        assert_eq!(
+            candlesticks.rows[1].open_time,
+            Timestamp::from_millis(1577836860000)
+        );
+        assert_eq!(candlesticks.rows[1].trades, 7);
+        assert!(candlesticks
+            .rows
+            .iter()
+            .all(|candlestick| candlestick.closed));
+    }
+
+    #[test]
+    fn test_read_malformed_rows() {
+        // Unparsable rows, invalid prices and misaligned open times are skipped.
+        let klines = "1577836800000,x\n\
+                      1577836860000,0.0182,0.0181,0.0184,0.0183,2.0,1577836919999,0.04,7,1.0,0.02,0\n\
+                      1577836870000,0.0182,0.0184,0.0181,0.0183,2.0,1577836929999,0.04,7,1.0,0.02,0\n\
+                      1577836980000,0.0183,0.0183,0.0183,0.0183,1.0,1577837039999,0.02,1,0.0,0.0,0\n";
+        let candlesticks = read_klines(
+            archive("ETHBTC-1m-2020-01-01.csv", klines),
+            ethbtc(),
+            Interval::I1m,
+        )
+        .unwrap();
+        assert_eq!(candlesticks.rows.len(), 1);
+        let lines: Vec<u64> = candlesticks
+            .skipped
+            .iter()
+            .map(|skipped| skipped.line)
+            .collect();
+        assert_eq!(lines, vec![1, 2, 3]);
+        assert_eq!(candlesticks.skipped[0].file, "ETHBTC-1m-2020-01-01.csv");
+
+        let trades = "1,0.0181,2.0,1,1,1577836800500,true,true\n\
+                      2,0.0183,1.0,2,3,1577836801000,maybe,true\n";
+        let trades =
+            read_agg_trades(archive("ETHBTC-aggTrades-2020-01-01.csv", trades), ethbtc()).unwrap();
+        assert_eq!(trades.rows.len(), 1);
+        assert_eq!(
+            trades.skipped,
+            vec![SkippedRow {
+                file: "ETHBTC-aggTrades-2020-01-01.csv".to_string(),
+                line: 2
+            }]
+        );
+    }
+
+    #[test]
+    fn test_read_agg_trades() {
+        let trades = "3,0.0182,1.0,4,4,1577836860000,false,true\n\
+                      1,0.0181,2.0,1,1,1577836800500,true,true\n\
+                      2,0.0183,1.0,2,3,1577836801000,false,true\n";
+        let trades =
+            read_agg_trades(archive("ETHBTC-aggTrades-2020-01-01.csv", trades), ethbtc()).unwrap();
+        assert_eq!(trades.rows.len(), 3);
+        assert_eq!(trades.rows[0].id, 1);
+        assert!(trades.rows[0].buyer_maker);
+        assert_eq!(trades.rows[2].quantity.quantity, 1.0);
+    }
+
+    #[test]
+    fn test_import_klines() {
+        let storage = Storage::in_memory().unwrap();
+        let mut importer = DumpImporter::new(&storage);
+        let klines = format!("{}1577837040000,x\n", KLINES);
+        let imported = import("ETHBTC-1m-2020-01-01", &klines, |path| {
+            importer.import_klines(path)
+        });
+        assert_eq!(imported.unwrap(), 3);
+        assert_eq!(importer.skipped().len(), 1);
+        assert_eq!(importer.gaps().len(), 1);
+        assert_eq!(
+            importer.gaps()[0].start,
+            Timestamp::from_millis(1577836920000)
+        );
+        assert_eq!(
+            storage.latest(ethbtc(), Interval::I1m).unwrap(),
+            Some(Timestamp::from_millis(1577836980000))
+        );
+
+        assert!(matches!(
+            importer.import_klines("ETHXYZ-1m-2020-01.zip"),
+            Err(DumpError::UnknownFile(_))
+        ));
+    }
+
+    #[test]
+    fn test_import_unregistered_market() {
+        // The market is interned from the symbol of the file name.
+        let storage = Storage::in_memory().unwrap();
+        let mut importer = DumpImporter::new(&storage);
+        let imported = import("LTCUSDT-1m-2020-01-01", KLINES, |path| {
+            importer.import_klines(path)
+        });
+        assert_eq!(imported.unwrap(), 3);
+        let market = Market::from_symbol("LTCUSDT").unwrap();
+        assert_eq!(market.base, Asset::intern("LTC"));
+        assert_eq!(market.quote, Asset::intern("USDT"));
+        assert!(storage.latest(market, Interval::I1m).unwrap().is_some());
+    }
+
+    #[test]
+    fn test_millis() {
+        // Timestamps in milliseconds stay below 100_000_000_000_000 until the year 5138,
+        // while timestamps in microseconds exceed it since 1973.
+        assert_eq!(millis(1577836800000), 1577836800000);
+        assert_eq!(millis(99_999_999_999_999), 99_999_999_999_999);
+        assert_eq!(millis(100_000_000_000_000), 100_000_000_000);
+        assert_eq!(millis(1735689600000000), 1735689600000);
+    }
+
+    #[test]
+    fn test_import_agg_trades() {
+        let storage = Storage::in_memory().unwrap();
+        let mut importer = DumpImporter::new(&storage);
+        let stored = |storage: &Storage| {
+            storage
+                .candlesticks(
+                    ethbtc(),
+                    Interval::I1h,
+                    Timestamp::from_millis(0),
+                    Timestamp::now(),
+                )
+                .unwrap()
+        };
+
+        // The last hour of the first day continues in the archive of the next day.
+        let first = "1,0.0181,2.0,1,1,1577833200000,true,true\n\
+                     2,0.0183,1.0,2,3,1577836799000,false,true\n";
+        let imported = import("ETHBTC-aggTrades-2019-12-31", first, |path| {
+            importer.import_agg_trades(path, Interval::I1h)
+        });
+        assert_eq!(imported.unwrap(), 1);
+        let candlesticks = stored(&storage);
+        assert_eq!(candlesticks.len(), 1);
+        assert!(!candlesticks[0].closed);
+
+        let second = "3,0.0182,1.0,4,4,1577836800000,false,true\n";
+        let imported = import("ETHBTC-aggTrades-2020-01-01", second, |path| {
+            importer.import_agg_trades(path, Interval::I1h)
+        });
+        assert_eq!(imported.unwrap(), 2);
+        let candlesticks = stored(&storage);
+        assert_eq!(candlesticks.len(), 2);
+        assert!(candlesticks[0].closed);
+        assert_eq!(candlesticks[0].volume.quantity, 3.0);
+        assert!(!candlesticks[1].closed);
+        assert!(importer.gaps().is_empty());
+    }
+}
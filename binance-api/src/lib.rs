@@ -1,22 +1,31 @@
+mod dump;
 mod model;
 
+pub use dump::*;
+
 use api::{
-    Api, Asset, Candlestick, Error, Interval, Market, Order, OrderError, OrderResponse, Price,
-    Quantity, Subscription,
+    Api, Asset, Candlestick, Error, Filters, Interval, Market, Order, OrderError, OrderResponse,
+    Subscription, Timestamp, TradeStream, DepthStream,
 };
-use chrono::Utc;
+use futures::future;
 use futures::stream::{self, BoxStream, StreamExt};
 use hmac::{Hmac, Mac, NewMac};
 use reqwest::{Client, Url};
 use sha2::Sha256;
 use std::collections::HashSet;
-use tokio_tungstenite::{self as tungstenite};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::time;
+use tokio_tungstenite::{self as tungstenite, tungstenite::Message};
 use serde::de::DeserializeOwned;
 use std::fmt::Display;
 
 const ENDPOINT: &'static str = "https://www.binance.com/api/v3/";
 const WS_ENDPOINT: &'static str = "wss://stream.binance.com:9443/ws/";
 const RECV_WINDOW: usize = 5000;
+const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+const KLINES_LIMIT: usize = 1000;
 
 type SubscriptionStream = BoxStream<'static, Candlestick>;
 
@@ -39,6 +48,7 @@ pub struct Binance {
     client: Client,
     assets: HashSet<&'static Asset>,
     markets: HashSet<&'static Market>,
+    filters: Filters,
 }
 
 impl Binance {
@@ -54,89 +64,110 @@ impl Binance {
             client: Client::builder().build().unwrap(),
             assets: HashSet::new(),
             markets: HashSet::new(),
+            filters: Filters::new(),
         }
     }
 
-    async fn request<R>(&self, path: &'static str, params: Vec<(&'static str, Box<dyn Display + Send>)>) -> R
+    async fn request<R>(&self, path: &'static str, params: Vec<(&'static str, Box<dyn Display + Send>)>) -> Result<R, Error>
     where
         R: DeserializeOwned,
     {
-        let mut url = Url::parse_with_params(
-            &format!("{}{}", ENDPOINT, path), 
-            params
+        request(&self.client, &self.key, path, params).await
+    }
+}
+
+async fn request<R>(client: &Client, key: &str, path: &'static str, params: Vec<(&'static str, Box<dyn Display + Send>)>) -> Result<R, Error>
+where
+    R: DeserializeOwned,
+{
+    let mut url = Url::parse_with_params(
+        &format!("{}{}", ENDPOINT, path), 
+        params
+            .into_iter()
+            .map(|tuple| {
+                (tuple.0, format!("{}", tuple.1))
+            })
+            .collect::<Vec<(&str, String)>>()
+    ).unwrap();
+
+    /*
+    url.query_pairs_mut()
+        .append_pair("timestamp", &Timestamp::now().millis().to_string());
+    url.query_pairs_mut()
+        .append_pair("recvWindow", &RECV_WINDOW.to_string());
+    */
+
+    let body = String::new();
+
+    /*
+    let mut mac = Hmac::<Sha256>::new_varkey(self.secret.as_bytes()).unwrap();
+    let message = format!("{}{}", url.query().unwrap_or(""), body);
+    mac.update(message.as_bytes());
+    let signature = hex::encode(mac.finalize().into_bytes());
+
+    url.query_pairs_mut().append_pair("signature", &signature);
+    */
+
+    let result = client
+        .get(url)
+        .header("X-MBX-APIKEY", key)
+        .body(body)
+        .send()
+        .await
+        .map_err(|_| Error::ConnectionError)?
+        .json::<R>()
+        .await
+        .map_err(|_| Error::ConnectionError)?;
+
+    Ok(result)
+}
+
+async fn history(client: &Client, key: &str, market: &'static Market, interval: Interval, start: Timestamp, end: Timestamp) -> Result<Vec<Candlestick>, Error> {
+    let mut candlesticks = Vec::new();
+    let mut start = start;
+
+    while start <= end {
+        let result: model::Candlesticks = request(client, key, "klines", params!{
+            "symbol": market,
+            "interval": interval,
+            "startTime": start.millis(),
+            "endTime": end.millis(),
+            "limit": KLINES_LIMIT
+        }).await?;
+
+        let now = Timestamp::now();
+        let fetched = result.len();
+        if let Some(last) = result.last() {
+            start = Timestamp::from_millis(last.open_time) + interval.duration();
+        }
+        // Invalid candlesticks are skipped and reported as gaps by the subscription.
+        candlesticks.extend(
+            result
                 .into_iter()
-                .map(|tuple| {
-                    (tuple.0, format!("{}", tuple.1))
-                })
-                .collect::<Vec<(&str, String)>>()
-        ).unwrap();
-
-        /*
-        url.query_pairs_mut()
-            .append_pair("timestamp", &Utc::now().timestamp_millis().to_string());
-        url.query_pairs_mut()
-            .append_pair("recvWindow", &RECV_WINDOW.to_string());
-        */
-
-        let body = String::new();
-
-        /*
-        let mut mac = Hmac::<Sha256>::new_varkey(self.secret.as_bytes()).unwrap();
-        let message = format!("{}{}", url.query().unwrap_or(""), body);
-        mac.update(message.as_bytes());
-        let signature = hex::encode(mac.finalize().into_bytes());
-
-        url.query_pairs_mut().append_pair("signature", &signature);
-        */
-
-        let result = self
-            .client
-            .get(url)
-            .header("X-MBX-APIKEY", &self.key)
-            .body(body)
-            .send()
-            .await
-            .unwrap()
-            .json::<R>()
-            .await
-            .unwrap();
+                .filter_map(|candlestick| candlestick.candlestick(market, now).ok())
+        );
 
-        result
+        if fetched < KLINES_LIMIT {
+            break;
+        }
     }
+
+    Ok(candlesticks)
 }
 
 #[async_trait::async_trait]
 impl Api<SubscriptionStream> for Binance {
     async fn update(&mut self) -> Result<(), Error> {
-        let result: model::ExchangeInfo = self.request("exchangeInfo", params!{}).await;
-
-        for (base, quote) in result.symbols.into_iter().map(|symbol| {
-            (
-                Asset::from(symbol.base_asset),
-                Asset::from(symbol.quote_asset),
-            )
-        }) {
-            let base: &'static Asset = if let Some(base) = self.assets.get(&base) {
-                base
-            } else {
-                let base: &'static Asset = Box::leak(Box::new(base.clone()));
-                self.assets.insert(base);
-                base
-            };
-
-            let quote: &'static Asset = if let Some(quote) = self.assets.get(&quote) {
-                quote
-            } else {
-                let quote: &'static Asset = Box::leak(Box::new(quote.clone()));
-                self.assets.insert(quote);
-                quote
-            };
+        let result: model::ExchangeInfo = self.request("exchangeInfo", params!{}).await?;
 
-            let market = Market::from((base, quote));
-            if !self.markets.contains(&market) {
-                let market: &'static Market = Box::leak(Box::new(market));
-                self.markets.insert(market);
-            }
+        for symbol in result.symbols {
+            let base = Asset::intern(&symbol.base_asset);
+            let quote = Asset::intern(&symbol.quote_asset);
+            self.assets.insert(base);
+            self.assets.insert(quote);
+            let market = Market::intern(base, quote);
+            self.filters.set(market, symbol.filters.iter().filter_map(model::SymbolFilter::filter).collect());
+            self.markets.insert(market);
         }
 
         Ok(())
@@ -150,56 +181,151 @@ impl Api<SubscriptionStream> for Binance {
         &self.assets
     }
 
+    fn filters(&self) -> Filters {
+        self.filters.clone()
+    }
+
     async fn subscribe(
         &self,
         market: &'static Market,
         interval: Interval,
     ) -> Subscription<SubscriptionStream> {
-        let result: model::Candlesticks = self.request("klines", params!{
-            "symbol": market,
-            "interval": interval
-        }).await;
-
-        let candlestick = Candlestick {
-            market,
-            open_time: 0,
-            close_time: 0,
-            high: Price { price: 0.0, market },
-            low: Price { price: 0.0, market },
-            open: Price { price: 0.0, market },
-            close: Price { price: 0.0, market },
-            volume: Quantity {
-                quantity: 0.0,
-                asset: market.base,
-            },
-            trades: 0,
-        };
-
-        let buffer = vec![candlestick];
-        
-        let url = format!("{}{}@kline_{}", WS_ENDPOINT, market, interval);
-        let live_stream = tungstenite::connect_async(url)
-            .await
-            .unwrap()
-            .0
-            .filter_map(move |result| async move { Some(candlestick) });
-        let buffered_stream = stream::iter(buffer);
-        let stream = buffered_stream
-            .chain(live_stream)
-            .map(|candlestick| {
-                candlestick
-            })
-            .boxed();
+        let client = self.client.clone();
+        let key = self.key.clone();
+        // Open time of the latest closed candlestick, used to resume after a reconnect.
+        let latest = Arc::new(AtomicU64::new(0));
+        let resume = Arc::clone(&latest);
+
+        let stream = stream::unfold(0u32, move |reconnects| {
+            let client = client.clone();
+            let key = key.clone();
+            let start = resume.load(Ordering::SeqCst);
+
+            async move {
+                if reconnects > 0 {
+                    time::delay_for(RECONNECT_DELAY).await;
+                }
+
+                let mut params = params!{
+                    "symbol": market,
+                    "interval": interval
+                };
+                if start > 0 {
+                    params.push(("startTime", Box::new(start)));
+                }
+                let result: model::Candlesticks = request(&client, &key, "klines", params)
+                    .await
+                    .unwrap_or_default();
+                let now = Timestamp::now();
+                let buffered_stream = stream::iter(
+                    result
+                        .into_iter()
+                        .filter_map(move |candlestick| candlestick.candlestick(market, now).ok())
+                        .collect::<Vec<Candlestick>>()
+                );
+
+                let url = format!("{}{}@kline_{}", WS_ENDPOINT, format!("{}", market).to_lowercase(), interval);
+                let live_stream = match tungstenite::connect_async(url).await {
+                    Ok((socket, _)) => socket
+                        .take_while(|result| future::ready(result.is_ok()))
+                        .filter_map(move |result| async move {
+                            match result {
+                                Ok(Message::Text(text)) => serde_json::from_str::<model::KlineEvent>(&text)
+                                    .ok()
+                                    .and_then(|event| event.kline.candlestick(market).ok()),
+                                _ => None,
+                            }
+                        })
+                        .boxed(),
+                    Err(_) => stream::empty().boxed(),
+                };
+
+                Some((buffered_stream.chain(live_stream), reconnects + 1))
+            }
+        })
+        .flatten()
+        .inspect(move |candlestick| {
+            if candlestick.closed {
+                latest.fetch_max(candlestick.open_time.millis(), Ordering::SeqCst);
+            }
+        })
+        .boxed();
 
         Subscription::new(market, interval, stream)
     }
 
+    async fn history(
+        &self,
+        market: &'static Market,
+        interval: Interval,
+        start: Timestamp,
+        end: Timestamp,
+    ) -> Result<Vec<Candlestick>, Error> {
+        history(&self.client, &self.key, market, interval, start, end).await
+    }
+
+    async fn subscribe_trades(&self, market: &'static Market) -> Result<TradeStream, Error> {
+        let url = format!("{}{}@trade", WS_ENDPOINT, format!("{}", market).to_lowercase());
+
+        // Trades missed while reconnecting are skipped.
+        Ok(reconnecting(url, move |text| {
+            serde_json::from_str::<model::TradeEvent>(text)
+                .ok()
+                .map(|event| event.trade(market))
+        }))
+    }
+
+    async fn subscribe_depth(&self, market: &'static Market) -> Result<DepthStream, Error> {
+        let url = format!("{}{}@depth20@100ms", WS_ENDPOINT, format!("{}", market).to_lowercase());
+
+        // The partial depth stream has no event time, so snapshots are stamped on arrival.
+        Ok(reconnecting(url, move |text| {
+            serde_json::from_str::<model::PartialDepth>(text)
+                .ok()
+                .and_then(|event| event.depth(market, Timestamp::now()))
+        }))
+    }
+
     async fn order(&mut self, order: Order) -> Result<OrderResponse, OrderError> {
         //let result = self.request(path, params)
         Err(OrderError::Other(Error::ConnectionError))
     }
 }
 
+/// Streams the parsed text messages of a websocket and reconnects whenever it closes.
+fn reconnecting<T, F>(url: String, parse: F) -> BoxStream<'static, T>
+where
+    T: Send + 'static,
+    F: Fn(&str) -> Option<T> + Copy + Send + Sync + 'static,
+{
+    stream::unfold(0u32, move |reconnects| {
+        let url = url.clone();
+
+        async move {
+            if reconnects > 0 {
+                time::delay_for(RECONNECT_DELAY).await;
+            }
+
+            let live_stream = match tungstenite::connect_async(url).await {
+                Ok((socket, _)) => socket
+                    .take_while(|result| future::ready(result.is_ok()))
+                    .filter_map(move |result| async move {
+                        match result {
+                            Ok(Message::Text(text)) => parse(&text),
+                            _ => None,
+                        }
+                    })
+                    .boxed(),
+                Err(_) => stream::empty().boxed(),
+            };
+
+            Some((live_stream, reconnects + 1))
+        }
+    })
+    .flatten()
+    .boxed()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
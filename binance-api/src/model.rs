@@ -1,3 +1,6 @@
+use api::{Asset, Balance, CandlestickError, Depth, Filter, Level, LotSize, Market, MinNotional, Price, PriceFilter, Quantity, Timestamp, Wallet};
+use std::collections::HashSet;
+use std::sync::Arc;
 use serde::{Deserialize, Serialize};
 
 pub type Result<T> = std::result::Result<T, Error>;
@@ -33,6 +36,46 @@ pub struct Symbol {
     pub filters: Vec<SymbolFilter>,
 }
 
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct AccountInformation {
+    pub balances: Vec<AccountBalance>,
+}
+
+impl AccountInformation {
+    /// Converts the balances of all known assets into a wallet.
+    pub fn wallet(&self, assets: &HashSet<&'static Asset>) -> Wallet {
+        let mut wallet = Wallet::new();
+        for balance in &self.balances {
+            if let Some(asset) = assets.get(&Asset::from(balance.asset.clone())) {
+                wallet
+                    .set(Balance {
+                        free: Quantity {
+                            quantity: balance.free,
+                            asset,
+                        },
+                        locked: Quantity {
+                            quantity: balance.locked,
+                            asset,
+                        },
+                    })
+                    .ok();
+            }
+        }
+
+        wallet
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct AccountBalance {
+    pub asset: String,
+    #[serde(with = "string_or_float")]
+    pub free: f64,
+    #[serde(with = "string_or_float")]
+    pub locked: f64,
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 #[serde(tag = "filterType", rename_all = "SCREAMING_SNAKE_CASE")]
 pub enum SymbolFilter {
@@ -74,6 +117,35 @@ pub enum SymbolFilter {
     IcebergParts { limit: u64 },
 }
 
+impl SymbolFilter {
+    /// Returns the filter that applies the rule to orders,
+    /// or `None` if orders are not checked against the rule or its values cannot be parsed.
+    pub fn filter(&self) -> Option<Arc<dyn Filter>> {
+        let parse = |value: &str| value.parse::<f64>().ok();
+
+        match self {
+            Self::LotSize { min_qty, max_qty, step_size } => Some(Arc::new(LotSize {
+                min: parse(min_qty)?,
+                max: parse(max_qty)?,
+                step: parse(step_size)?,
+            })),
+            Self::PriceFilter { min_price, max_price, tick_size } => {
+                // A maximum of zero disables the limit.
+                let max = parse(max_price)?;
+                Some(Arc::new(PriceFilter {
+                    min: parse(min_price)?,
+                    max: if max > 0.0 { max } else { f64::INFINITY },
+                    tick: parse(tick_size)?,
+                }))
+            }
+            Self::MinNotional { min_notional } => Some(Arc::new(MinNotional {
+                min: parse(min_notional)?,
+            })),
+            _ => None,
+        }
+    }
+}
+
 #[derive(Deserialize)]
 #[serde(untagged)]
 pub enum Value {
@@ -92,11 +164,7 @@ impl Value {
 
     pub fn as_f64(&self) -> Option<f64> {
         if let Self::Float(value) = self {
-            if let Ok(value) = value.parse::<f64>() {
-                Some(value)
-            } else {
-                None
-            }
+            value.parse::<f64>().ok()
         } else {
             None
         }
@@ -139,8 +207,170 @@ impl From<IntoCandlestick> for Candlestick {
     }
 }
 
+impl Candlestick {
+    /// Converts the candlestick, which is closed if its close time has passed.
+    pub fn candlestick(
+        &self,
+        market: &'static Market,
+        now: Timestamp,
+    ) -> std::result::Result<api::Candlestick, CandlestickError> {
+        let price = |price| Price { price, market };
+        let quantity = |quantity, asset| Quantity { quantity, asset };
+
+        api::Candlestick::new(
+            market,
+            Timestamp::from_millis(self.open_time),
+            Timestamp::from_millis(self.close_time),
+            price(self.open),
+            price(self.high),
+            price(self.low),
+            price(self.close),
+            quantity(self.volume, market.base),
+            quantity(self.quote_asset_volume, market.quote),
+            quantity(self.taker_buy_base_asset_volume, market.base),
+            quantity(self.taker_buy_quote_asset_volume, market.quote),
+            self.number_of_trades,
+            Timestamp::from_millis(self.close_time) < now,
+        )
+    }
+}
+
 pub type Candlesticks = Vec<Candlestick>;
 
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct KlineEvent {
+    #[serde(rename = "E")]
+    pub event_time: u64,
+    #[serde(rename = "s")]
+    pub symbol: String,
+    #[serde(rename = "k")]
+    pub kline: Kline,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Kline {
+    #[serde(rename = "t")]
+    pub open_time: u64,
+    #[serde(rename = "T")]
+    pub close_time: u64,
+    #[serde(rename = "o", with = "string_or_float")]
+    pub open: f64,
+    #[serde(rename = "h", with = "string_or_float")]
+    pub high: f64,
+    #[serde(rename = "l", with = "string_or_float")]
+    pub low: f64,
+    #[serde(rename = "c", with = "string_or_float")]
+    pub close: f64,
+    #[serde(rename = "v", with = "string_or_float")]
+    pub volume: f64,
+    #[serde(rename = "n")]
+    pub number_of_trades: u64,
+    #[serde(rename = "x")]
+    pub is_closed: bool,
+    #[serde(rename = "q", with = "string_or_float")]
+    pub quote_asset_volume: f64,
+    #[serde(rename = "V", with = "string_or_float")]
+    pub taker_buy_base_asset_volume: f64,
+    #[serde(rename = "Q", with = "string_or_float")]
+    pub taker_buy_quote_asset_volume: f64,
+}
+
+impl Kline {
+    pub fn candlestick(
+        &self,
+        market: &'static Market,
+    ) -> std::result::Result<api::Candlestick, CandlestickError> {
+        let price = |price| Price { price, market };
+        let quantity = |quantity, asset| Quantity { quantity, asset };
+
+        api::Candlestick::new(
+            market,
+            Timestamp::from_millis(self.open_time),
+            Timestamp::from_millis(self.close_time),
+            price(self.open),
+            price(self.high),
+            price(self.low),
+            price(self.close),
+            quantity(self.volume, market.base),
+            quantity(self.quote_asset_volume, market.quote),
+            quantity(self.taker_buy_base_asset_volume, market.base),
+            quantity(self.taker_buy_quote_asset_volume, market.quote),
+            self.number_of_trades,
+            self.is_closed,
+        )
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TradeEvent {
+    #[serde(rename = "E")]
+    pub event_time: u64,
+    #[serde(rename = "s")]
+    pub symbol: String,
+    #[serde(rename = "t")]
+    pub trade_id: u64,
+    #[serde(rename = "p", with = "string_or_float")]
+    pub price: f64,
+    #[serde(rename = "q", with = "string_or_float")]
+    pub quantity: f64,
+    #[serde(rename = "T")]
+    pub trade_time: u64,
+    #[serde(rename = "m")]
+    pub is_buyer_maker: bool,
+}
+
+impl TradeEvent {
+    pub fn trade(&self, market: &'static Market) -> api::Trade {
+        api::Trade {
+            market,
+            id: self.trade_id,
+            time: Timestamp::from_millis(self.trade_time),
+            price: Price {
+                price: self.price,
+                market,
+            },
+            quantity: Quantity {
+                quantity: self.quantity,
+                asset: market.base,
+            },
+            buyer_maker: self.is_buyer_maker,
+        }
+    }
+}
+
+/// Snapshot of the best levels of the order book, as sent by the partial depth stream.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct PartialDepth {
+    pub last_update_id: u64,
+    /// Pairs of price and quantity.
+    pub bids: Vec<[String; 2]>,
+    pub asks: Vec<[String; 2]>,
+}
+
+impl PartialDepth {
+    pub fn depth(&self, market: &'static Market, time: Timestamp) -> Option<Depth> {
+        let levels = |levels: &[[String; 2]]| -> Option<Vec<Level>> {
+            levels.iter().map(|[price, quantity]| Some(Level {
+                price: Price {
+                    price: price.parse().ok()?,
+                    market,
+                },
+                quantity: Quantity {
+                    quantity: quantity.parse().ok()?,
+                    asset: market.base,
+                },
+            })).collect()
+        };
+
+        Some(Depth {
+            market,
+            time,
+            bids: levels(&self.bids)?,
+            asks: levels(&self.asks)?,
+        })
+    }
+}
 
 mod string_or_float {
     use std::fmt;
@@ -171,4 +401,30 @@ mod string_or_float {
             StringOrFloat::Float(i) => Ok(i),
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_account_wallet() {
+        let account: AccountInformation = serde_json::from_str(r#"{
+            "makerCommission": 15,
+            "balances": [
+                { "asset": "BTC", "free": "0.50000000", "locked": "0.25000000" },
+                { "asset": "ETH", "free": "2.00000000", "locked": "0.00000000" },
+                { "asset": "UNLISTED", "free": "1.00000000", "locked": "0.00000000" }
+            ]
+        }"#).unwrap();
+        let (btc, eth) = (Asset::intern("BTC"), Asset::intern("ETH"));
+        let assets = [btc, eth].iter().copied().collect();
+
+        let wallet = account.wallet(&assets);
+        assert_eq!(wallet.free(btc).quantity, 0.5);
+        assert_eq!(wallet.locked(btc).quantity, 0.25);
+        assert_eq!(wallet.total(eth).quantity, 2.0);
+        // Assets that are not traded are skipped.
+        assert_eq!(wallet.balances().count(), 2);
+    }
+}
@@ -0,0 +1,398 @@
+use api::{Asset, Candlestick, Filters, Market, Monetary, Order, Price, Quantity, Side, Timestamp};
+use futures_core::{
+    stream::Stream,
+    task::{Context, Poll},
+};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::pin::Pin;
+
+/// Single trade of an arbitrage cycle.
+#[derive(Debug, Copy, Clone)]
+pub struct Leg {
+    pub market: &'static Market,
+    /// Buying spends the quote asset, selling spends the base asset of the market.
+    pub side: Side,
+}
+
+impl Leg {
+    fn new(market: &'static Market, from: &'static Asset) -> Self {
+        Self {
+            market,
+            side: if market.base == from {
+                Side::Sell
+            } else {
+                Side::Buy
+            },
+        }
+    }
+
+    /// Returns the asset that is spent.
+    pub fn from(&self) -> &'static Asset {
+        match self.side {
+            Side::Buy => self.market.quote,
+            Side::Sell => self.market.base,
+        }
+    }
+
+    /// Returns the asset that is received.
+    pub fn to(&self) -> &'static Asset {
+        match self.side {
+            Side::Buy => self.market.base,
+            Side::Sell => self.market.quote,
+        }
+    }
+}
+
+/// Profitable cycle of three trades that starts and ends in the same asset.
+#[derive(Debug, Copy, Clone)]
+pub struct Opportunity {
+    pub legs: [Leg; 3],
+    /// Largest quantity that can be put into the cycle.
+    pub start: Quantity,
+    /// Expected quantity at the end of the cycle, after fees and filters.
+    pub end: Quantity,
+    /// Expected return as a fraction of the start quantity.
+    pub expected_return: Monetary,
+    /// Close time of the candlestick that revealed the opportunity.
+    pub time: Timestamp,
+}
+
+/// Finds triangular arbitrage opportunities, like USDT to BTC to ETH and back to USDT,
+/// from the latest candlesticks of all involved markets.
+///
+/// The executable size of a cycle is limited by the configured maximum quantity
+/// and by the share of the latest volume of each market that may be traded.
+/// Each trade is adjusted by the trading rules of its market, for example to the tick size.
+#[derive(Debug)]
+pub struct ArbitrageScanner {
+    start: Quantity,
+    /// Fee of a single trade as a fraction of the received quantity.
+    fee: Monetary,
+    /// Share of the latest volume of a market that a single trade may take.
+    participation: Monetary,
+    /// Smallest expected return of an opportunity.
+    min_return: Monetary,
+    filters: Filters,
+    cycles: Vec<[Leg; 3]>,
+    /// Indices of the cycles that trade on each market.
+    involved: HashMap<&'static Market, Vec<usize>>,
+    latest: HashMap<&'static Market, Candlestick>,
+}
+
+impl ArbitrageScanner {
+    /// Creates a scanner for all cycles through the given markets that start with the asset
+    /// of the given quantity, which is the largest quantity that is put into a cycle.
+    pub fn new(
+        markets: &HashSet<&'static Market>,
+        start: Quantity,
+        fee: Monetary,
+        participation: Monetary,
+        min_return: Monetary,
+    ) -> Self {
+        let mut neighbours: HashMap<&'static Asset, Vec<&'static Market>> = HashMap::new();
+        for market in markets {
+            neighbours.entry(market.base).or_default().push(market);
+            neighbours.entry(market.quote).or_default().push(market);
+        }
+        let legs = |from: &'static Asset| {
+            neighbours
+                .get(from)
+                .into_iter()
+                .flatten()
+                .map(move |market| Leg::new(market, from))
+        };
+
+        let mut cycles = Vec::new();
+        for first in legs(start.asset) {
+            for second in legs(first.to()).filter(|leg| leg.to() != start.asset) {
+                for third in legs(second.to()).filter(|leg| leg.to() == start.asset) {
+                    cycles.push([first, second, third]);
+                }
+            }
+        }
+
+        let mut involved: HashMap<&'static Market, Vec<usize>> = HashMap::new();
+        for (index, cycle) in cycles.iter().enumerate() {
+            for leg in cycle {
+                involved.entry(leg.market).or_default().push(index);
+            }
+        }
+
+        Self {
+            start,
+            fee,
+            participation,
+            min_return,
+            filters: Filters::new(),
+            cycles,
+            involved,
+            latest: HashMap::new(),
+        }
+    }
+
+    /// Applies the trading rules of the markets, usually those of `Api::filters`.
+    pub fn filters(mut self, filters: Filters) -> Self {
+        self.filters = filters;
+        self
+    }
+
+    pub fn cycles(&self) -> &[[Leg; 3]] {
+        &self.cycles
+    }
+
+    /// Updates the latest candlestick of its market and returns the opportunities
+    /// of all cycles through that market, best first.
+    pub fn push(&mut self, candlestick: Candlestick) -> Vec<Opportunity> {
+        self.latest.insert(candlestick.market, candlestick);
+
+        let mut opportunities: Vec<Opportunity> = self
+            .involved
+            .get(candlestick.market)
+            .into_iter()
+            .flatten()
+            .filter_map(|index| self.evaluate(self.cycles[*index], candlestick.close_time))
+            .collect();
+        opportunities.sort_by(|a, b| {
+            b.expected_return
+                .partial_cmp(&a.expected_return)
+                .unwrap_or(std::cmp::Ordering::Equal)
+        });
+
+        opportunities
+    }
+
+    fn evaluate(&self, legs: [Leg; 3], time: Timestamp) -> Option<Opportunity> {
+        let mut candlesticks = Vec::with_capacity(legs.len());
+        for leg in &legs {
+            candlesticks.push(self.latest.get(leg.market)?);
+        }
+
+        // Quantity of each spent asset per unit of the start asset, without fees,
+        // used to express the liquidity of each market in the start asset.
+        let mut rate = 1.0;
+        let mut size = self.start.quantity;
+        for (leg, candlestick) in legs.iter().zip(&candlesticks) {
+            let (liquidity, price) = match leg.side {
+                Side::Buy => (candlestick.quote_volume, 1.0 / candlestick.close.price),
+                Side::Sell => (candlestick.volume, candlestick.close.price),
+            };
+            size = size.min(liquidity.quantity * self.participation / rate);
+            rate *= price;
+        }
+
+        let start = Quantity {
+            quantity: size,
+            asset: self.start.asset,
+        };
+        let mut quantity = start;
+        for (leg, candlestick) in legs.iter().zip(&candlesticks) {
+            quantity = self.trade(*leg, quantity, candlestick.close)?;
+        }
+
+        let expected_return = quantity.quantity / start.quantity - 1.0;
+        if !(start.quantity > 0.0 && expected_return > self.min_return) {
+            return None;
+        }
+
+        Some(Opportunity {
+            legs,
+            start,
+            end: quantity,
+            expected_return,
+            time,
+        })
+    }
+
+    /// Returns the quantity that is received by spending the given quantity in a leg,
+    /// or `None` if the order is rejected by the filters of the market.
+    fn trade(&self, leg: Leg, quantity: Quantity, price: Price) -> Option<Quantity> {
+        let base = match leg.side {
+            Side::Buy => quantity.checked_div(price).ok()?,
+            Side::Sell => quantity,
+        };
+        let order = Order::Limit(leg.side, base, price);
+        let (base, price) = match self.filters.apply(leg.market, order) {
+            Ok(Order::Limit(_, base, price)) => (base, price),
+            _ => return None,
+        };
+
+        let received = match leg.side {
+            Side::Buy => base,
+            Side::Sell => base.checked_mul(price).ok()?,
+        };
+        Some(received * (1.0 - self.fee))
+    }
+}
+
+/// Stream adapter that scans candlesticks, for example merged subscriptions of
+/// all involved markets or historical data, for arbitrage opportunities.
+pub struct Opportunities<S>
+where
+    S: Stream<Item = Candlestick> + Unpin,
+{
+    stream: S,
+    scanner: ArbitrageScanner,
+    buffer: VecDeque<Opportunity>,
+}
+
+impl<S> Opportunities<S>
+where
+    S: Stream<Item = Candlestick> + Unpin,
+{
+    pub fn new(stream: S, scanner: ArbitrageScanner) -> Self {
+        Self {
+            stream,
+            scanner,
+            buffer: VecDeque::new(),
+        }
+    }
+
+    pub fn get_ref(&self) -> &ArbitrageScanner {
+        &self.scanner
+    }
+}
+
+impl<S> Stream for Opportunities<S>
+where
+    S: Stream<Item = Candlestick> + Unpin,
+{
+    type Item = Opportunity;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.as_mut().get_mut();
+
+        loop {
+            if let Some(opportunity) = this.buffer.pop_front() {
+                return Poll::Ready(Some(opportunity));
+            }
+
+            match Pin::new(&mut this.stream).poll_next(cx) {
+                Poll::Ready(Some(candlestick)) => {
+                    this.buffer.extend(this.scanner.push(candlestick))
+                }
+                Poll::Ready(None) => return Poll::Ready(None),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use api::{LotSize, PriceFilter};
+    use std::sync::Arc;
+
+    fn market(base: &str, quote: &str) -> &'static Market {
+        Market::intern(Asset::intern(base), Asset::intern(quote))
+    }
+
+    fn latest(market: &'static Market, price: Monetary, volume: Monetary) -> Candlestick {
+        let price = Price { price, market };
+        let quantity = |quantity, asset| Quantity { quantity, asset };
+        Candlestick {
+            market,
+            open_time: Timestamp::from_millis(0),
+            close_time: Timestamp::from_millis(59_999),
+            high: price,
+            low: price,
+            open: price,
+            close: price,
+            volume: quantity(volume, market.base),
+            quote_volume: quantity(volume * price.price, market.quote),
+            taker_buy_base_volume: quantity(0.0, market.base),
+            taker_buy_quote_volume: quantity(0.0, market.quote),
+            trades: 1,
+            closed: true,
+        }
+    }
+
+    /// Returns a scanner of the cycles through the three markets of the assets,
+    /// which starts with at most 10000 of the quote asset.
+    fn scanner(base: &str, middle: &str, quote: &str) -> ArbitrageScanner {
+        let markets = vec![
+            market(middle, quote),
+            market(base, middle),
+            market(base, quote),
+        ]
+        .into_iter()
+        .collect();
+        let start = Quantity {
+            quantity: 10_000.0,
+            asset: Asset::intern(quote),
+        };
+
+        ArbitrageScanner::new(&markets, start, 0.001, 0.1, 0.0)
+    }
+
+    /// Pushes the latest candlesticks of the cycle through DOT, EUR and USDC
+    /// and returns the expected return of its opportunity, if any.
+    fn dot_cycle(scanner: &mut ArbitrageScanner) -> Option<Monetary> {
+        scanner.push(latest(market("EUR", "USDC"), 1.2, 100_000.0));
+        scanner.push(latest(market("DOT", "EUR"), 5.0, 10_000.0));
+        scanner
+            .push(latest(market("DOT", "USDC"), 5.5, 10_000.0))
+            .first()
+            .map(|opportunity| opportunity.expected_return)
+    }
+
+    #[test]
+    fn test_arbitrage_scanner() {
+        let (btcusdt, ethbtc, ethusdt) = (
+            market("BTC", "USDT"),
+            market("ETH", "BTC"),
+            market("ETH", "USDT"),
+        );
+        let mut scanner = scanner("ETH", "BTC", "USDT");
+        assert_eq!(scanner.cycles().len(), 2);
+
+        assert!(scanner.push(latest(btcusdt, 50_000.0, 100.0)).is_empty());
+        assert!(scanner.push(latest(ethbtc, 0.05, 10.0)).is_empty());
+
+        // ETH is cheaper in USDT than through BTC, so buying ETH with USDT,
+        // selling it for BTC and selling the BTC for USDT returns about 4.7 percent.
+        let opportunities = scanner.push(latest(ethusdt, 2_380.0, 1_000.0));
+        assert_eq!(opportunities.len(), 1);
+        let opportunity = opportunities[0];
+        assert_eq!(opportunity.legs[0].market, ethusdt);
+        assert_eq!(opportunity.legs[2].market, btcusdt);
+        // Only 1 ETH can be sold for BTC, which costs 2380 USDT.
+        assert!((opportunity.start.quantity - 2_380.0).abs() < 1e-6);
+        assert!((opportunity.expected_return - 0.047_2).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_arbitrage_scanner_lot_size() {
+        assert!(dot_cycle(&mut scanner("DOT", "EUR", "USDC")).is_some());
+
+        // The cycle cannot be executed if the first leg exceeds the lot size.
+        let mut filters = Filters::new();
+        filters.set(
+            market("DOT", "USDC"),
+            vec![Arc::new(LotSize {
+                min: 0.0,
+                max: 1.0,
+                step: 0.1,
+            })],
+        );
+        assert!(dot_cycle(&mut scanner("DOT", "EUR", "USDC").filters(filters)).is_none());
+    }
+
+    #[test]
+    fn test_arbitrage_scanner_price_filter() {
+        let unfiltered = dot_cycle(&mut scanner("DOT", "EUR", "USDC")).unwrap();
+
+        // Selling the EUR at a price rounded up to the tick size receives more USDC.
+        let mut filters = Filters::new();
+        filters.set(
+            market("EUR", "USDC"),
+            vec![Arc::new(PriceFilter {
+                min: 0.0,
+                max: f64::INFINITY,
+                tick: 0.25,
+            })],
+        );
+        let filtered = dot_cycle(&mut scanner("DOT", "EUR", "USDC").filters(filters)).unwrap();
+        assert!(((1.0 + filtered) / (1.0 + unfiltered) - 1.25 / 1.2).abs() < 1e-9);
+    }
+}
@@ -1,21 +1,63 @@
-use crate::{Indicator, Trader};
-use api::{Api, Market};
+use crate::Trader;
+use api::{Api, Candlestick, Interval, Market, Merge, MergeOrder, OrderError};
+use futures_core::stream::Stream;
+use std::collections::HashSet;
+use tokio::stream::StreamExt;
 
-struct Investor<API>
+pub struct Investor<API, S>
 where
-    API: Api,
+    API: Api<S>,
+    S: Stream<Item = Candlestick> + Unpin + Send + 'static,
 {
-    markets: HashMap<MarketKey>,
-    indicators: Vec<Box<dyn Indicator>>,
+    api: API,
+    markets: HashSet<&'static Market>,
     traders: Vec<Box<dyn Trader>>,
+    _phantom: std::marker::PhantomData<fn() -> S>,
 }
 
-impl<API> Investor<API>
+impl<API, S> Investor<API, S>
 where
-    API: Api,
+    API: Api<S>,
+    S: Stream<Item = Candlestick> + Unpin + Send + 'static,
 {
-    pub async fn run(&mut self) {
-        loop {
+    pub fn new(api: API) -> Self {
+        Self {
+            api,
+            markets: HashSet::new(),
+            traders: Vec::new(),
+            _phantom: std::marker::PhantomData,
         }
     }
+
+    pub fn market(mut self, market: &'static Market) -> Self {
+        self.markets.insert(market);
+        self
+    }
+
+    pub fn trader<T: Trader + 'static>(mut self, trader: T) -> Self {
+        self.traders.push(Box::new(trader));
+        self
+    }
+
+    /// Lets all traders evaluate the candlesticks of all markets in the interval
+    /// and places the orders they decide on, until the subscriptions end or an order fails.
+    pub async fn run(&mut self, interval: Interval) -> Result<(), OrderError> {
+        let mut subscriptions = Vec::with_capacity(self.markets.len());
+        for &market in &self.markets {
+            subscriptions.push(self.api.subscribe(market, interval).await);
+        }
+
+        // Candlesticks of markets that fall silent are not waited for longer than an interval.
+        let window = MergeOrder::Window(interval.duration());
+        let mut candlesticks = Merge::new(subscriptions, window);
+        while let Some(candlestick) = candlesticks.next().await {
+            for trader in &mut self.traders {
+                if let Some(order) = trader.evaluate(candlestick).await {
+                    self.api.order(order).await?;
+                }
+            }
+        }
+
+        Ok(())
+    }
 }
@@ -1,7 +1,9 @@
+mod arbitrage;
 mod indicator;
 mod investor;
 mod trader;
 
+pub use arbitrage::*;
 pub use indicator::*;
 pub use investor::*;
 pub use trader::*;
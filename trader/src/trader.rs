@@ -1,24 +1,34 @@
 use api::{Candlestick, Order};
+use std::time::Duration;
+use tokio::time;
 
 #[async_trait::async_trait]
-pub trait Trader {
-    async fn evaluate<'a>(&mut self, candlestick: Candlestick<'a>) -> Option<Order>;
+pub trait Trader: Send {
+    async fn evaluate(&mut self, candlestick: Candlestick) -> Option<Order>;
 }
 
-struct LuaTrader {}
+#[derive(Default)]
+pub struct LuaTrader {}
 
-impl LuaTrader {}
+impl LuaTrader {
+    /// Scripts cannot be run yet, so no order is placed.
+    async fn evaluate_lua(&mut self, _candlestick: Candlestick) -> Option<Order> {
+        None
+    }
+}
 
 #[async_trait::async_trait]
 impl Trader for LuaTrader {
-    async fn evaluate<'a>(&mut self, candlestick: Candlestick<'a>) -> Option<Order> {
-        let mut delay = time::delay_for(Duration::from_millis(50));
+    async fn evaluate(&mut self, candlestick: Candlestick) -> Option<Order> {
+        let delay = time::delay_for(Duration::from_millis(50));
         tokio::select! {
             _ = delay => {
                 println!("operation timed out");
+                None
             }
-            _ = self.evaluate_lua() => {
+            order = self.evaluate_lua(candlestick) => {
                 println!("operation completed");
+                order
             }
         }
     }
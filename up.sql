@@ -1,3 +0,0 @@
-CREATE TABLE candlesticks {
-    
-}
\ No newline at end of file